@@ -0,0 +1,268 @@
+//! Android Open Accessory (AOA) host side: switches a connected Android device into the
+//! accessory mode and exchanges data with the app which opens the accessory on that device
+//! (through `UsbManager.openAccessory()`). It makes phone-to-phone or phone-to-head-unit
+//! serial links possible without any serial adapter.
+//!
+//! The device disconnects and connects again with Google's vendor ID after `start()`,
+//! so it should be found again (possibly with `usb::watch_devices()`), and the permission
+//! has to be requested again for the new device before `AccessorySerial::build()`.
+//!
+//! Reference: <https://source.android.com/docs/core/interaction/accessories/aoa>.
+
+use std::{
+    io::{self, Error, ErrorKind},
+    time::Duration,
+};
+
+use crate::usb::{self, BulkReader, BulkWriter, DeviceInfo, PortLock};
+use nusb::transfer::{
+    Control, ControlType, Direction, EndpointType, Queue, Recipient, RequestBuffer, TransferError,
+};
+
+#[cfg(feature = "blocking")]
+use std::io::{Read, Write};
+
+/// Vendor ID of devices in the accessory mode.
+pub const AOA_VENDOR_ID: u16 = 0x18d1;
+/// Product IDs of devices in the accessory mode: accessory, accessory + ADB,
+/// audio + accessory, audio + accessory + ADB.
+pub const AOA_PRODUCT_IDS: &[u16] = &[0x2d00, 0x2d01, 0x2d04, 0x2d05];
+
+const ACCESSORY_GET_PROTOCOL: u8 = 51;
+const ACCESSORY_SEND_STRING: u8 = 52;
+const ACCESSORY_START: u8 = 53;
+
+/// Identifying strings sent to the Android device before starting the accessory mode.
+/// The device selects the app to handle the accessory by `manufacturer`, `model` and
+/// `version` (check `<usb-accessory>` entries in Android's `accessory_filter.xml`);
+/// `description`, `uri` and `serial` are shown to the user or passed to the app.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AccessoryIdentity {
+    pub manufacturer: String,
+    pub model: String,
+    pub description: String,
+    pub version: String,
+    /// URI of a web page shown if no app is installed for the accessory.
+    pub uri: String,
+    pub serial: String,
+}
+
+impl AccessoryIdentity {
+    /// Strings in the order of the string indexes defined by the AOA protocol.
+    fn strings(&self) -> [&str; 6] {
+        [
+            &self.manufacturer,
+            &self.model,
+            &self.description,
+            &self.version,
+            &self.uri,
+            &self.serial,
+        ]
+    }
+}
+
+/// Returns true if the device is already in the accessory mode.
+pub fn is_accessory_mode(dev_info: &DeviceInfo) -> bool {
+    dev_info.vendor_id() == AOA_VENDOR_ID && AOA_PRODUCT_IDS.contains(&dev_info.product_id())
+}
+
+/// Probes for devices in the accessory mode. Returns an empty vector if no device is found.
+pub fn probe() -> io::Result<Vec<DeviceInfo>> {
+    Ok(usb::list_devices_cached()?
+        .into_iter()
+        .filter(is_accessory_mode)
+        .collect())
+}
+
+/// Reads the AOA protocol version supported by the device; returns `Ok(0)` if the
+/// device doesn't support AOA. Please get permission for the device before calling it.
+pub fn protocol_version(dev_info: &DeviceInfo, timeout: Duration) -> io::Result<u16> {
+    let device = dev_info.open_device()?;
+    get_protocol(&device, timeout)
+}
+
+/// Sends the identifying strings and switches the device into the accessory mode;
+/// returns the AOA protocol version. Returns `Unsupported` error if the device doesn't
+/// support AOA. Please get permission for the device before calling this function.
+///
+/// Note: It does nothing and returns `Ok(0)` if the device is already in the accessory
+/// mode, in which case it can be opened by `AccessorySerial::build()` directly.
+pub fn start(
+    dev_info: &DeviceInfo,
+    identity: &AccessoryIdentity,
+    timeout: Duration,
+) -> io::Result<u16> {
+    if is_accessory_mode(dev_info) {
+        return Ok(0);
+    }
+    let device = dev_info.open_device()?;
+    let version = get_protocol(&device, timeout)?;
+    if version == 0 {
+        return Err(Error::new(
+            ErrorKind::Unsupported,
+            "The device doesn't support Android Open Accessory",
+        ));
+    }
+    for (index, string) in identity.strings().into_iter().enumerate() {
+        // null-terminated UTF-8 strings
+        let mut data = Vec::with_capacity(string.len() + 1);
+        data.extend_from_slice(string.as_bytes());
+        data.push(0);
+        control_out(&device, ACCESSORY_SEND_STRING, index as u16, &data, timeout)
+            .map_err(usb::map_transfer_error)?;
+    }
+    match control_out(&device, ACCESSORY_START, 0, &[], timeout) {
+        // the device may disconnect before responding
+        Ok(_) | Err(TransferError::Disconnected) => Ok(version),
+        Err(e) => Err(usb::map_transfer_error(e)),
+    }
+}
+
+fn get_protocol(device: &nusb::Device, timeout: Duration) -> io::Result<u16> {
+    let mut buf = [0u8; 2];
+    let control = Control {
+        control_type: ControlType::Vendor,
+        recipient: Recipient::Device,
+        request: ACCESSORY_GET_PROTOCOL,
+        value: 0,
+        index: 0,
+    };
+    match device.control_in_blocking(control, &mut buf, timeout) {
+        Ok(2) => Ok(u16::from_le_bytes(buf)),
+        Ok(_) | Err(TransferError::Stall) => Ok(0),
+        Err(e) => Err(usb::map_transfer_error(e)),
+    }
+}
+
+fn control_out(
+    device: &nusb::Device,
+    request: u8,
+    index: u16,
+    data: &[u8],
+    timeout: Duration,
+) -> Result<usize, TransferError> {
+    let control = Control {
+        control_type: ControlType::Vendor,
+        recipient: Recipient::Device,
+        request,
+        value: 0,
+        index,
+    };
+    device.control_out_blocking(control, data, timeout)
+}
+
+/// Serial stream of a device in the accessory mode, i.e. the bulk endpoints of the
+/// accessory interface. The app on the Android device reads and writes the other end
+/// through the `ParcelFileDescriptor` returned by `UsbManager.openAccessory()`.
+pub struct AccessorySerial {
+    usb_path_name: String, // the name from `android.hardware.usb.UsbDevice`
+    reader: BulkReader,    // for the bulk IN endpoint
+    writer: BulkWriter,    // for the bulk OUT endpoint
+    timeout: Duration,     // standard `Read` and `Write` timeout
+    _lock: PortLock,       // released on drop or `into_queues()`
+}
+
+impl AccessorySerial {
+    /// Connects to the device in the accessory mode, returns the `AccessorySerial` handler.
+    /// Please get permission for the device before calling this function.
+    /// Returns `AlreadyExists` error if it is opened by another handler in this process.
+    /// - `timeout`: Set for standard `Read` and `Write` traits.
+    pub fn build(dev_info: &DeviceInfo, timeout: Duration) -> io::Result<Self> {
+        if !is_accessory_mode(dev_info) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "The device is not in the accessory mode",
+            ));
+        }
+        // the accessory interface is always the first one
+        let intr_num = dev_info
+            .try_interfaces()?
+            .next()
+            .map(|intr| intr.interface_number())
+            .ok_or(Error::new(ErrorKind::NotFound, "No interface found"))?;
+        let lock = PortLock::acquire_as(dev_info, intr_num, "AccessorySerial")?;
+
+        let device = dev_info.open_device()?;
+        let intr = device.detach_and_claim_interface(intr_num)?;
+        let (mut addr_r, mut addr_w) = (None, None);
+        for alt in intr
+            .descriptors()
+            .filter(|alt| alt.alternate_setting() == 0)
+        {
+            for endp in alt.endpoints() {
+                match (endp.transfer_type(), endp.direction()) {
+                    (EndpointType::Bulk, Direction::In) => addr_r = addr_r.or(Some(endp.address())),
+                    (EndpointType::Bulk, Direction::Out) => {
+                        addr_w = addr_w.or(Some(endp.address()))
+                    }
+                    _ => (),
+                }
+            }
+        }
+        let (Some(addr_r), Some(addr_w)) = (addr_r, addr_w) else {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                "Bulk endpoints of the accessory interface not found",
+            ));
+        };
+
+        Ok(Self {
+            usb_path_name: dev_info.path_name().clone(),
+            reader: intr.bulk_in_queue(addr_r).into(),
+            writer: intr.bulk_out_queue(addr_w).into(),
+            timeout,
+            _lock: lock,
+        })
+    }
+
+    /// Returns the path of the device in the usbfs file system.
+    pub fn name(&self) -> &str {
+        &self.usb_path_name
+    }
+
+    /// Returns the timeout for standard `Read` and `Write` implementations.
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Sets timeout for standard `Read` and `Write` implementations to do USB bulk transfers.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Takes `nusb` transfer queues of the bulk IN endpoint and the bulk OUT endpoint.
+    /// Wrap them with `SyncReader` and `SyncWriter` to do reading and writing in
+    /// different threads.
+    pub fn into_queues(self) -> (Queue<RequestBuffer>, Queue<Vec<u8>>) {
+        (self.reader.into(), self.writer.into())
+    }
+}
+
+#[cfg(feature = "blocking")]
+impl Read for AccessorySerial {
+    #[inline]
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.reader.read(buf, self.timeout)
+    }
+}
+
+#[cfg(feature = "blocking")]
+impl Write for AccessorySerial {
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.write(buf, self.timeout)
+    }
+    /// Waits for the transfer left pending by a polling write (zero timeout), if any.
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.drain(self.timeout)
+    }
+}
+
+impl std::fmt::Debug for AccessorySerial {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AccessorySerial")
+            .field("name", &self.usb_path_name)
+            .field("timeout", &self.timeout)
+            .finish_non_exhaustive()
+    }
+}
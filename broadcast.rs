@@ -0,0 +1,151 @@
+//! Delivers incoming bytes to multiple consumers (e.g. a live console, a file logger
+//! and a protocol parser) without a manual fan-out thread.
+//!
+//! Note: Each consumer has its own bounded queue. If a consumer falls behind and its
+//! queue is full, chunks are dropped for that consumer only, so it cannot starve the
+//! others; the amount of dropped bytes can be checked by `Subscriber::dropped()`.
+
+use std::io::{ErrorKind, Read};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::Error;
+
+type Subscribers = Arc<Mutex<Vec<(SyncSender<Arc<[u8]>>, Arc<AtomicUsize>)>>>;
+
+/// Reader thread fanning out received chunks to all subscribers.
+pub struct Broadcast<R: Read + Send + 'static> {
+    subscribers: Subscribers,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<(R, Result<(), Error>)>>,
+}
+
+impl<R: Read + Send + 'static> Broadcast<R> {
+    /// Starts the reader thread. `reader` should have a read timeout (e.g. `CdcSerial`),
+    /// which decides how quickly the thread responds to `stop()`; `TimedOut` errors are
+    /// ignored, other errors stop the thread. `Ok(0)` is not treated as the end of
+    /// stream, because it is returned on zero-length packets.
+    /// - `chunk_size`: Size of the buffer for each read.
+    pub fn start(mut reader: R, chunk_size: usize) -> Self {
+        let subscribers: Subscribers = Arc::new(Mutex::new(Vec::new()));
+        let stop = Arc::new(AtomicBool::new(false));
+        let (subs, stop_flag) = (subscribers.clone(), stop.clone());
+        let thread = std::thread::spawn(move || {
+            let mut buf = vec![0u8; chunk_size.max(1)];
+            while !stop_flag.load(Ordering::Relaxed) {
+                let len = match reader.read(&mut buf) {
+                    Ok(0) => continue,
+                    Ok(len) => len,
+                    Err(e) if e.kind() == ErrorKind::TimedOut => continue,
+                    Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                    Err(e) => return (reader, Err(e)),
+                };
+                let chunk: Arc<[u8]> = Arc::from(&buf[..len]);
+                lock(&subs).retain(|(sender, dropped)| match sender.try_send(chunk.clone()) {
+                    Ok(()) => true,
+                    Err(TrySendError::Full(_)) => {
+                        dropped.fetch_add(len, Ordering::Relaxed);
+                        true
+                    }
+                    Err(TrySendError::Disconnected(_)) => false,
+                });
+            }
+            (reader, Ok(()))
+        });
+        Self {
+            subscribers,
+            stop,
+            thread: Some(thread),
+        }
+    }
+
+    /// Adds a consumer with a queue of at most `capacity` chunks.
+    /// Data received before subscribing is not delivered to it.
+    pub fn subscribe(&self, capacity: usize) -> Subscriber {
+        let (sender, receiver) = mpsc::sync_channel(capacity.max(1));
+        let dropped = Arc::new(AtomicUsize::new(0));
+        lock(&self.subscribers).push((sender, dropped.clone()));
+        Subscriber { receiver, dropped }
+    }
+
+    /// Returns the amount of consumers which are not dropped.
+    pub fn subscriber_count(&self) -> usize {
+        lock(&self.subscribers).len()
+    }
+
+    /// Returns true if the reader thread has exited because of an error.
+    pub fn is_finished(&self) -> bool {
+        self.thread.as_ref().map_or(true, |t| t.is_finished())
+    }
+
+    /// Stops the reader thread, returns the reader and the error stopping the thread
+    /// (if the thread has exited before). Subscribers are disconnected after receiving
+    /// the remaining chunks.
+    pub fn stop(mut self) -> (R, Result<(), Error>) {
+        self.stop.store(true, Ordering::Relaxed);
+        lock(&self.subscribers).clear();
+        let thread = self.thread.take().unwrap();
+        thread
+            .join()
+            .unwrap_or_else(|e| std::panic::resume_unwind(e))
+    }
+}
+
+impl<R: Read + Send + 'static> Drop for Broadcast<R> {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        lock(&self.subscribers).clear();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Receiving side of a consumer, returned by `Broadcast::subscribe()`.
+/// Dropping it unsubscribes the consumer.
+#[derive(Debug)]
+pub struct Subscriber {
+    receiver: Receiver<Arc<[u8]>>,
+    dropped: Arc<AtomicUsize>,
+}
+
+impl Subscriber {
+    /// Waits for the next chunk. Returns `NotConnected` error if the broadcast is stopped
+    /// and all remaining chunks have been received.
+    pub fn recv(&self) -> Result<Arc<[u8]>, Error> {
+        self.receiver
+            .recv()
+            .map_err(|_| Error::from(ErrorKind::NotConnected))
+    }
+
+    /// Waits for the next chunk with a timeout. Returns `Ok(None)` on timeout.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<Option<Arc<[u8]>>, Error> {
+        match self.receiver.recv_timeout(timeout) {
+            Ok(chunk) => Ok(Some(chunk)),
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Err(RecvTimeoutError::Disconnected) => Err(Error::from(ErrorKind::NotConnected)),
+        }
+    }
+
+    /// Takes the next chunk if it is available. Returns `Ok(None)` if it is not.
+    pub fn try_recv(&self) -> Result<Option<Arc<[u8]>>, Error> {
+        match self.receiver.try_recv() {
+            Ok(chunk) => Ok(Some(chunk)),
+            Err(mpsc::TryRecvError::Empty) => Ok(None),
+            Err(mpsc::TryRecvError::Disconnected) => Err(Error::from(ErrorKind::NotConnected)),
+        }
+    }
+
+    /// Returns the amount of bytes dropped because this consumer's queue was full.
+    pub fn dropped(&self) -> usize {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+#[inline(always)]
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
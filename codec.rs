@@ -0,0 +1,117 @@
+//! Framing helpers for binary serial protocols.
+//!
+//! Note: Serial reads may return partial frames or multiple frames at once, so received
+//! bytes should be accumulated and passed to the decoder.
+
+use std::io::{ErrorKind, Read, Write};
+
+use crate::Error;
+
+/// Width of the length field.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PrefixSize {
+    One = 1,
+    Two = 2,
+    Four = 4,
+}
+
+/// Frames prefixed by the payload length.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LengthPrefixedCodec {
+    /// Width of the length field, `PrefixSize::Two` by default.
+    pub prefix_size: PrefixSize,
+    /// Byte order of the length field, true (big-endian) by default.
+    pub big_endian: bool,
+    /// Maximum payload length, 64 KiB by default. Larger frames are rejected.
+    pub max_frame_len: usize,
+}
+
+impl Default for LengthPrefixedCodec {
+    fn default() -> Self {
+        Self {
+            prefix_size: PrefixSize::Two,
+            big_endian: true,
+            max_frame_len: 64 * 1024,
+        }
+    }
+}
+
+impl LengthPrefixedCodec {
+    /// Appends the frame of `payload` to `dst`.
+    pub fn encode(&self, payload: &[u8], dst: &mut Vec<u8>) -> Result<(), Error> {
+        let len = payload.len();
+        let max_by_prefix = match self.prefix_size {
+            PrefixSize::One => u8::MAX as usize,
+            PrefixSize::Two => u16::MAX as usize,
+            PrefixSize::Four => u32::MAX as usize,
+        };
+        if len > self.max_frame_len || len > max_by_prefix {
+            return Err(Error::new(ErrorKind::InvalidInput, "Frame is too long"));
+        }
+        let len_bytes = if self.big_endian {
+            (len as u32).to_be_bytes()
+        } else {
+            (len as u32).to_le_bytes()
+        };
+        let n = self.prefix_size as usize;
+        if self.big_endian {
+            dst.extend_from_slice(&len_bytes[4 - n..]);
+        } else {
+            dst.extend_from_slice(&len_bytes[..n]);
+        }
+        dst.extend_from_slice(payload);
+        Ok(())
+    }
+
+    /// Takes the first complete frame from the beginning of `src`, returns `Ok(None)`
+    /// if more bytes are required. On `InvalidData` error (the length exceeds the
+    /// maximum), the stream is out of sync and `src` should be cleared.
+    pub fn decode(&self, src: &mut Vec<u8>) -> Result<Option<Vec<u8>>, Error> {
+        let n = self.prefix_size as usize;
+        if src.len() < n {
+            return Ok(None);
+        }
+        let len = self.parse_len(&src[..n]);
+        if len > self.max_frame_len {
+            return Err(Error::new(ErrorKind::InvalidData, "Frame is too long"));
+        }
+        if src.len() < n + len {
+            return Ok(None);
+        }
+        let payload = src[n..n + len].to_vec();
+        src.drain(..n + len);
+        Ok(Some(payload))
+    }
+
+    /// Writes a frame to the port.
+    pub fn write_frame<W: Write>(&self, port: &mut W, payload: &[u8]) -> Result<(), Error> {
+        let mut frame = Vec::with_capacity(payload.len() + 4);
+        self.encode(payload, &mut frame)?;
+        port.write_all(&frame)
+    }
+
+    /// Reads a frame from the port. The port's timeout applies to each read operation.
+    pub fn read_frame<R: Read>(&self, port: &mut R) -> Result<Vec<u8>, Error> {
+        let n = self.prefix_size as usize;
+        let mut len_bytes = [0u8; 4];
+        port.read_exact(&mut len_bytes[..n])?;
+        let len = self.parse_len(&len_bytes[..n]);
+        if len > self.max_frame_len {
+            return Err(Error::new(ErrorKind::InvalidData, "Frame is too long"));
+        }
+        let mut payload = vec![0u8; len];
+        port.read_exact(&mut payload)?;
+        Ok(payload)
+    }
+
+    fn parse_len(&self, bytes: &[u8]) -> usize {
+        let mut len_bytes = [0u8; 4];
+        if self.big_endian {
+            len_bytes[4 - bytes.len()..].copy_from_slice(bytes);
+            u32::from_be_bytes(len_bytes) as usize
+        } else {
+            len_bytes[..bytes.len()].copy_from_slice(bytes);
+            u32::from_le_bytes(len_bytes) as usize
+        }
+    }
+}
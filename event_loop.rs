@@ -0,0 +1,198 @@
+//! `calloop` event sources, for apps driving a `calloop` event loop (e.g. with
+//! `android-activity`). No extra thread is created: USB events are sent from the
+//! broadcast receiver, and completions of IN transfers wake the loop through a ping.
+
+use std::{
+    io::ErrorKind,
+    sync::Arc,
+    task::{Context, Poll, Wake, Waker},
+};
+
+use calloop::{
+    channel::{self, Channel},
+    ping::{make_ping, Ping, PingError, PingSource},
+    EventSource, PostAction, Readiness, Token, TokenFactory,
+};
+use jni_min_helper::BroadcastReceiver;
+use nusb::transfer::{Queue, RequestBuffer, TransferError};
+
+use crate::usb::{self, UsbEvent};
+use crate::Error;
+
+/// Event source of `UsbEvent` (device connection, disconnection, permission results
+/// and accessory events). The broadcast receiver is unregistered when it is dropped.
+pub struct UsbEventSource {
+    channel: Channel<UsbEvent>,
+    receiver: BroadcastReceiver,
+}
+
+impl UsbEventSource {
+    /// Registers the broadcast receiver.
+    pub fn new() -> Result<Self, Error> {
+        let (sender, channel) = channel::channel();
+        let receiver = usb::register_receiver(usb::USB_EVENT_ACTIONS, move |_env, intent| {
+            if let Some(event) = usb::usb_event(intent) {
+                let _ = sender.send(event);
+            }
+            Ok(())
+        })?;
+        Ok(Self { channel, receiver })
+    }
+}
+
+impl Drop for UsbEventSource {
+    fn drop(&mut self) {
+        usb::unregister_receiver(&self.receiver);
+    }
+}
+
+impl EventSource for UsbEventSource {
+    type Event = UsbEvent;
+    type Metadata = ();
+    type Ret = ();
+    type Error = channel::ChannelError;
+
+    fn process_events<F>(
+        &mut self,
+        readiness: Readiness,
+        token: Token,
+        mut callback: F,
+    ) -> Result<PostAction, Self::Error>
+    where
+        F: FnMut(Self::Event, &mut Self::Metadata) -> Self::Ret,
+    {
+        self.channel
+            .process_events(readiness, token, |event, meta| {
+                if let channel::Event::Msg(event) = event {
+                    callback(event, meta)
+                }
+            })
+    }
+
+    fn register(
+        &mut self,
+        poll: &mut calloop::Poll,
+        token_factory: &mut TokenFactory,
+    ) -> calloop::Result<()> {
+        self.channel.register(poll, token_factory)
+    }
+
+    fn reregister(
+        &mut self,
+        poll: &mut calloop::Poll,
+        token_factory: &mut TokenFactory,
+    ) -> calloop::Result<()> {
+        self.channel.reregister(poll, token_factory)
+    }
+
+    fn unregister(&mut self, poll: &mut calloop::Poll) -> calloop::Result<()> {
+        self.channel.unregister(poll)
+    }
+}
+
+/// Event source of data received from the IN endpoint, which can be taken from a serial
+/// handler by `UsbSerial::into_queues()`. Each event is the data of a completed transfer
+/// or the error; it is removed from the loop after the device is disconnected.
+pub struct ReadSource {
+    queue: Queue<RequestBuffer>,
+    ping: PingSource,
+    waker: Waker,
+    transfer_size: usize,
+}
+
+struct PingWaker(Ping);
+
+impl Wake for PingWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.ping();
+    }
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.0.ping();
+    }
+}
+
+impl ReadSource {
+    /// Keeps `num_transfers` IN transfers of `transfer_size` bytes submitted.
+    pub fn new(
+        mut queue: Queue<RequestBuffer>,
+        transfer_size: usize,
+        num_transfers: usize,
+    ) -> Result<Self, Error> {
+        let (ping, ping_source) = make_ping()?;
+        while queue.pending() < num_transfers.max(1) {
+            queue.submit(RequestBuffer::new(transfer_size));
+        }
+        Ok(Self {
+            queue,
+            ping: ping_source,
+            waker: Waker::from(Arc::new(PingWaker(ping))),
+            transfer_size,
+        })
+    }
+
+    /// Takes the queue back, pending transfers are cancelled.
+    pub fn into_inner(mut self) -> Queue<RequestBuffer> {
+        self.queue.cancel_all();
+        self.queue
+    }
+}
+
+impl EventSource for ReadSource {
+    type Event = Result<Vec<u8>, Error>;
+    type Metadata = ();
+    type Ret = ();
+    type Error = PingError;
+
+    fn process_events<F>(
+        &mut self,
+        readiness: Readiness,
+        token: Token,
+        mut callback: F,
+    ) -> Result<PostAction, Self::Error>
+    where
+        F: FnMut(Self::Event, &mut Self::Metadata) -> Self::Ret,
+    {
+        self.ping.process_events(readiness, token, |_, _| {})?;
+        let mut cx = Context::from_waker(&self.waker);
+        while let Poll::Ready(comp) = self.queue.poll_next(&mut cx) {
+            match comp.status {
+                Ok(()) => callback(Ok(comp.data), &mut ()),
+                Err(TransferError::Disconnected) => {
+                    callback(Err(Error::from(ErrorKind::NotConnected)), &mut ());
+                    return Ok(PostAction::Remove);
+                }
+                Err(e) => {
+                    if let TransferError::Stall = e {
+                        let _ = self.queue.clear_halt();
+                    }
+                    callback(Err(usb::map_transfer_error(e)), &mut ())
+                }
+            }
+            self.queue.submit(RequestBuffer::new(self.transfer_size));
+        }
+        Ok(PostAction::Continue)
+    }
+
+    fn register(
+        &mut self,
+        poll: &mut calloop::Poll,
+        token_factory: &mut TokenFactory,
+    ) -> calloop::Result<()> {
+        self.ping.register(poll, token_factory)?;
+        // polls the queue once in the loop, so that the waker is registered
+        self.waker.wake_by_ref();
+        Ok(())
+    }
+
+    fn reregister(
+        &mut self,
+        poll: &mut calloop::Poll,
+        token_factory: &mut TokenFactory,
+    ) -> calloop::Result<()> {
+        self.ping.reregister(poll, token_factory)
+    }
+
+    fn unregister(&mut self, poll: &mut calloop::Poll) -> calloop::Result<()> {
+        self.ping.unregister(poll)
+    }
+}
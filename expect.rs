@@ -0,0 +1,168 @@
+//! `expect`-like scripted dialogue helper, for login prompts and menu-driven equipment
+//! which predates structured protocols.
+//!
+//! Regular expression patterns are available if the `regex` feature is enabled.
+
+use std::io::{ErrorKind, Read, Write};
+use std::time::{Duration, Instant};
+
+use crate::Error;
+
+/// Pattern waited by `Expect::expect()`.
+#[derive(Clone, Debug)]
+pub enum Pattern {
+    /// Exact byte sequence.
+    Exact(Vec<u8>),
+    /// Regular expression matched against received bytes.
+    #[cfg(feature = "regex")]
+    Regex(regex::bytes::Regex),
+}
+
+impl Pattern {
+    /// Exact byte sequence, e.g. `Pattern::exact("login: ")`.
+    pub fn exact(bytes: impl AsRef<[u8]>) -> Self {
+        Self::Exact(bytes.as_ref().to_vec())
+    }
+
+    /// Compiles the regular expression. Returns `InvalidInput` error if it is invalid.
+    #[cfg(feature = "regex")]
+    pub fn regex(re: &str) -> Result<Self, Error> {
+        regex::bytes::Regex::new(re)
+            .map(Self::Regex)
+            .map_err(|e| Error::new(ErrorKind::InvalidInput, e))
+    }
+
+    /// Returns (start, end, captures) of the first match in `buf`.
+    fn find(&self, buf: &[u8]) -> Option<(usize, usize, Vec<Option<Vec<u8>>>)> {
+        match self {
+            Self::Exact(bytes) if bytes.is_empty() => Some((0, 0, Vec::new())),
+            Self::Exact(bytes) => buf
+                .windows(bytes.len())
+                .position(|w| w == bytes.as_slice())
+                .map(|pos| (pos, pos + bytes.len(), Vec::new())),
+            #[cfg(feature = "regex")]
+            Self::Regex(re) => re.captures(buf).map(|caps| {
+                let whole = caps.get(0).unwrap();
+                let groups = caps
+                    .iter()
+                    .skip(1)
+                    .map(|m| m.map(|m| m.as_bytes().to_vec()))
+                    .collect();
+                (whole.start(), whole.end(), groups)
+            }),
+        }
+    }
+}
+
+/// Result of `Expect::expect()`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Match {
+    /// Index of the matched pattern in the given slice.
+    pub index: usize,
+    /// Bytes received before the match.
+    pub before: Vec<u8>,
+    /// The matched bytes.
+    pub matched: Vec<u8>,
+    /// Capture groups of a regular expression, empty for exact patterns.
+    pub captures: Vec<Option<Vec<u8>>>,
+}
+
+/// Dialogue helper wrapping the port. The port should have a read timeout
+/// (e.g. `CdcSerial`) shorter than the timeout given to `expect()`.
+#[derive(Debug)]
+pub struct Expect<P: Read + Write> {
+    port: P,
+    buf: Vec<u8>,       // received bytes not consumed by matches
+    max_buf_len: usize, // older bytes are discarded if it is exceeded
+}
+
+impl<P: Read + Write> Expect<P> {
+    /// Wraps the port. At most 64 KiB of unmatched bytes are kept.
+    pub fn new(port: P) -> Self {
+        Self {
+            port,
+            buf: Vec::new(),
+            max_buf_len: 64 * 1024,
+        }
+    }
+
+    /// Takes the port and the received bytes not consumed by matches.
+    pub fn into_inner(self) -> (P, Vec<u8>) {
+        (self.port, self.buf)
+    }
+
+    /// Returns the received bytes not consumed by matches.
+    pub fn buffer(&self) -> &[u8] {
+        &self.buf
+    }
+
+    /// Discards the received bytes not consumed by matches.
+    pub fn clear(&mut self) {
+        self.buf.clear();
+    }
+
+    /// Sets the maximum amount of unmatched bytes to be kept, 64 KiB by default.
+    pub fn set_max_buffer_len(&mut self, len: usize) {
+        self.max_buf_len = len.max(1);
+    }
+
+    /// Writes all bytes to the port.
+    pub fn send(&mut self, data: impl AsRef<[u8]>) -> Result<(), Error> {
+        self.port.write_all(data.as_ref())?;
+        self.port.flush()
+    }
+
+    /// Waits for the first occurrence of any pattern; if more than one pattern matches,
+    /// the one starting earliest (then the one listed first) is chosen. Bytes up to the
+    /// end of the match are consumed. Returns `TimedOut` error if nothing is matched
+    /// before the timeout, the received bytes are kept in the buffer.
+    pub fn expect(&mut self, patterns: &[Pattern], timeout: Duration) -> Result<Match, Error> {
+        let deadline = Instant::now() + timeout;
+        let mut chunk = vec![0u8; 1024];
+        loop {
+            if let Some(m) = self.take_match(patterns) {
+                return Ok(m);
+            }
+            if Instant::now() >= deadline {
+                return Err(Error::new(ErrorKind::TimedOut, "No pattern is matched"));
+            }
+            match self.port.read(&mut chunk) {
+                Ok(len) => self.buf.extend_from_slice(&chunk[..len]),
+                Err(e) if e.kind() == ErrorKind::TimedOut => (),
+                Err(e) if e.kind() == ErrorKind::Interrupted => (),
+                Err(e) => return Err(e),
+            }
+            if self.buf.len() > self.max_buf_len {
+                let excess = self.buf.len() - self.max_buf_len;
+                self.buf.drain(..excess);
+            }
+        }
+    }
+
+    /// Sends `data`, then waits for one of the patterns. Check `expect()`.
+    pub fn send_expect(
+        &mut self,
+        data: impl AsRef<[u8]>,
+        patterns: &[Pattern],
+        timeout: Duration,
+    ) -> Result<Match, Error> {
+        self.send(data)?;
+        self.expect(patterns, timeout)
+    }
+
+    fn take_match(&mut self, patterns: &[Pattern]) -> Option<Match> {
+        let (index, (start, end, captures)) = patterns
+            .iter()
+            .enumerate()
+            .filter_map(|(i, pat)| pat.find(&self.buf).map(|found| (i, found)))
+            .min_by_key(|(i, (start, _, _))| (*start, *i))?;
+        let mut before: Vec<u8> = self.buf.drain(..end).collect();
+        let matched = before.split_off(start);
+        Some(Match {
+            index,
+            before,
+            matched,
+            captures,
+        })
+    }
+}
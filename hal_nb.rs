@@ -0,0 +1,127 @@
+//! `embedded-hal-nb` serial adapter, so host tooling written against
+//! `embedded_hal_nb::serial::{Read, Write}` can target the serial handlers.
+//!
+//! Note: Set a zero (or very short) timeout for the handler, e.g. by
+//! `SerialPort::set_timeout(Duration::ZERO)`; `TimedOut` errors of the port are
+//! reported as `nb::Error::WouldBlock`.
+
+use std::io::{self, ErrorKind, Read, Write};
+
+use embedded_hal_nb::nb;
+use embedded_hal_nb::serial;
+
+/// Size of the receive buffer and the transmit buffer of `NbSerial`.
+const BUF_SIZE: usize = 512;
+
+/// Wraps a port (e.g. `CdcSerial`) to implement `embedded-hal-nb` serial traits.
+///
+/// Received bytes are buffered to avoid doing a USB transfer for each word; written
+/// words are buffered until `flush()` is called or the buffer is full.
+#[derive(Debug)]
+pub struct NbSerial<P: Read + Write> {
+    port: P,
+    rx_buf: Vec<u8>, // received bytes
+    rx_pos: usize,   // amount of consumed bytes in `rx_buf`
+    tx_buf: Vec<u8>, // words not sent yet
+}
+
+impl<P: Read + Write> NbSerial<P> {
+    /// Wraps the port. Check the module documentation for the timeout setting.
+    pub fn new(port: P) -> Self {
+        Self {
+            port,
+            rx_buf: Vec::with_capacity(BUF_SIZE),
+            rx_pos: 0,
+            tx_buf: Vec::with_capacity(BUF_SIZE),
+        }
+    }
+
+    /// Returns a reference of the port.
+    pub fn get_ref(&self) -> &P {
+        &self.port
+    }
+
+    /// Returns a mutable reference of the port. Reading or writing through it directly
+    /// may reorder the data buffered here.
+    pub fn get_mut(&mut self) -> &mut P {
+        &mut self.port
+    }
+
+    /// Takes the port. Buffered received bytes and unsent words are dropped.
+    pub fn into_inner(self) -> P {
+        self.port
+    }
+}
+
+/// Error of `NbSerial`, wrapping the I/O error of the port.
+#[derive(Debug)]
+pub struct NbSerialError(pub io::Error);
+
+impl serial::Error for NbSerialError {
+    fn kind(&self) -> serial::ErrorKind {
+        serial::ErrorKind::Other
+    }
+}
+
+impl From<NbSerialError> for io::Error {
+    fn from(value: NbSerialError) -> Self {
+        value.0
+    }
+}
+
+fn nb_error(err: io::Error) -> nb::Error<NbSerialError> {
+    match err.kind() {
+        ErrorKind::TimedOut | ErrorKind::WouldBlock | ErrorKind::Interrupted => {
+            nb::Error::WouldBlock
+        }
+        _ => nb::Error::Other(NbSerialError(err)),
+    }
+}
+
+impl<P: Read + Write> serial::ErrorType for NbSerial<P> {
+    type Error = NbSerialError;
+}
+
+impl<P: Read + Write> serial::Read<u8> for NbSerial<P> {
+    fn read(&mut self) -> nb::Result<u8, Self::Error> {
+        if self.rx_pos >= self.rx_buf.len() {
+            self.rx_buf.resize(BUF_SIZE, 0);
+            let len = self.port.read(&mut self.rx_buf).map_err(|e| {
+                self.rx_buf.clear();
+                nb_error(e)
+            })?;
+            self.rx_buf.truncate(len);
+            self.rx_pos = 0;
+            if len == 0 {
+                return Err(nb::Error::WouldBlock); // zero-length packet
+            }
+        }
+        let word = self.rx_buf[self.rx_pos];
+        self.rx_pos += 1;
+        Ok(word)
+    }
+}
+
+impl<P: Read + Write> serial::Write<u8> for NbSerial<P> {
+    fn write(&mut self, word: u8) -> nb::Result<(), Self::Error> {
+        if self.tx_buf.len() >= BUF_SIZE {
+            serial::Write::flush(self)?;
+        }
+        self.tx_buf.push(word);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> nb::Result<(), Self::Error> {
+        while !self.tx_buf.is_empty() {
+            match self.port.write(&self.tx_buf) {
+                Ok(0) => {
+                    let err = io::Error::from(ErrorKind::WriteZero);
+                    return Err(nb::Error::Other(NbSerialError(err)));
+                }
+                Ok(len) => drop(self.tx_buf.drain(..len)),
+                Err(e) => return Err(nb_error(e)),
+            }
+        }
+        self.port.flush().map_err(nb_error)
+    }
+}
@@ -6,16 +6,74 @@
 //! [react-native-usb-serialport](https://www.npmjs.com/package/react-native-usb-serialport),
 //! however, that may introduce multiple layers between Rust and the Linux kernel.
 //!
-//! This crate uses `ndk_context::AndroidContext`, usually initialized by `android_activity`.
+//! A raw driver of USB printer class devices (`UsbPrinter`) is also provided, so receipt printers
+//! can be driven with the same `Write` and timeout API.
+//!
+//! Android devices can also be switched into the Android Open Accessory mode and used as
+//! serial links, check the `accessory` module.
+//!
+//! This crate uses `ndk_context::AndroidContext`, usually initialized by `android_activity`;
+//! apps without it should call `usb::init_with()` first. Forward Activity pause and resume
+//! events to `usb::on_pause()` and `usb::on_resume()`, so ports survive screen rotation
+//! and app switching; handlers can be parked by `usb::retain()` while the Activity is
+//! recreated on configuration changes.
 //!
 //! The initial version of this crate performs USB transfers through JNI calls but not `nusb`,
-//! do not use it except you have encountered compatibility problems.
+//! do not use it except you have encountered compatibility problems. `CdcSerial` can also
+//! be opened with `Backend::JavaBulkTransfer` for such problems.
+//!
+//! Cargo features:
+//! - `serialport` (default): implements `serialport::SerialPort` for the serial handlers,
+//!   which requires `blocking`. Without it, `DataBits`, `Parity`, `StopBits` and
+//!   `FlowControl` are defined in this crate.
+//! - `blocking` (default): synchronous wrappers of `nusb` transfer queues, `Read` and
+//!   `Write` implementations of the handlers based on them, and `RobustSerial` reopening
+//!   `CdcSerial` after reattachment. Without it, handlers can still be opened and
+//!   configured, then their queues can be taken by `into_queues()`.
+//! - `async` (default): implements `Stream` for `HotplugWatch` and `UsbEventWatch`,
+//!   and `Future` for `PermissionRequest`; provides `AsyncReader` and `AsyncWriter`
+//!   implementing `futures-io` traits. Without it, `futures-core` and `futures-io`
+//!   are not required.
+//! - `calloop`: provides `calloop` event sources of USB events and received data
+//!   in the `event_loop` module.
+//! - `regex`: enables regular expression patterns in the `expect` module.
+//! - `embedded-hal-nb`: provides the `NbSerial` adapter implementing `embedded-hal-nb`
+//!   serial traits in the `hal_nb` module.
 
+pub mod accessory;
+pub mod broadcast;
+pub mod codec;
+#[cfg(feature = "calloop")]
+pub mod event_loop;
+pub mod expect;
+#[cfg(feature = "embedded-hal-nb")]
+pub mod hal_nb;
+pub mod modeswitch;
+mod printer;
+mod probe;
+pub mod profile;
 mod ser_cdc;
+#[cfg(feature = "blocking")]
+mod ser_robust;
+mod ser_virtual;
+pub mod stm32boot;
+#[cfg(feature = "async")]
+mod usb_async;
 mod usb_conn;
+mod usb_control;
+mod usb_filter;
 mod usb_info;
+mod usb_java;
+mod usb_lifecycle;
+mod usb_monitor;
+#[cfg(feature = "blocking")]
 mod usb_sync;
+pub use printer::*;
+pub use probe::*;
 pub use ser_cdc::*;
+#[cfg(feature = "blocking")]
+pub use ser_robust::*;
+pub use ser_virtual::*;
 
 /// Equals `std::io::Error`.
 pub type Error = std::io::Error;
@@ -26,11 +84,32 @@ pub type Error = std::io::Error;
 /// - <https://developer.android.com/develop/connectivity/usb/host>
 /// - <https://developer.android.com/reference/android/hardware/usb/package-summary>
 pub mod usb {
+    #[cfg(feature = "async")]
+    pub use crate::usb_async::*;
     pub use crate::usb_conn::*;
+    pub use crate::usb_control::*;
+    pub use crate::usb_filter::*;
     pub use crate::usb_info::*;
+    pub use crate::usb_java::*;
+    pub use crate::usb_lifecycle::*;
+    pub use crate::usb_monitor::*;
+    #[cfg(feature = "blocking")]
     pub use crate::usb_sync::*;
     pub use crate::Error;
 
+    /// Reader of the bulk IN endpoint kept by handlers: `SyncReader` if the `blocking`
+    /// feature is enabled, otherwise the queue to be taken by `into_queues()`.
+    #[cfg(feature = "blocking")]
+    pub(crate) type BulkReader = SyncReader;
+    #[cfg(not(feature = "blocking"))]
+    pub(crate) type BulkReader = nusb::transfer::Queue<nusb::transfer::RequestBuffer>;
+
+    /// Writer of the bulk OUT endpoint kept by handlers. Check `BulkReader`.
+    #[cfg(feature = "blocking")]
+    pub(crate) type BulkWriter = SyncWriter;
+    #[cfg(not(feature = "blocking"))]
+    pub(crate) type BulkWriter = nusb::transfer::Queue<Vec<u8>>;
+
     /// Maps unexpected JNI errors to `std::io::Error` of `ErrorKind::Other`
     /// (`From<jni::errors::Error>` cannot be implemented for `std::io::Error`
     /// here because of the orphan rule). Side effect: `jni_last_cleared_ex()`.
@@ -52,27 +131,179 @@ pub mod usb {
             Error::other(err)
         }
     }
+
+    /// Maps `nusb` transfer errors to `std::io::Error` of the corresponding `ErrorKind`,
+    /// keeping the `TransferError` as the inner error:
+    /// - `Cancelled`: `Interrupted` (synchronous wrappers report `TimedOut` instead);
+    /// - `Stall`: `BrokenPipe` (the halt should be cleared, check `RetryPolicy`);
+    /// - `Disconnected`: `NotConnected`;
+    /// - `Fault`: `InvalidData` (protocol errors, e.g. CRC or babble errors on bad cables);
+    /// - `Unknown`: `Other`.
+    pub fn map_transfer_error(err: nusb::transfer::TransferError) -> Error {
+        use nusb::transfer::TransferError;
+        use std::io::ErrorKind;
+        let kind = match err {
+            TransferError::Cancelled => ErrorKind::Interrupted,
+            TransferError::Stall => ErrorKind::BrokenPipe,
+            TransferError::Disconnected => ErrorKind::NotConnected,
+            TransferError::Fault => ErrorKind::InvalidData,
+            TransferError::Unknown => ErrorKind::Other,
+        };
+        Error::new(kind, err)
+    }
+
+    /// Returns the `TransferError` kept in the error returned by transfer functions of this
+    /// crate, so callers can tell the original failure from other errors of the same kind.
+    pub fn transfer_error(err: &Error) -> Option<nusb::transfer::TransferError> {
+        err.get_ref()?.downcast_ref().copied()
+    }
 }
 
 use nusb::transfer::{Queue, RequestBuffer};
 
-/// Serial driver implementations inside this crate should implement this trait.
+/// Supertrait of `UsbSerial`: `serialport::SerialPort` if the `serialport` feature is enabled,
+/// otherwise `Read + Write + Send`.
+#[cfg(feature = "serialport")]
+#[doc(hidden)]
+pub trait SerialPortBase: serialport::SerialPort {}
+#[cfg(feature = "serialport")]
+impl<T: serialport::SerialPort> SerialPortBase for T {}
+
+/// Supertrait of `UsbSerial`: `serialport::SerialPort` if the `serialport` feature is enabled,
+/// otherwise `Read + Write + Send`.
+#[cfg(all(feature = "blocking", not(feature = "serialport")))]
+#[doc(hidden)]
+pub trait SerialPortBase: std::io::Read + std::io::Write + Send {}
+#[cfg(all(feature = "blocking", not(feature = "serialport")))]
+impl<T: std::io::Read + std::io::Write + Send> SerialPortBase for T {}
+
+/// Supertrait of `UsbSerial`: only `Send` without the `blocking` feature, because the
+/// handlers have no `Read` and `Write` implementations then.
+#[cfg(not(feature = "blocking"))]
+#[doc(hidden)]
+pub trait SerialPortBase: Send {}
+#[cfg(not(feature = "blocking"))]
+impl<T: Send> SerialPortBase for T {}
+
+/// Supertrait of `UsbSerial` for downcasting, implemented for all `'static` types.
+#[doc(hidden)]
+pub trait AsAny: std::any::Any {
+    fn as_any(&self) -> &dyn std::any::Any;
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any;
+}
+impl<T: std::any::Any> AsAny for T {
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
+
+/// Serial driver implementations should implement this trait. Drivers outside of this
+/// crate can be registered by `register_driver()` to participate in `open_any()`.
 ///
-/// TODO: add crate-level functions `probe() -> Result<Vec<DeviceInfo>, Error>`
-/// and `open(dev_info: &DeviceInfo, timeout: Duration) -> Result<Box<dyn UsbSerial>, Error>`.
-pub trait UsbSerial: serialport::SerialPort {
+/// `Box<dyn UsbSerial>` can hold handlers of different drivers; use `downcast_ref()` or
+/// `downcast_mut()` to reach driver-specific functions.
+pub trait UsbSerial: SerialPortBase + AsAny {
     /// Sets baudrate, parity check mode, data bits and stop bits.
     fn configure(&mut self, conf: &SerialConfig) -> std::io::Result<()>;
 
     /// Takes `nusb` transfer queues of the read endpoint and the write endpoint.
     /// This can be called after serial configuration to do asynchronous operations.
-    fn into_queues(self) -> (Queue<RequestBuffer>, Queue<Vec<u8>>);
+    /// Returns `ErrorKind::Unsupported` if the handler doesn't transfer through `nusb`
+    /// (then the handler is dropped).
+    fn into_queues(self) -> std::io::Result<(Queue<RequestBuffer>, Queue<Vec<u8>>)>
+    where
+        Self: Sized;
+
+    /// Sets DTR and RTS states. Returns `ErrorKind::Unsupported` by default.
+    fn set_dtr_rts(&mut self, dtr: bool, rts: bool) -> std::io::Result<()> {
+        let _ = (dtr, rts);
+        Err(std::io::Error::from(std::io::ErrorKind::Unsupported))
+    }
+
+    /// Returns the features supported by this device and driver combination, so generic
+    /// UIs can enable or disable controls without trying them. Nothing is supported
+    /// by default.
+    fn capabilities(&self) -> PortCapabilities {
+        PortCapabilities {
+            port_count: 1,
+            ..Default::default()
+        }
+    }
+}
+
+/// Features supported by a serial handler, returned by `UsbSerial::capabilities()`.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct PortCapabilities {
+    /// The break signal can be sent.
+    pub break_signal: bool,
+    /// Flow control modes other than `FlowControl::None` can be set.
+    pub flow_control: bool,
+    /// DTR and RTS states can be set.
+    pub modem_lines: bool,
+    /// Range of adjustable baudrates (inclusive), `None` if it cannot be changed.
+    pub baud_range: Option<(u32, u32)>,
+    /// Amount of serial ports of the device, which may be opened separately.
+    pub port_count: usize,
+}
+
+impl dyn UsbSerial {
+    /// Returns the reference of the concrete handler if it is of type `T`.
+    pub fn downcast_ref<T: UsbSerial>(&self) -> Option<&T> {
+        AsAny::as_any(self).downcast_ref()
+    }
+
+    /// Returns the mutable reference of the concrete handler if it is of type `T`.
+    pub fn downcast_mut<T: UsbSerial>(&mut self) -> Option<&mut T> {
+        AsAny::as_any_mut(self).downcast_mut()
+    }
+
+    /// Returns true if the concrete handler is of type `T`.
+    pub fn is<T: UsbSerial>(&self) -> bool {
+        AsAny::as_any(self).is::<T>()
+    }
+}
 
-    #[doc(hidden)]
-    fn sealer(_: private::Internal);
+#[cfg(feature = "serialport")]
+pub use serialport::{DataBits, FlowControl, Parity, StopBits};
+
+/// Number of bits per character.
+#[cfg(not(feature = "serialport"))]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DataBits {
+    Five,
+    Six,
+    Seven,
+    Eight,
 }
 
-use serialport::{DataBits, Parity, StopBits};
+/// Parity checking modes.
+#[cfg(not(feature = "serialport"))]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Parity {
+    None,
+    Odd,
+    Even,
+}
+
+/// Number of stop bits.
+#[cfg(not(feature = "serialport"))]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StopBits {
+    One,
+    Two,
+}
+
+/// Flow control modes.
+#[cfg(not(feature = "serialport"))]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FlowControl {
+    None,
+    Software,
+    Hardware,
+}
 
 /// Serial parameters including baudrate, parity check mode, data bits and stop bits.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -175,8 +406,20 @@ impl std::fmt::Display for SerialConfig {
     }
 }
 
-mod private {
-    /// Used as a parameter of the hidden function in sealed traits.
-    #[derive(Debug)]
-    pub struct Internal;
-}
+// Compile-time checks of thread safety of the public handlers.
+const _: () = {
+    const fn assert_send<T: Send>() {}
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send::<CdcSerial>();
+    assert_send_sync::<usb::DeviceInfo>();
+};
+#[cfg(feature = "blocking")]
+const _: () = {
+    const fn assert_send<T: Send>() {}
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send::<RobustSerial>();
+    assert_send_sync::<usb::SyncReader>();
+    assert_send_sync::<usb::SyncWriter>();
+    assert_send_sync::<usb::SyncInterruptReader>();
+    assert_send_sync::<usb::SyncInterruptWriter>();
+};
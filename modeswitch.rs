@@ -0,0 +1,210 @@
+//! Mode switching helpers for devices which enumerate as mass storage devices first,
+//! and expose their serial (ACM) functions only after receiving a "switch" message,
+//! like what `usb_modeswitch` does on desktop Linux. Most of them are LTE/3G sticks.
+//!
+//! The device disconnects and connects again with another product ID after switching,
+//! so it should be probed again (possibly with `usb::watch_devices()`), and the permission
+//! has to be requested again for the new device.
+//!
+//! Reference: <https://www.draisberghof.de/usb_modeswitch/>.
+//!
+//! Note: Switching messages can only be sent with the `blocking` feature.
+
+// constants of switching messages are unused without the `blocking` feature
+#![cfg_attr(not(feature = "blocking"), allow(dead_code))]
+
+use crate::usb::DeviceInfo;
+
+#[cfg(feature = "blocking")]
+use crate::usb::{SyncReader, SyncWriter};
+#[cfg(feature = "blocking")]
+use crate::Error;
+#[cfg(feature = "blocking")]
+use nusb::transfer::{Control, ControlType, Direction, EndpointType, Recipient, TransferError};
+#[cfg(feature = "blocking")]
+use std::{io::ErrorKind, time::Duration};
+
+const USB_INTR_CLASS_MASS_STORAGE: u8 = 0x08;
+const CBW_SIGNATURE: &[u8; 4] = b"USBC";
+const CBW_LEN: usize = 31;
+const CSW_LEN: usize = 13;
+
+/// SCSI `PREVENT ALLOW MEDIUM REMOVAL` (allow).
+const SCSI_ALLOW_MEDIUM_REMOVAL: &[u8] = &[0x1E, 0, 0, 0, 0, 0];
+/// SCSI `START STOP UNIT` with the `LoEj` bit (eject).
+const SCSI_EJECT: &[u8] = &[0x1B, 0, 0, 0, 0x02, 0];
+/// Huawei vendor-specific SCSI command ("HuaweiNewMode" in `usb_modeswitch`).
+const SCSI_HUAWEI_NEW_MODE: &[u8] = &[
+    0x11, 0x06, 0x20, 0, 0, 0x01, 0x01, 0, 0x01, 0, 0, 0, 0, 0, 0, 0,
+];
+
+/// How to switch the device into the mode with serial functions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SwitchMethod {
+    /// Sends a vendor-specific control request without data (recipient: device).
+    VendorControl { request: u8, value: u16, index: u16 },
+    /// Sends a SCSI command block through the Bulk-Only Transport of the mass storage
+    /// interface (it is wrapped in a Command Block Wrapper here).
+    ScsiCommand(&'static [u8]),
+    /// Sends SCSI commands `ALLOW MEDIUM REMOVAL` and `START STOP UNIT` (eject).
+    StandardEject,
+}
+
+/// A known mode switching sequence for devices of given vendor ID and product ID.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ModeSwitch {
+    /// Vendor ID in the initial mode.
+    pub vendor_id: u16,
+    /// Product ID in the initial mode.
+    pub product_id: u16,
+    /// The switching message.
+    pub method: SwitchMethod,
+}
+
+/// A small table of known switching sequences. It is far from being complete, and
+/// custom `ModeSwitch` entries can be applied with `apply()`.
+pub const KNOWN_SWITCHES: &[ModeSwitch] = &[
+    // Huawei E1550, E173 and many others
+    ModeSwitch {
+        vendor_id: 0x12d1,
+        product_id: 0x1446,
+        method: SwitchMethod::ScsiCommand(SCSI_HUAWEI_NEW_MODE),
+    },
+    // ZTE MF series
+    ModeSwitch {
+        vendor_id: 0x19d2,
+        product_id: 0x2000,
+        method: SwitchMethod::StandardEject,
+    },
+    // Sierra Wireless (TRU-Install), `SWIMS_USB_REQUEST_SetSwocMode` to modem mode
+    ModeSwitch {
+        vendor_id: 0x1199,
+        product_id: 0x0fff,
+        method: SwitchMethod::VendorControl {
+            request: 0x0b,
+            value: 0x0001,
+            index: 0,
+        },
+    },
+];
+
+/// Finds the switching sequence for the device in `KNOWN_SWITCHES`.
+pub fn find_switch(dev_info: &DeviceInfo) -> Option<&'static ModeSwitch> {
+    KNOWN_SWITCHES
+        .iter()
+        .find(|sw| sw.vendor_id == dev_info.vendor_id() && sw.product_id == dev_info.product_id())
+}
+
+#[cfg(feature = "blocking")]
+/// Applies the known switching sequence if the device is found in `KNOWN_SWITCHES`.
+/// Returns `Ok(false)` if it is not found. Please get permission for the device before
+/// calling this function, and call it before probing for serial devices.
+pub fn switch_if_known(dev_info: &DeviceInfo, timeout: Duration) -> Result<bool, Error> {
+    let Some(switch) = find_switch(dev_info) else {
+        return Ok(false);
+    };
+    apply(dev_info, switch, timeout).map(|_| true)
+}
+
+#[cfg(feature = "blocking")]
+/// Sends the switching message to the device. Please get permission for the device
+/// before calling this function.
+pub fn apply(dev_info: &DeviceInfo, switch: &ModeSwitch, timeout: Duration) -> Result<(), Error> {
+    let intr_num = dev_info
+        .try_interfaces()?
+        .find(|intr| intr.class() == USB_INTR_CLASS_MASS_STORAGE)
+        .or(dev_info.interfaces().next())
+        .map(|intr| intr.interface_number())
+        .ok_or(Error::new(ErrorKind::NotFound, "No interface found"))?;
+    let device = dev_info.open_device()?;
+    let intr = device.detach_and_claim_interface(intr_num)?;
+
+    match switch.method {
+        SwitchMethod::VendorControl {
+            request,
+            value,
+            index,
+        } => {
+            let control = Control {
+                control_type: ControlType::Vendor,
+                recipient: Recipient::Device,
+                request,
+                value,
+                index,
+            };
+            match intr.control_out_blocking(control, &[], timeout) {
+                // the device may disconnect before responding
+                Ok(_) | Err(TransferError::Disconnected) => Ok(()),
+                Err(e) => Err(crate::usb::map_transfer_error(e)),
+            }
+        }
+        SwitchMethod::ScsiCommand(cdb) => send_scsi_commands(&intr, &[cdb], timeout),
+        SwitchMethod::StandardEject => {
+            send_scsi_commands(&intr, &[SCSI_ALLOW_MEDIUM_REMOVAL, SCSI_EJECT], timeout)
+        }
+    }
+}
+
+#[cfg(feature = "blocking")]
+/// Sends SCSI commands without data stage through the Bulk-Only Transport.
+fn send_scsi_commands(
+    intr: &nusb::Interface,
+    cdbs: &[&[u8]],
+    timeout: Duration,
+) -> Result<(), Error> {
+    let (mut addr_r, mut addr_w) = (None, None);
+    for alt in intr.descriptors() {
+        for endp in alt.endpoints() {
+            match (endp.transfer_type(), endp.direction()) {
+                (EndpointType::Bulk, Direction::In) => addr_r = addr_r.or(Some(endp.address())),
+                (EndpointType::Bulk, Direction::Out) => addr_w = addr_w.or(Some(endp.address())),
+                _ => (),
+            }
+        }
+    }
+    let addr_w = addr_w.ok_or(Error::new(
+        ErrorKind::NotFound,
+        "Bulk OUT endpoint not found",
+    ))?;
+    let mut writer = SyncWriter::new(intr.bulk_out_queue(addr_w));
+    let mut reader = addr_r.map(|r| SyncReader::new(intr.bulk_in_queue(r)));
+
+    for (i, cdb) in cdbs.iter().enumerate() {
+        let cbw = command_block_wrapper(0x12345678 + i as u32, cdb)?;
+        match writer.write(&cbw, timeout) {
+            Ok(CBW_LEN) => (),
+            Ok(_) => {
+                return Err(Error::new(
+                    ErrorKind::WriteZero,
+                    "CBW is not sent completely",
+                ))
+            }
+            Err(e) if e.kind() == ErrorKind::NotConnected => return Ok(()), // switched
+            Err(e) => return Err(e),
+        }
+        // reads the Command Status Wrapper; the device may switch before sending it
+        if let Some(reader) = reader.as_mut() {
+            let mut csw = [0u8; CSW_LEN];
+            let _ = reader.read(&mut csw, timeout);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(feature = "blocking")]
+/// Wraps the SCSI command block (up to 16 bytes) in a Command Block Wrapper.
+fn command_block_wrapper(tag: u32, cdb: &[u8]) -> Result<[u8; CBW_LEN], Error> {
+    if cdb.is_empty() || cdb.len() > 16 {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Bad SCSI command block length",
+        ));
+    }
+    let mut cbw = [0u8; CBW_LEN];
+    cbw[..4].copy_from_slice(CBW_SIGNATURE);
+    cbw[4..8].copy_from_slice(&tag.to_le_bytes());
+    // data transfer length: 0; flags: 0 (OUT); LUN: 0
+    cbw[14] = cdb.len() as u8;
+    cbw[15..15 + cdb.len()].copy_from_slice(cdb);
+    Ok(cbw)
+}
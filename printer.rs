@@ -0,0 +1,263 @@
+use std::{
+    io::{self, Error, ErrorKind},
+    time::Duration,
+};
+
+use crate::usb::{self, BulkReader, BulkWriter, DeviceInfo, InterfaceInfo, PortLock, SyncControl};
+use nusb::transfer::{Direction, EndpointType, Queue, RequestBuffer};
+
+#[cfg(feature = "blocking")]
+use std::io::{Read, Write};
+
+const USB_INTR_CLASS_PRINTER: u8 = 0x07;
+const USB_INTR_SUBCLASS_PRINTER: u8 = 0x01;
+const USB_INTR_PROTOCOL_BIDIRECTIONAL: u8 = 0x02;
+
+const GET_DEVICE_ID: u8 = 0x00;
+const GET_PORT_STATUS: u8 = 0x01;
+const SOFT_RESET: u8 = 0x02;
+
+/// Raw driver of USB printer class devices, e.g. ESC/POS receipt printers and label
+/// printers. The print data is written to the bulk OUT endpoint as-is; the bulk IN
+/// endpoint is available only if the device supports the bidirectional protocol.
+///
+/// Reference: *Universal Serial Bus Device Class Definition for Printing Devices,
+/// Version 1.1*, especially section 4.2.
+pub struct UsbPrinter {
+    usb_path_name: String,      // the name from `android.hardware.usb.UsbDevice`
+    ctrl_index: u16,            // interface number and alternate setting for control transfers
+    ctrl: SyncControl,          // printer interface keeper
+    reader: Option<BulkReader>, // for the optional bulk IN endpoint
+    writer: BulkWriter,         // for the bulk OUT endpoint
+
+    timeout: Duration,              // standard `Read` and `Write` timeout
+    ctrl_timeout: Option<Duration>, // control transfer timeout, `timeout * 2` if `None`
+    _lock: PortLock,                // released on drop or `into_queues()`
+}
+
+impl UsbPrinter {
+    /// Probes for USB printer class devices. Returns an empty vector if no device is found.
+    pub fn probe() -> io::Result<Vec<DeviceInfo>> {
+        let devs = usb::list_devices_cached()?;
+        Ok(devs
+            .into_iter()
+            .filter(|dev| matches!(Self::find_interface(dev), Ok(Some(_))))
+            .collect())
+    }
+
+    /// Connects to the printer, returns the `UsbPrinter` handler.
+    /// Please get permission for the device before calling this function.
+    /// Returns `AlreadyExists` error if the printer is opened by another handler in this process.
+    /// - `timeout`: Set for standard `Read` and `Write` traits.
+    pub fn build(dev_info: &DeviceInfo, timeout: Duration) -> io::Result<Self> {
+        let intr_info = Self::find_interface(dev_info)?
+            .ok_or(Error::new(ErrorKind::InvalidInput, "Not a USB printer"))?;
+        let lock = PortLock::acquire_as(dev_info, intr_info.interface_number(), "UsbPrinter")?;
+
+        let device = dev_info.open_device()?;
+        let intr = device.detach_and_claim_interface(intr_info.interface_number())?;
+
+        // Prefers the bidirectional alternate setting.
+        let mut selected = None;
+        for alt in intr.descriptors() {
+            if alt.class() != USB_INTR_CLASS_PRINTER {
+                continue;
+            }
+            let endps: Vec<_> = alt
+                .endpoints()
+                .filter(|endp| endp.transfer_type() == EndpointType::Bulk)
+                .collect();
+            let addr_r = endps
+                .iter()
+                .find(|endp| endp.direction() == Direction::In)
+                .map(|endp| endp.address());
+            let Some(addr_w) = endps
+                .iter()
+                .find(|endp| endp.direction() == Direction::Out)
+                .map(|endp| endp.address())
+            else {
+                continue;
+            };
+            let bidir = alt.protocol() == USB_INTR_PROTOCOL_BIDIRECTIONAL && addr_r.is_some();
+            if selected.is_none() || bidir {
+                selected.replace((alt.alternate_setting(), addr_r, addr_w));
+            }
+            if bidir {
+                break;
+            }
+        }
+        let Some((alt_setting, addr_r, addr_w)) = selected else {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                "Bulk OUT endpoint not found",
+            ));
+        };
+        if alt_setting != 0 {
+            intr.set_alt_setting(alt_setting)?;
+        }
+
+        Ok(Self {
+            usb_path_name: dev_info.path_name().clone(),
+            ctrl_index: ((intr_info.interface_number() as u16) << 8) | alt_setting as u16,
+            reader: addr_r.map(|r| intr.bulk_in_queue(r).into()),
+            writer: intr.bulk_out_queue(addr_w).into(),
+            ctrl: SyncControl::new(intr),
+            timeout,
+            ctrl_timeout: None,
+            _lock: lock,
+        })
+    }
+
+    /// Returns the printer interface if it is a USB printer class device, or the error
+    /// of reading interfaces of the device.
+    pub(crate) fn find_interface(dev_info: &DeviceInfo) -> io::Result<Option<InterfaceInfo>> {
+        Ok(dev_info
+            .try_interfaces()?
+            .find(|intr| {
+                intr.class() == USB_INTR_CLASS_PRINTER
+                    && intr.sub_class() == USB_INTR_SUBCLASS_PRINTER
+            })
+            .cloned())
+    }
+
+    /// Returns the path of the device in the usbfs file system.
+    pub fn name(&self) -> &str {
+        &self.usb_path_name
+    }
+
+    /// Returns true if the bulk IN endpoint is available for `Read`.
+    pub fn is_bidirectional(&self) -> bool {
+        self.reader.is_some()
+    }
+
+    /// Returns the timeout for standard `Read` and `Write` implementations.
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Sets timeout for standard `Read` and `Write` implementations to do USB bulk transfers.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Returns the timeout of control transfers.
+    pub fn control_timeout(&self) -> Duration {
+        self.ctrl_timeout.unwrap_or(self.timeout * 2)
+    }
+
+    /// Sets the timeout of control transfers independently of the `Read` and `Write`
+    /// timeout. `None` (by default) means twice the `Read` and `Write` timeout.
+    pub fn set_control_timeout(&mut self, timeout: Option<Duration>) {
+        self.ctrl_timeout = timeout;
+    }
+
+    /// Takes `nusb` transfer queues of the bulk IN endpoint (`None` if the printer is not
+    /// bidirectional) and the bulk OUT endpoint, for asynchronous operations.
+    pub fn into_queues(self) -> (Option<Queue<RequestBuffer>>, Queue<Vec<u8>>) {
+        (self.reader.map(Into::into), self.writer.into())
+    }
+
+    /// Reads the IEEE 1284 device ID string, e.g. `MFG:...;MDL:...;CMD:ESC/POS;`.
+    pub fn device_id(&self) -> io::Result<String> {
+        let mut buf = vec![0u8; 1024];
+        let len = self.control_get(GET_DEVICE_ID, 0, self.ctrl_index, &mut buf)?;
+        if len < 2 {
+            return Err(Error::new(ErrorKind::InvalidData, "device ID is too short"));
+        }
+        // the first two bytes are the big-endian length including themselves
+        let len_id = (u16::from_be_bytes([buf[0], buf[1]]) as usize).clamp(2, len);
+        Ok(String::from_utf8_lossy(&buf[2..len_id]).into_owned())
+    }
+
+    /// Reads the port status, which is compatible with a parallel port status register.
+    pub fn port_status(&self) -> io::Result<PortStatus> {
+        let mut buf = [0u8; 1];
+        let index = self.ctrl_index >> 8;
+        match self.control_get(GET_PORT_STATUS, 0, index, &mut buf)? {
+            1 => Ok(PortStatus(buf[0])),
+            _ => Err(Error::new(
+                ErrorKind::InvalidData,
+                "no port status returned",
+            )),
+        }
+    }
+
+    /// Flushes all buffers and resets the bulk endpoints to their initial states.
+    pub fn soft_reset(&self) -> io::Result<()> {
+        let index = self.ctrl_index >> 8;
+        self.ctrl
+            .class_out(SOFT_RESET, 0, index, &[], self.control_timeout())
+    }
+
+    fn control_get(
+        &self,
+        request: u8,
+        value: u16,
+        index: u16,
+        buf: &mut [u8],
+    ) -> io::Result<usize> {
+        self.ctrl
+            .class_in(request, value, index, buf, self.control_timeout())
+    }
+}
+
+#[cfg(feature = "blocking")]
+impl Read for UsbPrinter {
+    /// Returns `ErrorKind::Unsupported` if the printer is not bidirectional.
+    #[inline]
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Some(reader) = self.reader.as_mut() {
+            reader.read(buf, self.timeout)
+        } else {
+            Err(Error::new(
+                ErrorKind::Unsupported,
+                "the printer is not bidirectional",
+            ))
+        }
+    }
+}
+
+#[cfg(feature = "blocking")]
+impl Write for UsbPrinter {
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.write(buf, self.timeout)
+    }
+    /// Waits for the transfer left pending by a polling write (zero timeout), if any.
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.drain(self.timeout)
+    }
+}
+
+/// Printer port status returned by `UsbPrinter::port_status()`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct PortStatus(u8);
+
+impl PortStatus {
+    /// The raw status byte.
+    pub fn bits(&self) -> u8 {
+        self.0
+    }
+    /// Paper is empty.
+    pub fn paper_empty(&self) -> bool {
+        self.0 & 0x20 != 0
+    }
+    /// The printer is selected (online).
+    pub fn selected(&self) -> bool {
+        self.0 & 0x10 != 0
+    }
+    /// The printer has an error.
+    pub fn error(&self) -> bool {
+        self.0 & 0x08 == 0 // "Not Error" bit
+    }
+}
+
+impl std::fmt::Debug for PortStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PortStatus")
+            .field("paper_empty", &self.paper_empty())
+            .field("selected", &self.selected())
+            .field("error", &self.error())
+            .finish()
+    }
+}
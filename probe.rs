@@ -0,0 +1,228 @@
+use std::{
+    io::ErrorKind,
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+
+use crate::usb::{self, DeviceInfo};
+use crate::{modeswitch, CdcSerial, Error, SerialConfig, UsbPrinter, UsbSerial};
+
+const USB_CLASS_MASS_STORAGE: u8 = 0x08;
+const USB_CLASS_HUB: u8 = 0x09;
+
+/// Maximum time of waiting for the user's response in `open_first_matching()`.
+const PERMISSION_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Serial drivers known by this crate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DriverKind {
+    /// `CdcSerial`.
+    CdcAcm,
+    /// A driver registered by `register_driver()`, identified by its name.
+    Custom(&'static str),
+}
+
+/// An out-of-tree serial driver to be registered by `register_driver()`.
+#[derive(Clone, Copy, Debug)]
+pub struct CustomDriver {
+    /// Name of the driver, used in `DriverKind::Custom`.
+    pub name: &'static str,
+    /// Returns the amount of serial ports provided by the device, 0 if it's not supported.
+    pub probe: fn(&DeviceInfo) -> usize,
+    /// Connects to the first serial port of the device.
+    pub open: fn(&DeviceInfo, Duration) -> Result<Box<dyn UsbSerial>, Error>,
+}
+
+static CUSTOM_DRIVERS: Mutex<Vec<CustomDriver>> = Mutex::new(Vec::new());
+
+fn custom_drivers() -> MutexGuard<'static, Vec<CustomDriver>> {
+    CUSTOM_DRIVERS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Registers an out-of-tree serial driver. Registered drivers are checked before
+/// built-in drivers in `probe_all()` and `open_any()`; a driver registered again
+/// with the same name replaces the previous one.
+pub fn register_driver(driver: CustomDriver) {
+    let mut drivers = custom_drivers();
+    drivers.retain(|d| d.name != driver.name);
+    drivers.push(driver);
+}
+
+/// Connects to the first serial port of the device with the driver found by probing.
+/// Please get permission for the device before calling this function.
+/// - `timeout`: Set for standard `Read` and `Write` traits.
+pub fn open_any(dev_info: &DeviceInfo, timeout: Duration) -> Result<Box<dyn UsbSerial>, Error> {
+    match dev_info.likely_driver() {
+        Some(DriverKind::CdcAcm) => Ok(Box::new(CdcSerial::build(dev_info, timeout)?)),
+        Some(DriverKind::Custom(name)) => {
+            let driver = custom_drivers().iter().find(|d| d.name == name).copied();
+            let driver = driver.ok_or(Error::from(ErrorKind::NotFound))?;
+            (driver.open)(dev_info, timeout)
+        }
+        None => Err(Error::new(
+            ErrorKind::Unsupported,
+            "No driver for the device",
+        )),
+    }
+}
+
+type AliasFilter = Arc<dyn Fn(&DeviceInfo) -> bool + Send + Sync>;
+
+static ALIASES: Mutex<Vec<(String, AliasFilter)>> = Mutex::new(Vec::new());
+
+fn aliases() -> MutexGuard<'static, Vec<(String, AliasFilter)>> {
+    ALIASES.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Binds a human-readable alias (e.g. "printer", "scale") to a device filter,
+/// replacing the previous filter of the same alias.
+pub fn register_alias(alias: &str, filter: impl Fn(&DeviceInfo) -> bool + Send + Sync + 'static) {
+    let mut aliases = aliases();
+    aliases.retain(|(name, _)| name != alias);
+    aliases.push((alias.to_string(), Arc::new(filter)));
+}
+
+/// Removes the alias, returns false if it is not registered.
+pub fn unregister_alias(alias: &str) -> bool {
+    let mut aliases = aliases();
+    let len_old = aliases.len();
+    aliases.retain(|(name, _)| name != alias);
+    aliases.len() < len_old
+}
+
+/// Finds the first supported device matching the filter of the alias.
+pub fn find_alias(alias: &str) -> Result<ProbeResult, Error> {
+    let filter = aliases()
+        .iter()
+        .find(|(name, _)| name == alias)
+        .map(|(_, filter)| filter.clone())
+        .ok_or(Error::new(ErrorKind::InvalidInput, "Alias not registered"))?;
+    probe_all()?
+        .into_iter()
+        .find(|res| filter(&res.device))
+        .ok_or(Error::new(
+            ErrorKind::NotFound,
+            "No device found for the alias",
+        ))
+}
+
+/// Connects to the device resolved by `find_alias()` with `open_any()`.
+/// Please get permission for the device before calling this function.
+pub fn open_alias(alias: &str, timeout: Duration) -> Result<Box<dyn UsbSerial>, Error> {
+    open_any(&find_alias(alias)?.device, timeout)
+}
+
+/// Finds the first supported device accepted by `filter`, requests permission for it
+/// if needed, then connects to it with `open_any()` and applies the serial parameters.
+///
+/// Errors: `NotFound` if no device is found; `PermissionDenied` if the user denied it;
+/// `TimedOut` if the user didn't respond in 60 seconds.
+///
+/// Note: Don't call it in the `android_main()` thread, which must receive the result.
+pub fn open_first_matching(
+    filter: impl Fn(&DeviceInfo) -> bool,
+    config: &SerialConfig,
+    timeout: Duration,
+) -> Result<Box<dyn UsbSerial>, Error> {
+    let probed = probe_all()?
+        .into_iter()
+        .find(|res| filter(&res.device))
+        .ok_or(Error::new(ErrorKind::NotFound, "No matching device found"))?;
+    let dev_info = probed.device;
+    if let Some(req) = dev_info.request_permission()? {
+        if !req.wait_blocking(PERMISSION_TIMEOUT)? {
+            return Err(Error::from(ErrorKind::PermissionDenied));
+        }
+    }
+    let mut port = open_any(&dev_info, timeout)?;
+    port.configure(config)?;
+    Ok(port)
+}
+
+/// Result of probing a device, describing what will happen on connecting.
+#[derive(Clone, Debug)]
+pub struct ProbeResult {
+    /// The probed device.
+    pub device: DeviceInfo,
+    /// The driver which can handle the device.
+    pub driver: DriverKind,
+    /// Amount of serial ports provided by the device.
+    pub port_count: usize,
+    /// The permission should be requested before opening the device.
+    pub needs_permission: bool,
+}
+
+impl ProbeResult {
+    pub(crate) fn new(device: DeviceInfo, driver: DriverKind, port_count: usize) -> Self {
+        Self {
+            needs_permission: !device.has_permission().unwrap_or(false),
+            device,
+            driver,
+            port_count,
+        }
+    }
+}
+
+/// Probes for all serial devices supported by built-in and registered drivers.
+/// Returns an empty vector if no device is found.
+pub fn probe_all() -> Result<Vec<ProbeResult>, Error> {
+    Ok(usb::list_devices_cached()?
+        .into_iter()
+        .filter_map(probe_device)
+        .collect())
+}
+
+/// Checks if the device is supported by a built-in or registered driver.
+pub fn probe_device(device: DeviceInfo) -> Option<ProbeResult> {
+    let driver = device.likely_driver()?;
+    let port_count = match driver {
+        DriverKind::CdcAcm => CdcSerial::port_count(&device),
+        DriverKind::Custom(name) => custom_drivers()
+            .iter()
+            .find(|d| d.name == name)
+            .map(|d| (d.probe)(&device))
+            .unwrap_or(0),
+    };
+    Some(ProbeResult::new(device, driver, port_count))
+}
+
+/// Classification helpers based on class codes and the quirk table.
+impl DeviceInfo {
+    /// Returns true if the device has at least one CDC-ACM function.
+    pub fn is_cdc_acm(&self) -> bool {
+        CdcSerial::port_count(self) > 0
+    }
+
+    /// Returns true if the device has a USB printer class interface.
+    pub fn is_printer(&self) -> bool {
+        matches!(UsbPrinter::find_interface(self), Ok(Some(_)))
+    }
+
+    /// Returns true if the device is a USB hub.
+    pub fn is_hub(&self) -> bool {
+        self.class() == USB_CLASS_HUB
+    }
+
+    /// Returns true if the device has a mass storage interface.
+    pub fn is_mass_storage(&self) -> bool {
+        self.interfaces()
+            .any(|intr| intr.class() == USB_CLASS_MASS_STORAGE)
+    }
+
+    /// Returns true if the device is found in `modeswitch::KNOWN_SWITCHES`, which means
+    /// it should be switched before the serial functions become available.
+    pub fn needs_mode_switch(&self) -> bool {
+        modeswitch::find_switch(self).is_some()
+    }
+
+    /// Returns the serial driver which is able to handle the device.
+    /// Registered custom drivers are checked first.
+    pub fn likely_driver(&self) -> Option<DriverKind> {
+        let drivers = custom_drivers().clone();
+        if let Some(driver) = drivers.iter().find(|d| (d.probe)(self) > 0) {
+            return Some(DriverKind::Custom(driver.name));
+        }
+        self.is_cdc_acm().then_some(DriverKind::CdcAcm)
+    }
+}
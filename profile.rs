@@ -0,0 +1,249 @@
+//! Per-device serial configuration profiles, keyed by `DeviceKey` (vendor ID, product ID
+//! and serial number), with a pluggable storage backend.
+//!
+//! Profiles are stored as text, for example `1a86:55d3:5A2B0123=115200,N,8,1;N;11`,
+//! where the fields after the serial parameters are the flow control mode and DTR/RTS.
+
+use std::{
+    collections::HashMap,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use crate::usb::DeviceInfo;
+use crate::{open_any, Error, FlowControl, SerialConfig, UsbSerial};
+
+/// Stable key of a device, which doesn't depend on the bus or the connection order.
+/// Devices without a serial number of the same model share the same key.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct DeviceKey {
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub serial_number: Option<String>,
+}
+
+impl DeviceInfo {
+    /// Returns the stable key for profile storage.
+    pub fn key(&self) -> DeviceKey {
+        DeviceKey {
+            vendor_id: self.vendor_id(),
+            product_id: self.product_id(),
+            serial_number: self.serial_number().clone(),
+        }
+    }
+}
+
+impl std::fmt::Display for DeviceKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:04x}:{:04x}", self.vendor_id, self.product_id)?;
+        if let Some(serial) = self.serial_number.as_ref() {
+            write!(f, ":{serial}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for DeviceKey {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bad_par = || Error::new(ErrorKind::InvalidInput, s);
+        let mut strs = s.splitn(3, ':');
+        let vendor_id = strs.next().ok_or_else(bad_par)?;
+        let vendor_id = u16::from_str_radix(vendor_id.trim(), 16).map_err(|_| bad_par())?;
+        let product_id = strs.next().ok_or_else(bad_par)?;
+        let product_id = u16::from_str_radix(product_id.trim(), 16).map_err(|_| bad_par())?;
+        Ok(Self {
+            vendor_id,
+            product_id,
+            serial_number: strs.next().map(|s| s.to_string()),
+        })
+    }
+}
+
+/// Serial parameters, flow control mode and DTR/RTS states remembered for a device.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SerialProfile {
+    pub config: SerialConfig,
+    pub flow_control: FlowControl,
+    pub dtr_rts: (bool, bool),
+}
+
+impl Default for SerialProfile {
+    fn default() -> Self {
+        Self {
+            config: SerialConfig::default(),
+            flow_control: FlowControl::None,
+            dtr_rts: (false, false),
+        }
+    }
+}
+
+impl SerialProfile {
+    /// Applies the profile to the opened serial handler. Errors of unsupported
+    /// DTR/RTS or flow control operations are ignored.
+    pub fn apply(&self, port: &mut dyn UsbSerial) -> Result<(), Error> {
+        port.configure(&self.config)?;
+        let (dtr, rts) = self.dtr_rts;
+        match port.set_dtr_rts(dtr, rts) {
+            Err(e) if e.kind() != ErrorKind::Unsupported => return Err(e),
+            _ => (),
+        }
+        #[cfg(feature = "serialport")]
+        if self.flow_control != FlowControl::None {
+            let _ = port.set_flow_control(self.flow_control);
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for SerialProfile {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bad_par = || Error::new(ErrorKind::InvalidInput, s);
+        let mut strs = s.split(';');
+        let config = strs.next().ok_or_else(bad_par)?.parse()?;
+        let flow_control = match strs.next().map(|s| s.trim()) {
+            Some("N") | None => FlowControl::None,
+            Some("S") => FlowControl::Software,
+            Some("H") => FlowControl::Hardware,
+            _ => return Err(bad_par()),
+        };
+        let dtr_rts = match strs.next().map(|s| s.trim()) {
+            Some("00") | None => (false, false),
+            Some("01") => (false, true),
+            Some("10") => (true, false),
+            Some("11") => (true, true),
+            _ => return Err(bad_par()),
+        };
+        Ok(Self {
+            config,
+            flow_control,
+            dtr_rts,
+        })
+    }
+}
+
+impl std::fmt::Display for SerialProfile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let flow_control = match self.flow_control {
+            FlowControl::None => 'N',
+            FlowControl::Software => 'S',
+            FlowControl::Hardware => 'H',
+        };
+        let (dtr, rts) = (self.dtr_rts.0 as u8, self.dtr_rts.1 as u8);
+        write!(f, "{};{flow_control};{dtr}{rts}", self.config)
+    }
+}
+
+/// Storage backend of `ProfileStore`.
+pub trait ProfileStorage: Send {
+    /// Loads the profile of the device, returns `Ok(None)` if it is not stored.
+    fn load(&mut self, key: &DeviceKey) -> Result<Option<SerialProfile>, Error>;
+    /// Stores the profile of the device, replacing the old one.
+    fn save(&mut self, key: &DeviceKey, profile: &SerialProfile) -> Result<(), Error>;
+}
+
+/// Keeps profiles in memory.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryStorage {
+    profiles: HashMap<DeviceKey, SerialProfile>,
+}
+
+impl ProfileStorage for MemoryStorage {
+    fn load(&mut self, key: &DeviceKey) -> Result<Option<SerialProfile>, Error> {
+        Ok(self.profiles.get(key).copied())
+    }
+    fn save(&mut self, key: &DeviceKey, profile: &SerialProfile) -> Result<(), Error> {
+        self.profiles.insert(key.clone(), *profile);
+        Ok(())
+    }
+}
+
+/// Keeps profiles in a text file with a `key=profile` line for each device, which
+/// can be placed in the app's internal storage directory (`Context.getFilesDir()`).
+#[derive(Clone, Debug)]
+pub struct FileStorage {
+    path: PathBuf,
+}
+
+impl FileStorage {
+    /// The file is created on the first `save()` if it doesn't exist.
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    fn read_all(&self) -> Result<Vec<(DeviceKey, SerialProfile)>, Error> {
+        let text = match std::fs::read_to_string(&self.path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let mut profiles = Vec::new();
+        for line in text.lines().filter(|l| !l.trim().is_empty()) {
+            // the serial number may contain '=', but the profile doesn't
+            let (key, profile) = line
+                .rsplit_once('=')
+                .ok_or(Error::new(ErrorKind::InvalidData, line))?;
+            profiles.push((key.parse()?, profile.parse()?));
+        }
+        Ok(profiles)
+    }
+}
+
+impl ProfileStorage for FileStorage {
+    fn load(&mut self, key: &DeviceKey) -> Result<Option<SerialProfile>, Error> {
+        let profiles = self.read_all()?;
+        Ok(profiles.into_iter().find(|(k, _)| k == key).map(|(_, p)| p))
+    }
+    fn save(&mut self, key: &DeviceKey, profile: &SerialProfile) -> Result<(), Error> {
+        let mut profiles = self.read_all()?;
+        profiles.retain(|(k, _)| k != key);
+        profiles.push((key.clone(), *profile));
+        let text: String = profiles.iter().map(|(k, p)| format!("{k}={p}\n")).collect();
+        std::fs::write(&self.path, text)
+    }
+}
+
+/// Remembers serial profiles of devices.
+#[derive(Debug, Default)]
+pub struct ProfileStore<S: ProfileStorage = MemoryStorage> {
+    storage: S,
+}
+
+impl<S: ProfileStorage> ProfileStore<S> {
+    /// Creates the store with the given backend.
+    pub fn new(storage: S) -> Self {
+        Self { storage }
+    }
+
+    /// Gets the stored profile of the device.
+    pub fn get(&mut self, dev_info: &DeviceInfo) -> Result<Option<SerialProfile>, Error> {
+        self.storage.load(&dev_info.key())
+    }
+
+    /// Remembers the profile of the device.
+    pub fn set(&mut self, dev_info: &DeviceInfo, profile: &SerialProfile) -> Result<(), Error> {
+        self.storage.save(&dev_info.key(), profile)
+    }
+
+    /// Connects to the device with `open_any()`, then applies the stored profile
+    /// (or `default` if it is not found).
+    /// Please get permission for the device before calling this function.
+    pub fn open(
+        &mut self,
+        dev_info: &DeviceInfo,
+        default: SerialProfile,
+        timeout: Duration,
+    ) -> Result<Box<dyn UsbSerial>, Error> {
+        let profile = self.get(dev_info)?.unwrap_or(default);
+        let mut port = open_any(dev_info, timeout)?;
+        profile.apply(port.as_mut())?;
+        Ok(port)
+    }
+}
@@ -1,70 +1,342 @@
 use std::{
-    io::{self, Error, ErrorKind, Read, Write},
-    time::Duration,
+    io::{self, Error, ErrorKind},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
 };
 
-use crate::SerialConfig;
+#[cfg(feature = "blocking")]
+use crate::usb::{CancelHandle, SyncInterruptReader, SyncReader, SyncWriter};
 use crate::{
-    usb::{self, DeviceInfo, InterfaceInfo, SyncReader, SyncWriter},
-    UsbSerial,
+    usb::{
+        self, BulkReader, BulkWriter, DeviceInfo, InterfaceInfo, JavaConnection, JavaReader,
+        JavaRequestQueue, JavaWriter, PortLock, RetryPolicy,
+    },
+    DriverKind, PortCapabilities, ProbeResult, UsbSerial,
 };
-use nusb::transfer::{Control, ControlType, Direction, Queue, Recipient, RequestBuffer};
+use crate::{DataBits, Parity, SerialConfig, StopBits};
+use jni_min_helper::jni::objects::{GlobalRef, JObject};
+use nusb::transfer::{Control, Direction, EndpointType, Queue, RequestBuffer, TransferError};
+#[cfg(feature = "blocking")]
+use std::io::{Read, Write};
 
-use serialport::{DataBits, Parity, SerialPort, StopBits};
+#[cfg(feature = "serialport")]
+use serialport::SerialPort;
 
 const USB_INTR_CLASS_COMM: u8 = 0x02;
 const USB_INTR_SUBCLASS_ACM: u8 = 0x02;
 const USB_INTR_CLASS_CDC_DATA: u8 = 0x0A;
 
+const SEND_ENCAPSULATED_COMMAND: u8 = 0x00;
+const GET_ENCAPSULATED_RESPONSE: u8 = 0x01;
+const SET_COMM_FEATURE: u8 = 0x02;
+const GET_COMM_FEATURE: u8 = 0x03;
+const CLEAR_COMM_FEATURE: u8 = 0x04;
 const SET_LINE_CODING: u8 = 0x20;
+const GET_LINE_CODING: u8 = 0x21;
 const SET_CONTROL_LINE_STATE: u8 = 0x22;
 const SEND_BREAK: u8 = 0x23;
 
+const NETWORK_CONNECTION: u8 = 0x00;
+const RESPONSE_AVAILABLE: u8 = 0x01;
+const SERIAL_STATE: u8 = 0x20;
+const CONNECTION_SPEED_CHANGE: u8 = 0x2A;
+
+const CS_INTERFACE: u8 = 0x24;
+const ACM_FUNCTIONAL_DESCRIPTOR: u8 = 0x02;
+const ACM_CAP_COMM_FEATURE: u8 = 0x01;
+const ACM_CAP_LINE_CODING: u8 = 0x02;
+const ACM_CAP_SEND_BREAK: u8 = 0x04;
+
+#[cfg(feature = "blocking")]
+const STAGING_SIZE: usize = 4096; // transfer size of `wait_readable()`
+
 /// This is currently a thin wrapper of USB operations, it requires hardware buffers
 /// at the device side. It uses the CDC ACM Data Interface Class to transfer data
 /// (the Communication Interface Class is used for probing and serial configuration).
 ///
+/// Composite devices are supported if the UART function is a CDC-ACM interface pair,
+/// for example, Microchip MCP2221(A) exposes its UART in this way, besides a HID
+/// interface for I2C and GPIO functions.
+///
+/// It is `Send` (required by `serialport::SerialPort`), so it can be moved to a worker
+/// thread. Reading and writing require `&mut self`; to do them in different threads,
+/// take the queues by `into_queues()` and wrap them with `SyncReader` and `SyncWriter`.
+///
 /// Reference: *USB Class Definitions for Communication Devices, Version 1.1*,
 /// especially section 3.6.2.1, 5.2.3.2 and 6.2(.13).
+#[cfg_attr(not(feature = "blocking"), allow(dead_code))] // fields for `Read` and `Write`
 pub struct CdcSerial {
-    usb_path_name: String,      // the name from `android.hardware.usb.UsbDevice`
-    ctrl_index: u16,            // communication interface id as the control transfer index
-    intr_comm: nusb::Interface, // communication interface keeper
-    reader: SyncReader,         // for the bulk IN endpoint of data interface
-    writer: SyncWriter,         // for the bulk OUT endpoint of data interface
+    dev_info: DeviceInfo,    // for checking the permission on transfer failures
+    usb_path_name: String,   // the name from `android.hardware.usb.UsbDevice`
+    ctrl_index: u16,         // communication interface id as the control transfer index
+    acm_capabilities: u8,    // `bmCapabilities` of the ACM functional descriptor
+    notify_addr: Option<u8>, // interrupt IN endpoint of the communication interface
+    ctrl: ControlChannel,    // communication interface keeper
+    data: DataChannel,       // for the bulk endpoints of data interface
 
     timeout: Duration,              // standard `Read` and `Write` timeout
+    ctrl_timeout: Option<Duration>, // control transfer timeout, `timeout * 2` if `None`
+    retry: RetryPolicy,             // for transient failures of control transfers
     ser_conf: Option<SerialConfig>, // keeps the latest settings
     dtr_rts: (bool, bool),          // keeps the latest settings, (false, false) by default
+
+    half_duplex: Option<HalfDuplex>, // turnaround delays, disabled by default
+    last_tx: Option<Instant>,        // end of the latest write
+    last_rx: Option<Instant>,        // end of the latest read with data
+
+    rx_activity: Arc<Mutex<Instant>>, // latest data arrival or opening, for idle monitors
+    _lock: PortLock,                  // released on drop or `into_queues()`
+}
+
+/// Transfer backend of `CdcSerial`, selected by `CdcSerial::build_with_backend()`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Backend {
+    /// `nusb` transfer queues on the file descriptor of the connection.
+    #[default]
+    Nusb,
+    /// Blocking `UsbDeviceConnection.bulkTransfer()` and `controlTransfer()` calls
+    /// through JNI, for devices or ROMs on which `nusb` misbehaves. It is slower.
+    ///
+    /// Note: `into_queues()`, `cancel_handle()`, notifications, `wait_readable()`,
+    /// `read_to_end_deadline()`, halt clearing and stall counting are unsupported.
+    JavaBulkTransfer,
+    /// Queued `UsbRequest`s reaped by `UsbDeviceConnection.requestWait()` through JNI,
+    /// which requires Android 8.0 (API 26). Unlike `JavaBulkTransfer`, IN requests are
+    /// kept queued between reads (nothing is lost on read timeouts), and writes return
+    /// once the data is queued; `drain()` waits for queued writes. Control transfers
+    /// and limitations are the same as `JavaBulkTransfer`.
+    JavaUsbRequest,
+}
+
+/// Control transfer channel of the communication interface.
+#[derive(Clone)]
+enum ControlChannel {
+    Nusb(nusb::Interface),
+    Java(Arc<JavaConnection>),
+}
+
+impl ControlChannel {
+    fn control_in(
+        &self,
+        control: Control,
+        buf: &mut [u8],
+        timeout: Duration,
+    ) -> Result<usize, TransferError> {
+        match self {
+            Self::Nusb(intr) => intr.control_in_blocking(control, buf, timeout),
+            Self::Java(conn) => conn.control_in(control, buf, timeout),
+        }
+    }
+
+    fn control_out(
+        &self,
+        control: Control,
+        data: &[u8],
+        timeout: Duration,
+    ) -> Result<usize, TransferError> {
+        match self {
+            Self::Nusb(intr) => intr.control_out_blocking(control, data, timeout),
+            Self::Java(conn) => conn.control_out(control, data, timeout),
+        }
+    }
+}
+
+/// Transfer channel of the bulk endpoints.
+#[cfg_attr(not(feature = "blocking"), allow(dead_code))] // fields for `Read` and `Write`
+enum DataChannel {
+    Nusb {
+        reader: BulkReader,
+        writer: BulkWriter,
+    },
+    Java {
+        conn: Arc<JavaConnection>,
+        endp_r: GlobalRef, // `android.hardware.usb.UsbEndpoint` of bulk IN
+        endp_w: GlobalRef, // `android.hardware.usb.UsbEndpoint` of bulk OUT
+    },
+    JavaQueued {
+        reader: JavaReader,
+        writer: JavaWriter,
+    },
+}
+
+impl DataChannel {
+    /// Wraps the bulk queues, sharing the `CancelHandle` of the reader with the writer.
+    #[cfg(feature = "blocking")]
+    fn nusb(reader: Queue<RequestBuffer>, writer: Queue<Vec<u8>>) -> Self {
+        let (reader, mut writer) = (SyncReader::new(reader), SyncWriter::new(writer));
+        writer.set_cancel_handle(reader.cancel_handle());
+        Self::Nusb { reader, writer }
+    }
+
+    /// Keeps the bulk queues for `into_queues()`.
+    #[cfg(not(feature = "blocking"))]
+    fn nusb(reader: Queue<RequestBuffer>, writer: Queue<Vec<u8>>) -> Self {
+        Self::Nusb { reader, writer }
+    }
+}
+
+/// Turnaround delays of the half-duplex mode, for shared-bus protocols (e.g. RS-485
+/// through simple TTL adapters with automatic direction control).
+///
+/// Note: A write completes when the adapter receives the data, not when the adapter
+/// finishes transmitting it, so `tx_to_rx` should include the UART transmission time
+/// of the adapter's buffer if the device replies quickly.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct HalfDuplex {
+    /// Quiet period between the end of a write and the start of the next read.
+    pub tx_to_rx: Duration,
+    /// Quiet period between the end of a read and the start of the next write.
+    pub rx_to_tx: Duration,
 }
 
 impl CdcSerial {
     /// Probes for CDC-ACM devices. It checks the current configuration of each device.
     /// Returns an empty vector if no device is found.
-    pub fn probe() -> io::Result<Vec<DeviceInfo>> {
-        let devs = usb::list_devices()?;
+    pub fn probe() -> io::Result<Vec<ProbeResult>> {
+        let devs = usb::list_devices_cached()?;
         Ok(devs
             .into_iter()
-            .filter(|dev| Self::find_interfaces(dev).is_some())
+            .filter_map(|dev| {
+                let port_count = Self::port_count(&dev);
+                (port_count > 0).then(|| ProbeResult::new(dev, DriverKind::CdcAcm, port_count))
+            })
             .collect())
     }
 
-    /// Connects to the CDC-ACM device, returns the `CdcSerial` handler.
+    /// Returns the amount of CDC-ACM functions (serial ports) of the device.
+    pub fn port_count(dev_info: &DeviceInfo) -> usize {
+        Self::find_interface_pairs(dev_info.interfaces()).len()
+    }
+
+    /// Connects to the CDC-ACM device, returns the `CdcSerial` handler of the first port.
     /// Please get permission for the device before calling this function.
+    /// Returns `AlreadyExists` error if the port is opened by another handler in this process.
     /// - `timeout`: Set for standard `Read` and `Write` traits.
     pub fn build(dev_info: &DeviceInfo, timeout: Duration) -> io::Result<Self> {
-        let (intr_comm, intr_data) = Self::find_interfaces(dev_info)
-            .ok_or(Error::new(ErrorKind::InvalidInput, "Not a CDC-ACM device"))?;
-        let ctrl_index = intr_comm.interface_number() as u16;
+        if Self::find_ports(dev_info)?.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidInput, "Not a CDC-ACM device"));
+        }
+        let device = dev_info.open_device()?;
+        Self::build_port(&device, dev_info, 0, timeout)
+    }
+
+    /// Requests the permission if it is not granted and waits for the user's response,
+    /// then connects to the first port and applies `conf`. Returns `NotConnected` error
+    /// if the device is not connected, `PermissionDenied` error if the user denies it.
+    /// - `io_timeout`: Set for standard `Read` and `Write` traits.
+    ///
+    /// Note: Waiting for the response is not limited here; drop the future to give up
+    /// (e.g. by a timeout combinator of the executor), then the receiver is unregistered.
+    #[cfg(feature = "async")]
+    pub async fn open_with_permission(
+        dev_info: &DeviceInfo,
+        conf: SerialConfig,
+        io_timeout: Duration,
+    ) -> io::Result<Self> {
+        if Self::find_ports(dev_info)?.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidInput, "Not a CDC-ACM device"));
+        }
+        let device = dev_info.request_and_open_async().await?;
+        let mut serial = Self::build_port(&device, dev_info, 0, io_timeout)?;
+        serial.set_config(conf)?;
+        Ok(serial)
+    }
 
+    /// Connects to all CDC-ACM functions of a composite device with a single `openDevice()`
+    /// call, returns `CdcSerial` handlers in the order of their interface numbers.
+    /// Please get permission for the device before calling this function.
+    pub fn build_all(dev_info: &DeviceInfo, timeout: Duration) -> io::Result<Vec<Self>> {
+        let num_ports = Self::find_ports(dev_info)?.len();
+        if num_ports == 0 {
+            return Err(Error::new(ErrorKind::InvalidInput, "Not a CDC-ACM device"));
+        }
         let device = dev_info.open_device()?;
+        (0..num_ports)
+            .map(|port| Self::build_port(&device, dev_info, port, timeout))
+            .collect()
+    }
+
+    /// Selects the configuration of given `bConfigurationValue` and connects to the first
+    /// CDC-ACM function in it. Please get permission for the device before calling this.
+    pub fn build_with_configuration(
+        dev_info: &DeviceInfo,
+        configuration: u8,
+        timeout: Duration,
+    ) -> io::Result<Self> {
+        let (intr_comm, intr_data) = dev_info
+            .try_configurations()?
+            .find(|conf| conf.id() == configuration)
+            .and_then(|conf| {
+                Self::find_interface_pairs(conf.interfaces())
+                    .into_iter()
+                    .next()
+            })
+            .ok_or(Error::new(
+                ErrorKind::InvalidInput,
+                "Not a CDC-ACM configuration",
+            ))?;
+        let device = dev_info.open_device_with_configuration(configuration)?;
+        Self::build_pair(&device, dev_info, &intr_comm, &intr_data, timeout)
+    }
+
+    /// Connects to the CDC-ACM function of index `port` through a device opened by
+    /// `DeviceInfo::open_device()`. This allows opening multiple ports of a composite
+    /// device at different moments, because Android grants only one connection context.
+    pub fn build_port(
+        device: &nusb::Device,
+        dev_info: &DeviceInfo,
+        port: usize,
+        timeout: Duration,
+    ) -> io::Result<Self> {
+        let ports = Self::find_ports(dev_info)?;
+        let (intr_comm, intr_data) = ports.get(port).ok_or(Error::new(
+            ErrorKind::InvalidInput,
+            "CDC-ACM port not found",
+        ))?;
+        Self::build_pair(device, dev_info, intr_comm, intr_data, timeout)
+    }
+
+    fn build_pair(
+        device: &nusb::Device,
+        dev_info: &DeviceInfo,
+        intr_comm: &InterfaceInfo,
+        intr_data: &InterfaceInfo,
+        timeout: Duration,
+    ) -> io::Result<Self> {
+        let ctrl_index = intr_comm.interface_number() as u16;
+
+        let lock = PortLock::acquire_as(dev_info, intr_comm.interface_number(), "CdcSerial")?;
         let intr_comm = device.detach_and_claim_interface(intr_comm.interface_number())?;
-        let intr_data = device.detach_and_claim_interface(intr_data.interface_number())?;
+        let acm_capabilities = intr_comm
+            .descriptors()
+            .flat_map(|alt| alt.descriptors())
+            .find(|desc| {
+                desc.descriptor_type() == CS_INTERFACE
+                    && desc.len() >= 4
+                    && desc[2] == ACM_FUNCTIONAL_DESCRIPTOR
+            })
+            .map(|desc| desc[3])
+            .unwrap_or(0);
+        let notify_addr = intr_comm
+            .descriptors()
+            .flat_map(|alt| alt.endpoints().collect::<Vec<_>>())
+            .find(|endp| {
+                endp.transfer_type() == EndpointType::Interrupt && endp.direction() == Direction::In
+            })
+            .map(|endp| endp.address());
+        let intr_data = if intr_data.interface_number() as u16 == ctrl_index {
+            intr_comm.clone() // single combined interface
+        } else {
+            device.detach_and_claim_interface(intr_data.interface_number())?
+        };
 
         // Note: It doesn't select a setting with the highest bandwidth.
         let (mut addr_r, mut addr_w) = (None, None);
         for alt in intr_data.descriptors() {
-            let endps: Vec<_> = alt.endpoints().collect();
+            let endps: Vec<_> = alt
+                .endpoints()
+                .filter(|endp| endp.transfer_type() == EndpointType::Bulk)
+                .collect();
             let endp_r = endps.iter().find(|endp| endp.direction() == Direction::In);
             let endp_w = endps.iter().find(|endp| endp.direction() == Direction::Out);
             if endp_r.is_some() && endp_w.is_some() {
@@ -73,41 +345,221 @@ impl CdcSerial {
                 break;
             }
         }
-        let (reader, writer) = if let (Some(r), Some(w)) = (addr_r, addr_w) {
-            (
-                SyncReader::new(intr_data.bulk_in_queue(r)),
-                SyncWriter::new(intr_data.bulk_out_queue(w)),
-            )
+        let data = if let (Some(r), Some(w)) = (addr_r, addr_w) {
+            DataChannel::nusb(intr_data.bulk_in_queue(r), intr_data.bulk_out_queue(w))
         } else {
             return Err(Error::new(ErrorKind::NotFound, "Data endpoints not found"));
         };
 
-        Ok(Self {
+        Ok(Self::from_parts(
+            dev_info,
+            ctrl_index,
+            acm_capabilities,
+            notify_addr,
+            ControlChannel::Nusb(intr_comm),
+            data,
+            timeout,
+            lock,
+        ))
+    }
+
+    /// Connects to the CDC-ACM function of index `port` with the given backend.
+    /// Please get permission for the device before calling this function.
+    pub fn build_with_backend(
+        dev_info: &DeviceInfo,
+        port: usize,
+        timeout: Duration,
+        backend: Backend,
+    ) -> io::Result<Self> {
+        if backend == Backend::Nusb {
+            let device = dev_info.open_device()?;
+            return Self::build_port(&device, dev_info, port, timeout);
+        }
+        let ports = Self::find_ports(dev_info)?;
+        let (intr_comm, intr_data) = ports.get(port).ok_or(Error::new(
+            ErrorKind::InvalidInput,
+            "CDC-ACM port not found",
+        ))?;
+        let (Some(endp_r), Some(endp_w)) = (
+            intr_data.find_endpoint(Direction::In, EndpointType::Bulk),
+            intr_data.find_endpoint(Direction::Out, EndpointType::Bulk),
+        ) else {
+            return Err(Error::new(ErrorKind::NotFound, "Data endpoints not found"));
+        };
+
+        let lock = PortLock::acquire_as(dev_info, intr_comm.interface_number(), "CdcSerial")?;
+        let conn = Arc::new(JavaConnection::open(dev_info)?);
+        let acm_capabilities = conn
+            .raw_descriptors()
+            .map(|raw| acm_capabilities_from_raw(&raw, intr_comm.interface_number()))
+            .unwrap_or(0);
+        let java_comm = conn.claim_interface(intr_comm.interface_number())?;
+        let java_data = if intr_data.interface_number() == intr_comm.interface_number() {
+            java_comm
+        } else {
+            conn.claim_interface(intr_data.interface_number())?
+        };
+        let (endp_r, endp_w) = (
+            conn.endpoint(&java_data, endp_r.address())?,
+            conn.endpoint(&java_data, endp_w.address())?,
+        );
+        let data = if backend == Backend::JavaUsbRequest {
+            DataChannel::JavaQueued {
+                reader: JavaReader::new(JavaRequestQueue::new(conn.clone(), endp_r)?),
+                writer: JavaWriter::new(JavaRequestQueue::new(conn.clone(), endp_w)?),
+            }
+        } else {
+            DataChannel::Java {
+                endp_r,
+                endp_w,
+                conn: conn.clone(),
+            }
+        };
+        Ok(Self::from_parts(
+            dev_info,
+            intr_comm.interface_number() as u16,
+            acm_capabilities,
+            None, // notifications are unsupported
+            ControlChannel::Java(conn),
+            data,
+            timeout,
+            lock,
+        ))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn from_parts(
+        dev_info: &DeviceInfo,
+        ctrl_index: u16,
+        acm_capabilities: u8,
+        notify_addr: Option<u8>,
+        ctrl: ControlChannel,
+        data: DataChannel,
+        timeout: Duration,
+        lock: PortLock,
+    ) -> Self {
+        Self {
+            dev_info: dev_info.clone(),
             usb_path_name: dev_info.path_name().clone(),
             ctrl_index,
-            intr_comm,
-            reader,
-            writer,
+            acm_capabilities,
+            notify_addr,
+            ctrl,
+            data,
             timeout,
+            ctrl_timeout: None,
+            retry: RetryPolicy::default(),
             ser_conf: None,
             dtr_rts: (false, false),
-        })
+            half_duplex: None,
+            last_tx: None,
+            last_rx: None,
+            rx_activity: Arc::new(Mutex::new(Instant::now())),
+            _lock: lock,
+        }
     }
 
-    /// Returns (intr_comm, intr_data) if it is a CDC-ACM device.
-    fn find_interfaces(dev_info: &DeviceInfo) -> Option<(InterfaceInfo, InterfaceInfo)> {
-        let (comm, data) = (
-            dev_info.interfaces().find(|intr| {
-                intr.class() == USB_INTR_CLASS_COMM && intr.sub_class() == USB_INTR_SUBCLASS_ACM
-            }),
-            dev_info
-                .interfaces()
-                .find(|intr| intr.class() == USB_INTR_CLASS_CDC_DATA),
-        );
-        if let (Some(comm), Some(data)) = (comm, data) {
-            Some((*comm, *data))
+    /// Returns (intr_comm, intr_data) pairs of CDC-ACM functions, or the error of reading
+    /// interfaces of the device.
+    fn find_ports(dev_info: &DeviceInfo) -> io::Result<Vec<(InterfaceInfo, InterfaceInfo)>> {
+        Ok(Self::find_interface_pairs(dev_info.try_interfaces()?))
+    }
+
+    /// Returns (intr_comm, intr_data) pairs of CDC-ACM functions of the device.
+    ///
+    /// The data interface following the communication interface is preferred, which is
+    /// the common layout of composite devices. Some non-compliant devices put the
+    /// notification endpoint and the data endpoints on a single communication interface,
+    /// in this case `intr_data` equals `intr_comm`. Otherwise the communication interface
+    /// takes the first data interface which doesn't follow another communication interface.
+    fn find_interface_pairs<'a>(
+        interfaces: impl Iterator<Item = &'a InterfaceInfo>,
+    ) -> Vec<(InterfaceInfo, InterfaceInfo)> {
+        // `UsbDevice` lists each alternate setting as an individual interface
+        let mut comms: Vec<&InterfaceInfo> = Vec::new();
+        let mut datas: Vec<&InterfaceInfo> = Vec::new();
+        for intr in interfaces {
+            let (list, is_match) = if intr.class() == USB_INTR_CLASS_COMM {
+                (&mut comms, intr.sub_class() == USB_INTR_SUBCLASS_ACM)
+            } else {
+                (&mut datas, intr.class() == USB_INTR_CLASS_CDC_DATA)
+            };
+            if is_match
+                && !list
+                    .iter()
+                    .any(|i| i.interface_number() == intr.interface_number())
+            {
+                list.push(intr);
+            }
+        }
+        comms.sort_by_key(|intr| intr.interface_number());
+        datas.sort_by_key(|intr| intr.interface_number());
+
+        // take the following data interfaces first, so they are not taken by the fallback
+        let following: Vec<Option<&InterfaceInfo>> = comms
+            .iter()
+            .map(|comm| {
+                let next_num = comm.interface_number().wrapping_add(1);
+                let i_data = datas
+                    .iter()
+                    .position(|data| data.interface_number() == next_num)?;
+                Some(datas.remove(i_data))
+            })
+            .collect();
+
+        let mut pairs = Vec::new();
+        for (comm, data) in comms.into_iter().zip(following) {
+            let data = match data {
+                Some(data) => data,
+                None if Self::has_bulk_endpoints(comm) => comm,
+                None if !datas.is_empty() => datas.remove(0),
+                None => continue,
+            };
+            pairs.push((comm.clone(), data.clone()));
+        }
+        pairs
+    }
+
+    /// Checks if the interface has both bulk IN and bulk OUT endpoints.
+    fn has_bulk_endpoints(intr: &InterfaceInfo) -> bool {
+        let has_bulk = |dir| intr.find_endpoint(dir, EndpointType::Bulk).is_some();
+        has_bulk(Direction::In) && has_bulk(Direction::Out)
+    }
+
+    /// Returns the information of the connected device.
+    pub fn device_info(&self) -> &DeviceInfo {
+        &self.dev_info
+    }
+
+    /// Returns the `android.hardware.usb.UsbDeviceConnection` object if the port is opened
+    /// with a Java backend, for issuing requests by `usb::control_transfer()`.
+    pub fn java_connection(&self) -> Option<&JObject<'static>> {
+        match &self.ctrl {
+            ControlChannel::Java(conn) => Some(conn.java_connection()),
+            ControlChannel::Nusb(_) => None,
+        }
+    }
+
+    /// Checks if the USB permission is still granted, returns `PermissionDenied` error
+    /// if it is revoked. Check `DeviceInfo::monitor_permission()` for a background check.
+    pub fn check_permission(&self) -> io::Result<()> {
+        if self.dev_info.has_permission()? {
+            Ok(())
         } else {
-            None
+            Err(Error::new(
+                ErrorKind::PermissionDenied,
+                "USB permission revoked",
+            ))
+        }
+    }
+
+    #[cfg(feature = "blocking")]
+    /// Replaces unexpected transfer errors by `PermissionDenied` error if the permission
+    /// has been revoked.
+    fn map_revoked(&self, err: Error) -> Error {
+        match err.kind() {
+            ErrorKind::TimedOut | ErrorKind::Interrupted => err,
+            _ => self.check_permission().err().unwrap_or(err),
         }
     }
 
@@ -119,8 +571,170 @@ impl CdcSerial {
         Ok(())
     }
 
+    /// Sends the 7-byte `SET_LINE_CODING` payload as-is, for non-compliant devices expecting
+    /// vendor-specific values. Fields with standard values are decoded to update `config()`,
+    /// other fields keep their previous values (or the default ones).
+    pub fn set_line_coding_raw(&mut self, line_coding: [u8; 7]) -> io::Result<()> {
+        self.control_set(SET_LINE_CODING, 0, &line_coding)?;
+        let mut conf = self.ser_conf.unwrap_or_default();
+        conf.update_from_line_coding(&line_coding);
+        self.ser_conf.replace(conf);
+        Ok(())
+    }
+
+    #[cfg(feature = "blocking")]
+    /// Opens the notification endpoint (interrupt IN) of the communication interface.
+    /// Returns `ErrorKind::NotFound` if the device doesn't have it.
+    ///
+    /// Note: Only one reader should be opened for the port at a time.
+    pub fn notifications(&self) -> io::Result<NotificationReader> {
+        let ControlChannel::Nusb(intr_comm) = &self.ctrl else {
+            return Err(java_unsupported());
+        };
+        let addr = self.notify_addr.ok_or(Error::new(
+            ErrorKind::NotFound,
+            "Notification endpoint not found",
+        ))?;
+        // a notification may be split into multiple packets of small endpoints
+        let packet_size = intr_comm
+            .descriptors()
+            .flat_map(|alt| alt.endpoints().collect::<Vec<_>>())
+            .find(|endp| endp.address() == addr)
+            .map_or(64, |endp| endp.max_packet_size().max(1));
+        let max_len = 64usize.div_ceil(packet_size) * packet_size;
+        Ok(NotificationReader {
+            reader: SyncInterruptReader::new(intr_comm.interrupt_in_queue(addr), max_len),
+        })
+    }
+
+    /// Sends an encapsulated command (e.g. an AT command) through the communication interface,
+    /// for modems which don't route commands through the data endpoints.
+    pub fn send_encapsulated_command(&self, command: &[u8]) -> io::Result<()> {
+        self.control_set(SEND_ENCAPSULATED_COMMAND, 0, command)
+    }
+
+    /// Gets the encapsulated response into `buf`, returns the length of the response.
+    /// It should be called after receiving `CdcNotification::ResponseAvailable`.
+    pub fn encapsulated_response(&self, buf: &mut [u8]) -> io::Result<usize> {
+        self.control_get(GET_ENCAPSULATED_RESPONSE, 0, buf)
+    }
+
+    #[cfg(feature = "blocking")]
+    /// Sends the encapsulated command, waits for the `ResponseAvailable` notification,
+    /// then returns the response (4096 bytes at most). Other notifications received
+    /// meanwhile are dropped. Returns `TimedOut` error if no response is available.
+    pub fn encapsulated_exchange(
+        &self,
+        command: &[u8],
+        notifications: &mut NotificationReader,
+        timeout: Duration,
+    ) -> io::Result<Vec<u8>> {
+        let t_start = Instant::now();
+        self.send_encapsulated_command(command)?;
+        loop {
+            let remaining = timeout.saturating_sub(t_start.elapsed());
+            if remaining.is_zero() {
+                return Err(Error::from(ErrorKind::TimedOut));
+            }
+            match notifications.wait_next(remaining) {
+                Ok(CdcNotification::ResponseAvailable) => break,
+                Ok(_) => (),
+                Err(e) if e.kind() == ErrorKind::InvalidData => (),
+                Err(e) => return Err(e),
+            }
+        }
+        let mut buf = vec![0u8; 4096];
+        let len = self.encapsulated_response(&mut buf)?;
+        buf.truncate(len);
+        Ok(buf)
+    }
+
+    #[cfg(feature = "blocking")]
+    /// Clears the halt (stall) condition of the bulk IN or OUT endpoint deliberately,
+    /// for recovering from device-side protocol errors.
+    pub fn clear_halt(&mut self, direction: Direction) -> io::Result<()> {
+        let (reader, writer) = self.nusb_data()?;
+        match direction {
+            Direction::In => reader.clear_halt(),
+            Direction::Out => writer.clear_halt(),
+        }
+    }
+
+    #[cfg(feature = "blocking")]
+    /// Returns the amounts of stalled transfers of the bulk IN and OUT endpoints.
+    pub fn stall_counts(&self) -> (usize, usize) {
+        match &self.data {
+            DataChannel::Nusb { reader, writer } => (reader.stall_count(), writer.stall_count()),
+            _ => (0, 0),
+        }
+    }
+
+    #[cfg(feature = "blocking")]
+    /// Sets whether the halt conditions are cleared automatically when transfers stall,
+    /// true by default. Check `SyncReader::set_auto_clear_halt()`.
+    pub fn set_auto_clear_halt(&mut self, auto: bool) {
+        if let Ok((reader, writer)) = self.nusb_data() {
+            reader.set_auto_clear_halt(auto);
+            writer.set_auto_clear_halt(auto);
+        }
+    }
+
+    /// Returns `bmCapabilities` of the ACM functional descriptor, 0 if it is not found.
+    pub fn acm_capabilities(&self) -> u8 {
+        self.acm_capabilities
+    }
+
+    /// Sets the communication feature, e.g. the abstract state for devices requiring data
+    /// multiplexing to be enabled before accepting data. Returns `ErrorKind::Unsupported`
+    /// if the device doesn't declare the support in `acm_capabilities()`.
+    pub fn set_comm_feature(&self, feature: CommFeature, value: u16) -> io::Result<()> {
+        self.check_comm_feature_support()?;
+        self.control_set(SET_COMM_FEATURE, feature as u16, &value.to_le_bytes())
+    }
+
+    /// Gets the communication feature. Check `set_comm_feature()`.
+    pub fn comm_feature(&self, feature: CommFeature) -> io::Result<u16> {
+        self.check_comm_feature_support()?;
+        let mut buf = [0u8; 2];
+        let len = self.control_get(GET_COMM_FEATURE, feature as u16, &mut buf)?;
+        if len < 2 {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "comm feature is too short",
+            ));
+        }
+        Ok(u16::from_le_bytes(buf))
+    }
+
+    /// Resets the communication feature to its default value. Check `set_comm_feature()`.
+    pub fn clear_comm_feature(&self, feature: CommFeature) -> io::Result<()> {
+        self.check_comm_feature_support()?;
+        self.control_set(CLEAR_COMM_FEATURE, feature as u16, &[])
+    }
+
+    fn check_comm_feature_support(&self) -> io::Result<()> {
+        if self.acm_capabilities & ACM_CAP_COMM_FEATURE != 0 {
+            Ok(())
+        } else {
+            Err(Error::new(
+                ErrorKind::Unsupported,
+                "comm features are not supported by the device",
+            ))
+        }
+    }
+
+    /// Returns the latest serial parameters applied by `set_config()`.
+    pub fn config(&self) -> Option<SerialConfig> {
+        self.ser_conf
+    }
+
+    /// Returns the latest DTR and RTS states, (false, false) by default.
+    pub fn dtr_rts(&self) -> (bool, bool) {
+        self.dtr_rts
+    }
+
     /// Sets DTR and RTS states.
-    fn set_dtr_rts(&mut self, dtr: bool, rts: bool) -> io::Result<()> {
+    pub fn set_dtr_rts(&mut self, dtr: bool, rts: bool) -> io::Result<()> {
         let val_dtr = if dtr { 0x1 } else { 0x0 };
         let val_rts = if rts { 0x2 } else { 0x0 };
         let val = (val_dtr | val_rts) as u16;
@@ -129,58 +743,482 @@ impl CdcSerial {
         Ok(())
     }
 
+    #[cfg(feature = "blocking")]
+    /// Waits until some data is received without consuming it, returns the amount of bytes
+    /// available for the next reads. Returns `TimedOut` error if nothing is received.
+    /// Check `SyncReader::wait_readable()`.
+    pub fn wait_readable(&mut self, timeout: Duration) -> io::Result<usize> {
+        let (reader, _) = self.nusb_data()?;
+        let len = reader
+            .wait_readable(STAGING_SIZE, timeout)
+            .map_err(|e| self.map_revoked(e))?;
+        *lock_activity(&self.rx_activity) = Instant::now();
+        Ok(len)
+    }
+
+    #[cfg(feature = "blocking")]
+    /// Reads into a buffer which may be uninitialized, returns the filled part.
+    /// Check `SyncReader::read_uninit()`.
+    pub fn read_uninit<'b>(
+        &mut self,
+        buf: &'b mut [std::mem::MaybeUninit<u8>],
+    ) -> io::Result<&'b mut [u8]> {
+        let data = match &mut self.data {
+            DataChannel::Nusb { reader, .. } => reader.read_uninit(buf, self.timeout)?,
+            DataChannel::Java { conn, endp_r, .. } => {
+                buf.fill(std::mem::MaybeUninit::new(0));
+                // Safety: all bytes are initialized above.
+                let buf = unsafe { &mut *(buf as *mut [std::mem::MaybeUninit<u8>] as *mut [u8]) };
+                let len = conn.bulk_in(endp_r, buf, self.timeout)?;
+                &mut buf[..len]
+            }
+            DataChannel::JavaQueued { reader, .. } => {
+                buf.fill(std::mem::MaybeUninit::new(0));
+                // Safety: all bytes are initialized above.
+                let buf = unsafe { &mut *(buf as *mut [std::mem::MaybeUninit<u8>] as *mut [u8]) };
+                let len = reader.read(buf, self.timeout)?;
+                &mut buf[..len]
+            }
+        };
+        if !data.is_empty() {
+            *lock_activity(&self.rx_activity) = Instant::now();
+        }
+        Ok(data)
+    }
+
+    #[cfg(feature = "blocking")]
+    /// Keeps reading until the deadline or disconnection, returns the amount of bytes
+    /// appended to `buf`. Check `SyncReader::read_to_end_deadline()`.
+    pub fn read_to_end_deadline(
+        &mut self,
+        buf: &mut Vec<u8>,
+        deadline: Instant,
+    ) -> io::Result<usize> {
+        let (reader, _) = self.nusb_data()?;
+        let len = reader.read_to_end_deadline(buf, deadline)?;
+        if len > 0 {
+            *lock_activity(&self.rx_activity) = Instant::now();
+        }
+        Ok(len)
+    }
+
+    #[cfg(feature = "blocking")]
+    /// Writes the whole buffer, reporting the amount of bytes sent to `progress`.
+    /// Check `SyncWriter::write_all_with_progress()`.
+    pub fn write_all_with_progress(
+        &mut self,
+        buf: &[u8],
+        progress: impl FnMut(usize),
+    ) -> io::Result<()> {
+        match &mut self.data {
+            DataChannel::Nusb { writer, .. } => {
+                writer.write_all_with_progress(buf, self.timeout, progress)
+            }
+            _ => {
+                let mut progress = progress;
+                let mut sent = 0;
+                while sent < buf.len() {
+                    match self.write_data(&buf[sent..])? {
+                        0 => return Err(Error::from(ErrorKind::WriteZero)),
+                        len => sent += len,
+                    }
+                    progress(sent);
+                }
+                Ok(())
+            }
+        }
+    }
+
+    #[cfg(feature = "blocking")]
+    /// Waits until the device has received everything written, so DTR can be deasserted
+    /// or the port can be closed safely. Check `SyncWriter::drain()`.
+    pub fn drain(&mut self, timeout: Duration) -> io::Result<()> {
+        match &mut self.data {
+            DataChannel::Nusb { writer, .. } => writer.drain(timeout),
+            DataChannel::Java { .. } => Ok(()), // `bulkTransfer()` is synchronous
+            DataChannel::JavaQueued { writer, .. } => writer.drain(timeout),
+        }
+    }
+
+    /// Starts a thread checking if the device is still responding every `interval`, by
+    /// `GET_LINE_CODING` requests (a stall is considered as a response). `on_failure` is
+    /// called once if it stops responding, then the thread exits. It is useful for detecting
+    /// flaky cables, which may not cause a formal disconnection.
+    pub fn start_watchdog(
+        &self,
+        interval: Duration,
+        mut on_failure: impl FnMut(Error) + Send + 'static,
+    ) -> Watchdog {
+        let (stop, stop_recv) = std::sync::mpsc::channel::<()>();
+        let ctrl = self.ctrl.clone();
+        let index = self.ctrl_index;
+        let timeout = self.control_timeout();
+        let thread = std::thread::spawn(move || {
+            use nusb::transfer::TransferError;
+            use std::sync::mpsc::RecvTimeoutError;
+            while let Err(RecvTimeoutError::Timeout) = stop_recv.recv_timeout(interval) {
+                let mut buf = [0u8; 7];
+                let control = usb::class_request(GET_LINE_CODING, 0, index);
+                let err = match ctrl.control_in(control, &mut buf, timeout) {
+                    Ok(_) | Err(TransferError::Stall) => continue,
+                    Err(TransferError::Cancelled) => Error::from(ErrorKind::TimedOut),
+                    Err(e) => usb::map_transfer_error(e),
+                };
+                on_failure(err);
+                return;
+            }
+        });
+        Watchdog {
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+
+    /// Returns the time elapsed since the latest data was read, or since the port was
+    /// opened if nothing has been read.
+    pub fn idle_duration(&self) -> Duration {
+        lock_activity(&self.rx_activity).elapsed()
+    }
+
+    /// Starts a thread calling `on_idle` with the idle duration once no data has been
+    /// read for `idle_timeout`. It is called again only after new data is read and the
+    /// port becomes silent again, so a silent sensor can be flagged without timers
+    /// around every read.
+    ///
+    /// Note: Only data consumed through this handler counts; it doesn't work after
+    /// `into_queues()`, and data isn't read if the application doesn't read it.
+    pub fn start_idle_monitor(
+        &self,
+        idle_timeout: Duration,
+        mut on_idle: impl FnMut(Duration) + Send + 'static,
+    ) -> Watchdog {
+        let (stop, stop_recv) = std::sync::mpsc::channel::<()>();
+        let rx_activity = self.rx_activity.clone();
+        let idle_timeout = idle_timeout.max(Duration::from_millis(1));
+        let thread = std::thread::spawn(move || {
+            use std::sync::mpsc::RecvTimeoutError;
+            let mut reported: Option<Instant> = None; // activity time of the latest report
+            let mut wait = idle_timeout;
+            while let Err(RecvTimeoutError::Timeout) = stop_recv.recv_timeout(wait) {
+                let last = *lock_activity(&rx_activity);
+                let elapsed = last.elapsed();
+                if reported == Some(last) {
+                    wait = idle_timeout; // keeps polling for new data
+                } else if elapsed >= idle_timeout {
+                    on_idle(elapsed);
+                    reported.replace(last);
+                    wait = idle_timeout;
+                } else {
+                    wait = idle_timeout - elapsed;
+                }
+            }
+        });
+        Watchdog {
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+
+    #[cfg(feature = "blocking")]
+    /// Sets the maximum time of waiting for pending OUT transfers when the handler is
+    /// dropped, `None` by default. Check `SyncWriter::set_drain_on_drop()`.
+    pub fn set_drain_on_drop(&mut self, timeout: Option<Duration>) {
+        if let Ok((_, writer)) = self.nusb_data() {
+            writer.set_drain_on_drop(timeout);
+        }
+    }
+
+    /// Enables or disables the half-duplex mode. In this mode, `Read` and `Write`
+    /// implementations sleep for the quiet period before doing the transfer if needed.
+    pub fn set_half_duplex(&mut self, half_duplex: Option<HalfDuplex>) {
+        self.half_duplex = half_duplex;
+    }
+
+    /// Returns the half-duplex turnaround delays, `None` if it is disabled.
+    pub fn half_duplex(&self) -> Option<HalfDuplex> {
+        self.half_duplex
+    }
+
+    #[cfg(feature = "blocking")]
+    /// Returns the handle for aborting reads and writes in progress from another thread
+    /// (the handler itself is borrowed by the blocking call), which return `Interrupted`
+    /// error then. Control transfers are not affected. Returns `Unsupported` error
+    /// for the Java backends.
+    pub fn cancel_handle(&self) -> io::Result<CancelHandle> {
+        match &self.data {
+            DataChannel::Nusb { reader, .. } => Ok(reader.cancel_handle()),
+            _ => Err(java_unsupported()),
+        }
+    }
+
     /// Sets the break state.
-    fn set_break_state(&self, val: bool) -> io::Result<()> {
+    pub fn set_break_state(&self, val: bool) -> io::Result<()> {
         let val = if val { 0xffff } else { 0 } as u16;
         self.control_set(SEND_BREAK, val, &[])
     }
 
+    /// Returns the timeout for standard `Read` and `Write` implementations.
+    /// Equals `SerialPort::timeout()` if the `serialport` feature is enabled.
+    #[cfg(not(feature = "serialport"))]
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Sets timeout for standard `Read` and `Write` implementations to do USB bulk transfers.
+    /// Equals `SerialPort::set_timeout()` if the `serialport` feature is enabled.
+    #[cfg(not(feature = "serialport"))]
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Sets the retry policy for transient transfer failures of bulk and control transfers,
+    /// no retry by default. Check `RetryPolicy`.
+    pub fn set_retry_policy(&mut self, retry: RetryPolicy) {
+        self.retry = retry;
+        #[cfg(feature = "blocking")]
+        if let Ok((reader, writer)) = self.nusb_data() {
+            reader.set_retry_policy(retry);
+            writer.set_retry_policy(retry);
+        }
+    }
+
+    /// Returns the timeout of control transfers (serial configuration, DTR/RTS, etc.).
+    pub fn control_timeout(&self) -> Duration {
+        self.ctrl_timeout.unwrap_or(self.timeout * 2)
+    }
+
+    /// Sets the timeout of control transfers independently of the `Read` and `Write`
+    /// timeout. `None` (by default) means twice the `Read` and `Write` timeout.
+    pub fn set_control_timeout(&mut self, timeout: Option<Duration>) {
+        self.ctrl_timeout = timeout;
+    }
+
+    #[cfg(feature = "blocking")]
+    /// Returns the `nusb` reader and writer, `Unsupported` error for the Java backend.
+    fn nusb_data(&mut self) -> io::Result<(&mut SyncReader, &mut SyncWriter)> {
+        match &mut self.data {
+            DataChannel::Nusb { reader, writer } => Ok((reader, writer)),
+            _ => Err(java_unsupported()),
+        }
+    }
+
+    #[cfg(feature = "blocking")]
+    fn read_data(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match &mut self.data {
+            DataChannel::Nusb { reader, .. } => reader.read(buf, self.timeout),
+            DataChannel::Java { conn, endp_r, .. } => conn.bulk_in(endp_r, buf, self.timeout),
+            DataChannel::JavaQueued { reader, .. } => reader.read(buf, self.timeout),
+        }
+    }
+
+    #[cfg(feature = "blocking")]
+    fn write_data(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.data {
+            DataChannel::Nusb { writer, .. } => writer.write(buf, self.timeout),
+            DataChannel::Java { conn, endp_w, .. } => conn.bulk_out(endp_w, buf, self.timeout),
+            DataChannel::JavaQueued { writer, .. } => writer.write(buf, self.timeout),
+        }
+    }
+
+    fn control_get(&self, request: u8, value: u16, buf: &mut [u8]) -> io::Result<usize> {
+        let control = usb::class_request(request, value, self.ctrl_index);
+        usb::run_control_in(&self.retry, || {
+            self.ctrl
+                .control_in(control, &mut *buf, self.control_timeout())
+        })
+    }
+
     fn control_set(&self, request: u8, value: u16, buf: &[u8]) -> io::Result<()> {
-        use nusb::transfer::TransferError;
-        let sz_write = self
-            .intr_comm
-            .control_out_blocking(
-                Control {
-                    control_type: ControlType::Class,
-                    recipient: Recipient::Interface,
-                    request,
-                    value,
-                    index: self.ctrl_index,
-                },
-                buf,
-                self.timeout * 2,
-            )
-            .map_err(|e| match e {
-                TransferError::Disconnected => Error::from(ErrorKind::NotConnected),
-                _ => Error::other(e),
-            })?;
-        if sz_write == buf.len() {
-            Ok(())
-        } else {
-            Err(Error::new(
-                ErrorKind::Interrupted,
-                "control_set(), wrong written size",
-            ))
+        let control = usb::class_request(request, value, self.ctrl_index);
+        usb::run_control_out(&self.retry, buf.len(), || {
+            self.ctrl.control_out(control, buf, self.control_timeout())
+        })
+    }
+}
+
+/// Feature selectors of `SET_COMM_FEATURE` and `GET_COMM_FEATURE` requests.
+///
+/// The abstract state is a bitmap: D1 enables multiplexing of the data and commands
+/// (AT commands are passed through the data interface), D0 makes the device idle
+/// (it doesn't accept data).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CommFeature {
+    AbstractState = 0x01,
+    CountrySetting = 0x02,
+}
+
+/// Notification received from the interrupt IN endpoint of the communication interface.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CdcNotification {
+    /// Network (carrier) connection state, true if connected.
+    NetworkConnection(bool),
+    /// The device has a response for `GET_ENCAPSULATED_RESPONSE`.
+    ResponseAvailable,
+    /// UART state bitmap: D0 DCD, D1 DSR, D2 break, D3 ring, D4 framing error,
+    /// D5 parity error, D6 overrun.
+    SerialState(u16),
+    /// Link speeds in bits per second.
+    ConnectionSpeedChange { upstream: u32, downstream: u32 },
+    /// Other notifications with the code, `wValue` and the data.
+    Other { code: u8, value: u16, data: Vec<u8> },
+}
+
+impl CdcNotification {
+    /// Parses the 8-byte notification header and the following data.
+    /// Returns `None` if the packet is too short.
+    pub fn parse(packet: &[u8]) -> Option<Self> {
+        if packet.len() < 8 {
+            return None;
+        }
+        let code = packet[1];
+        let value = u16::from_le_bytes([packet[2], packet[3]]);
+        let len = u16::from_le_bytes([packet[6], packet[7]]) as usize;
+        let data = packet.get(8..8 + len)?;
+        Some(match code {
+            NETWORK_CONNECTION => Self::NetworkConnection(value != 0),
+            RESPONSE_AVAILABLE => Self::ResponseAvailable,
+            SERIAL_STATE if len >= 2 => Self::SerialState(u16::from_le_bytes([data[0], data[1]])),
+            CONNECTION_SPEED_CHANGE if len >= 8 => Self::ConnectionSpeedChange {
+                upstream: u32::from_le_bytes([data[0], data[1], data[2], data[3]]),
+                downstream: u32::from_le_bytes([data[4], data[5], data[6], data[7]]),
+            },
+            _ => Self::Other {
+                code,
+                value,
+                data: data.to_vec(),
+            },
+        })
+    }
+}
+
+#[cfg(feature = "blocking")]
+/// Reader of CDC notifications, returned by `CdcSerial::notifications()`.
+/// It can be moved to another thread.
+pub struct NotificationReader {
+    reader: SyncInterruptReader,
+}
+
+#[cfg(feature = "blocking")]
+impl NotificationReader {
+    /// Waits for the next notification. Returns `TimedOut` error if nothing is received,
+    /// or `InvalidData` error if the packet is malformed.
+    pub fn wait_next(&mut self, timeout: Duration) -> io::Result<CdcNotification> {
+        let packet = self.reader.read_packet(timeout)?;
+        CdcNotification::parse(&packet).ok_or(Error::new(
+            ErrorKind::InvalidData,
+            "Malformed CDC notification",
+        ))
+    }
+
+    /// Takes the interrupt IN queue for asynchronous operations.
+    pub fn into_inner(self) -> Queue<RequestBuffer> {
+        self.reader.into()
+    }
+}
+
+/// Handler of the monitoring thread started by `CdcSerial::start_watchdog()` or
+/// `CdcSerial::start_idle_monitor()`. The thread is stopped when this handler is dropped.
+#[derive(Debug)]
+pub struct Watchdog {
+    stop: Option<std::sync::mpsc::Sender<()>>,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+impl Watchdog {
+    /// Returns true if the thread has exited (after a failure for the keepalive thread).
+    pub fn is_finished(&self) -> bool {
+        self.thread.as_ref().map_or(true, |t| t.is_finished())
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        drop(self.stop.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
         }
     }
 }
 
+#[cfg(feature = "blocking")]
 impl Read for CdcSerial {
     #[inline]
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        self.reader.read(buf, self.timeout)
+        if let Some(half_duplex) = self.half_duplex {
+            wait_quiet_period(self.last_tx, half_duplex.tx_to_rx);
+        }
+        let len = self.read_data(buf).map_err(|e| self.map_revoked(e))?;
+        if len > 0 {
+            let now = Instant::now();
+            self.last_rx.replace(now);
+            *lock_activity(&self.rx_activity) = now;
+        }
+        Ok(len)
     }
 }
 
+#[cfg(feature = "blocking")]
 impl Write for CdcSerial {
     #[inline]
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.writer.write(buf, self.timeout)
+        let Some(half_duplex) = self.half_duplex else {
+            return self.write_data(buf).map_err(|e| self.map_revoked(e));
+        };
+        wait_quiet_period(self.last_rx, half_duplex.rx_to_tx);
+        let len = self.write_data(buf).map_err(|e| self.map_revoked(e))?;
+        self.last_tx.replace(Instant::now());
+        Ok(len)
     }
-    /// Does nothing.
+    /// Waits for the transfer left pending by a polling write (zero timeout), if any.
     fn flush(&mut self) -> io::Result<()> {
-        Ok(())
+        self.drain(self.timeout).map_err(|e| self.map_revoked(e))
+    }
+}
+
+#[inline(always)]
+fn lock_activity(rx_activity: &Mutex<Instant>) -> std::sync::MutexGuard<'_, Instant> {
+    rx_activity.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(feature = "blocking")]
+/// Sleeps until `period` has passed since `last`.
+fn wait_quiet_period(last: Option<Instant>, period: Duration) {
+    if let Some(elapsed) = last.map(|t| t.elapsed()) {
+        if elapsed < period {
+            std::thread::sleep(period - elapsed);
+        }
+    }
+}
+
+fn java_unsupported() -> Error {
+    Error::new(
+        ErrorKind::Unsupported,
+        "Unsupported by the Java transfer backend",
+    )
+}
+
+/// Finds `bmCapabilities` of the ACM functional descriptor of the interface in the raw
+/// descriptors, 0 if it is not found.
+fn acm_capabilities_from_raw(raw: &[u8], interface_number: u8) -> u8 {
+    const INTERFACE: u8 = 0x04;
+    let mut current = None; // interface of the following class-specific descriptors
+    let mut rest = raw;
+    while rest.len() >= 2 && rest[0] >= 2 && rest.len() >= rest[0] as usize {
+        let (desc, next) = rest.split_at(rest[0] as usize);
+        match desc[1] {
+            INTERFACE if desc.len() >= 3 => current = Some(desc[2]),
+            CS_INTERFACE
+                if current == Some(interface_number)
+                    && desc.len() >= 4
+                    && desc[2] == ACM_FUNCTIONAL_DESCRIPTOR =>
+            {
+                return desc[3];
+            }
+            _ => (),
+        }
+        rest = next;
     }
+    0
 }
 
 impl SerialConfig {
@@ -204,8 +1242,31 @@ impl SerialConfig {
         };
         bytes
     }
+
+    fn update_from_line_coding(&mut self, bytes: &[u8; 7]) {
+        self.baud_rate = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        match bytes[4] {
+            0 => self.stop_bits = StopBits::One,
+            2 => self.stop_bits = StopBits::Two,
+            _ => (),
+        }
+        match bytes[5] {
+            0 => self.parity = Parity::None,
+            1 => self.parity = Parity::Odd,
+            2 => self.parity = Parity::Even,
+            _ => (),
+        }
+        match bytes[6] {
+            5 => self.data_bits = DataBits::Five,
+            6 => self.data_bits = DataBits::Six,
+            7 => self.data_bits = DataBits::Seven,
+            8 => self.data_bits = DataBits::Eight,
+            _ => (),
+        }
+    }
 }
 
+#[cfg(feature = "serialport")]
 #[inline(always)]
 fn err_map_to_serialport(err: Error) -> serialport::Error {
     let desc = err.to_string();
@@ -217,6 +1278,7 @@ fn err_map_to_serialport(err: Error) -> serialport::Error {
     serialport::Error::new(kind, desc)
 }
 
+#[cfg(feature = "serialport")]
 fn err_unsupported_op() -> serialport::Error {
     err_map_to_serialport(Error::new(
         ErrorKind::Unsupported,
@@ -224,6 +1286,7 @@ fn err_unsupported_op() -> serialport::Error {
     ))
 }
 
+#[cfg(feature = "serialport")]
 impl CdcSerial {
     #[inline]
     fn get_conf_for_serialport(&self) -> Result<&SerialConfig, serialport::Error> {
@@ -234,6 +1297,7 @@ impl CdcSerial {
     }
 }
 
+#[cfg(feature = "serialport")]
 impl SerialPort for CdcSerial {
     fn name(&self) -> Option<String> {
         Some(self.usb_path_name.clone())
@@ -363,9 +1427,79 @@ impl UsbSerial for CdcSerial {
         self.set_config(*conf)
     }
 
-    fn into_queues(self) -> (Queue<RequestBuffer>, Queue<Vec<u8>>) {
-        (self.reader.into(), self.writer.into())
+    /// Returns `Unsupported` error if the port is opened with a Java backend.
+    fn into_queues(self) -> std::io::Result<(Queue<RequestBuffer>, Queue<Vec<u8>>)> {
+        match self.data {
+            DataChannel::Nusb { reader, writer } => Ok((reader.into(), writer.into())),
+            _ => Err(java_unsupported()),
+        }
+    }
+
+    fn set_dtr_rts(&mut self, dtr: bool, rts: bool) -> std::io::Result<()> {
+        CdcSerial::set_dtr_rts(self, dtr, rts)
+    }
+
+    /// Reports the features declared in `acm_capabilities()`. Note: Some devices accept
+    /// requests which are not declared there; flow control is never supported by CDC-ACM.
+    fn capabilities(&self) -> PortCapabilities {
+        let line_coding = self.acm_capabilities & ACM_CAP_LINE_CODING != 0;
+        PortCapabilities {
+            break_signal: self.acm_capabilities & ACM_CAP_SEND_BREAK != 0,
+            flow_control: false,
+            modem_lines: line_coding,
+            baud_range: line_coding.then_some((1, u32::MAX)),
+            port_count: Self::port_count(&self.dev_info),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOTIFY: &[(u8, u8)] = &[(0x81, 3)];
+    const BULK: &[(u8, u8)] = &[(0x82, 2), (0x02, 2)];
+    const COMBINED: &[(u8, u8)] = &[(0x81, 3), (0x82, 2), (0x02, 2)];
+
+    fn comm(num: u8, endpoints: &[(u8, u8)]) -> InterfaceInfo {
+        let class = (USB_INTR_CLASS_COMM, USB_INTR_SUBCLASS_ACM);
+        InterfaceInfo::with_endpoints(num, class, endpoints)
+    }
+
+    fn data(num: u8) -> InterfaceInfo {
+        InterfaceInfo::with_endpoints(num, (USB_INTR_CLASS_CDC_DATA, 0), BULK)
     }
 
-    fn sealer(_: crate::private::Internal) {}
+    fn pair_numbers(interfaces: &[InterfaceInfo]) -> Vec<(u8, u8)> {
+        CdcSerial::find_interface_pairs(interfaces.iter())
+            .iter()
+            .map(|(comm, data)| (comm.interface_number(), data.interface_number()))
+            .collect()
+    }
+
+    #[test]
+    fn following_data_interfaces() {
+        let interfaces = [comm(0, NOTIFY), data(1), comm(2, NOTIFY), data(3)];
+        assert_eq!(pair_numbers(&interfaces), [(0, 1), (2, 3)]);
+    }
+
+    #[test]
+    fn combined_interface_before_normal_pair() {
+        let interfaces = [comm(0, COMBINED), comm(1, NOTIFY), data(2)];
+        assert_eq!(pair_numbers(&interfaces), [(0, 0), (1, 2)]);
+    }
+
+    #[test]
+    fn fallback_skips_following_data_interfaces() {
+        let interfaces = [comm(0, NOTIFY), comm(3, NOTIFY), data(4), data(5)];
+        assert_eq!(pair_numbers(&interfaces), [(0, 5), (3, 4)]);
+    }
+
+    #[test]
+    fn combined_interface_needs_bulk_endpoints() {
+        // three endpoints, but no bulk OUT endpoint
+        let endpoints = [(0x81, 3), (0x82, 2), (0x83, 2)];
+        assert!(pair_numbers(&[comm(0, &endpoints)]).is_empty());
+        assert_eq!(pair_numbers(&[comm(0, COMBINED)]), [(0, 0)]);
+    }
 }
@@ -0,0 +1,283 @@
+use std::{
+    io::{self, Error, ErrorKind, Read, Write},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    usb::{self, DeviceFilter, DeviceInfo, HotplugEvent},
+    CdcSerial,
+};
+
+#[cfg(feature = "serialport")]
+use serialport::SerialPort;
+
+/// Event reported by `RobustSerial` to the handler set by `set_event_handler()`.
+#[derive(Debug)]
+pub enum ReconnectEvent {
+    /// The device is lost; the error of the failed operation is carried.
+    Disconnected(Error),
+    /// The device is attached again, and the permission is being requested.
+    PermissionRequested(DeviceInfo),
+    /// The port is reopened with previous settings.
+    Reconnected(DeviceInfo),
+    /// Reconnection failed; the operation returns this error too.
+    Failed(ErrorKind),
+}
+
+/// Wrapper of `CdcSerial` which reopens the port after the device is reattached.
+///
+/// On `NotConnected` failures (or failures after which the device is no longer
+/// connected), `Read` and `Write` operations wait for a device of the same vendor ID,
+/// product ID and serial number to be attached, request the permission if needed,
+/// reopen the same port, reapply `SerialConfig` and DTR/RTS states, then retry once.
+///
+/// Note: Waiting in the `android_main()` thread will prevent it from receiving hotplug
+/// events and the permission result. Data in transit during disconnection is lost.
+pub struct RobustSerial {
+    port: Option<CdcSerial>,       // `None` while disconnected
+    filter: DeviceFilter,          // vendor ID and product ID of the device
+    serial_number: Option<String>, // checked after gaining permission for the device
+    port_index: usize,             // index of the CDC-ACM function
+    timeout: Duration,             // standard `Read` and `Write` timeout
+    reconnect_timeout: Duration,   // maximum time of waiting for reattachment
+
+    on_event: Option<Box<dyn FnMut(ReconnectEvent) + Send>>, // reconnection event handler
+}
+
+impl RobustSerial {
+    /// Wraps an opened port. `reconnect_timeout` is the maximum time of waiting for the
+    /// device to be reattached and for the user's permission response.
+    /// - `port_index`: Index of the CDC-ACM function, 0 for `CdcSerial::build()`.
+    ///
+    /// Note: The serial number is checked on reattachment only if it is known by the
+    /// `DeviceInfo` of `port`; otherwise any device of the same IDs may be reopened.
+    pub fn new(port: CdcSerial, port_index: usize, reconnect_timeout: Duration) -> Self {
+        let dev_info = port.device_info();
+        // the serial number is unreadable before gaining permission on Android 10 and
+        // above, so it can't be matched while waiting for the device
+        let filter = DeviceFilter::with_ids(dev_info.vendor_id(), dev_info.product_id());
+        let serial_number = dev_info.serial_number().clone();
+        Self {
+            timeout: port_timeout(&port),
+            port: Some(port),
+            filter,
+            serial_number,
+            port_index,
+            reconnect_timeout,
+            on_event: None,
+        }
+    }
+
+    /// Sets the handler of reconnection events. It is called in the thread doing
+    /// `Read` or `Write` operations.
+    pub fn set_event_handler(&mut self, handler: impl FnMut(ReconnectEvent) + Send + 'static) {
+        self.on_event.replace(Box::new(handler));
+    }
+
+    /// Returns a reference of the current port, `None` while disconnected.
+    pub fn get_ref(&self) -> Option<&CdcSerial> {
+        self.port.as_ref()
+    }
+
+    /// Returns a mutable reference of the current port, `None` while disconnected.
+    /// Settings changed through it are kept for reconnection.
+    pub fn get_mut(&mut self) -> Option<&mut CdcSerial> {
+        self.port.as_mut()
+    }
+
+    /// Returns true if the port is opened.
+    pub fn is_connected(&self) -> bool {
+        self.port.is_some()
+    }
+
+    /// Returns the maximum time of waiting for reattachment.
+    pub fn reconnect_timeout(&self) -> Duration {
+        self.reconnect_timeout
+    }
+
+    /// Sets the maximum time of waiting for reattachment.
+    pub fn set_reconnect_timeout(&mut self, timeout: Duration) {
+        self.reconnect_timeout = timeout;
+    }
+
+    /// Sets timeout for standard `Read` and `Write` operations of the current port
+    /// and reopened ports.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+        if let Some(port) = self.port.as_mut() {
+            set_port_timeout(port, timeout);
+        }
+    }
+
+    /// Reopens the port now if it is disconnected. Check the struct documentation.
+    pub fn reconnect(&mut self) -> io::Result<()> {
+        if self.port.is_some() {
+            return Ok(());
+        }
+        self.reopen(None).map_err(|e| {
+            self.report(ReconnectEvent::Failed(e.kind()));
+            e
+        })
+    }
+
+    /// Takes the current port, `None` while disconnected.
+    pub fn into_inner(self) -> Option<CdcSerial> {
+        self.port
+    }
+
+    /// Calls `op` with the port, reconnecting and retrying once if the device is lost.
+    fn with_port<T>(
+        &mut self,
+        mut op: impl FnMut(&mut CdcSerial) -> io::Result<T>,
+    ) -> io::Result<T> {
+        self.reconnect()?;
+        let port = self.port.as_mut().unwrap();
+        match op(port) {
+            Err(e) if is_disconnection(port, &e) => {
+                self.report(ReconnectEvent::Disconnected(e));
+                self.reopen_after(self.port.take().unwrap())?;
+                op(self.port.as_mut().unwrap())
+            }
+            result => result,
+        }
+    }
+
+    /// Reopens the port with the settings of the lost port.
+    fn reopen_after(&mut self, lost: CdcSerial) -> io::Result<()> {
+        let (conf, dtr_rts) = (lost.config(), lost.dtr_rts());
+        let stale_path = lost.device_info().path_name().clone();
+        drop(lost);
+        let result = self.reopen(Some(stale_path.as_str())).and_then(|_| {
+            let port = self.port.as_mut().unwrap();
+            if let Some(conf) = conf {
+                port.set_config(conf)?;
+            }
+            if dtr_rts != (false, false) {
+                port.set_dtr_rts(dtr_rts.0, dtr_rts.1)?;
+            }
+            Ok(())
+        });
+        match result {
+            Ok(()) => {
+                let dev_info = self.port.as_ref().unwrap().device_info().clone();
+                self.report(ReconnectEvent::Reconnected(dev_info));
+                Ok(())
+            }
+            Err(e) => {
+                self.port.take();
+                self.report(ReconnectEvent::Failed(e.kind()));
+                Err(e)
+            }
+        }
+    }
+
+    /// Waits for the device, requests the permission if needed, and opens the port.
+    /// The device at `stale_path` is ignored: it may be listed before the detachment
+    /// broadcast is processed, and the reattached device gets a new address anyway.
+    /// Devices of the same IDs but another serial number are skipped after opening.
+    fn reopen(&mut self, stale_path: Option<&str>) -> io::Result<()> {
+        let t_start = Instant::now();
+        let mut watch =
+            usb::watch_devices_filtered(self.filter.clone())?.with_initial_snapshot()?;
+        let mut skipped: Vec<String> = stale_path.into_iter().map(String::from).collect();
+        loop {
+            let remaining = self.reconnect_timeout.saturating_sub(t_start.elapsed());
+            let mut dev_info = match watch.wait_blocking(remaining) {
+                Some(HotplugEvent::Connected(dev)) if !skipped.contains(dev.path_name()) => dev,
+                Some(_) => continue,
+                None => {
+                    return Err(Error::new(
+                        ErrorKind::NotConnected,
+                        "The device is not reattached",
+                    ))
+                }
+            };
+            if let Some(request) = dev_info.request_permission()? {
+                self.report(ReconnectEvent::PermissionRequested(dev_info.clone()));
+                let remaining = self.reconnect_timeout.saturating_sub(t_start.elapsed());
+                if !request.wait_blocking(remaining)? {
+                    return Err(Error::new(
+                        ErrorKind::PermissionDenied,
+                        "USB permission denied by the user",
+                    ));
+                }
+                dev_info.refresh()?; // re-read strings with the permission
+            }
+            let device = dev_info.open_device()?;
+            if self.serial_number.is_some() {
+                if dev_info.serial_number().is_none() {
+                    let remaining = self.reconnect_timeout.saturating_sub(t_start.elapsed());
+                    let _ = dev_info.fetch_strings(&device, remaining);
+                }
+                if dev_info.serial_number() != &self.serial_number {
+                    skipped.push(dev_info.path_name().clone());
+                    continue;
+                }
+            }
+            let port = CdcSerial::build_port(&device, &dev_info, self.port_index, self.timeout)?;
+            self.port.replace(port);
+            return Ok(());
+        }
+    }
+
+    fn report(&mut self, event: ReconnectEvent) {
+        if let Some(on_event) = self.on_event.as_mut() {
+            on_event(event);
+        }
+    }
+}
+
+/// Returns true if the failure is caused by disconnection of the device.
+fn is_disconnection(port: &CdcSerial, err: &Error) -> bool {
+    match err.kind() {
+        ErrorKind::NotConnected => true,
+        ErrorKind::TimedOut | ErrorKind::Interrupted => false,
+        _ => !port.device_info().check_connection(),
+    }
+}
+
+#[cfg(feature = "serialport")]
+fn port_timeout(port: &CdcSerial) -> Duration {
+    SerialPort::timeout(port)
+}
+#[cfg(not(feature = "serialport"))]
+fn port_timeout(port: &CdcSerial) -> Duration {
+    port.timeout()
+}
+
+#[cfg(feature = "serialport")]
+fn set_port_timeout(port: &mut CdcSerial, timeout: Duration) {
+    let _ = SerialPort::set_timeout(port, timeout);
+}
+#[cfg(not(feature = "serialport"))]
+fn set_port_timeout(port: &mut CdcSerial, timeout: Duration) {
+    port.set_timeout(timeout);
+}
+
+impl Read for RobustSerial {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.with_port(|port| port.read(buf))
+    }
+}
+
+impl Write for RobustSerial {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.with_port(|port| port.write(buf))
+    }
+    /// Does nothing.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for RobustSerial {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RobustSerial")
+            .field("connected", &self.port.is_some())
+            .field("filter", &self.filter)
+            .field("serial_number", &self.serial_number)
+            .field("port_index", &self.port_index)
+            .field("reconnect_timeout", &self.reconnect_timeout)
+            .finish_non_exhaustive()
+    }
+}
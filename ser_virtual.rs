@@ -0,0 +1,515 @@
+use std::{
+    collections::VecDeque,
+    io::{self, Error, ErrorKind, Read, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Condvar, Mutex, MutexGuard,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{PortCapabilities, SerialConfig, UsbSerial};
+use nusb::transfer::{Queue, RequestBuffer};
+
+#[cfg(feature = "serialport")]
+use serialport::SerialPort;
+
+/// Creates two connected in-memory ports, like a null-modem cable: data written to one
+/// port is read from the other, DTR of one port is DSR and DCD of the other, RTS of one
+/// port is CTS of the other. It is for testing two halves of an application protocol
+/// against each other on the host; serial parameters are stored but not checked.
+///
+/// The read timeout is 100 ms by default.
+pub fn virtual_pair() -> (VirtualPort, VirtualPort) {
+    let (pipe_a, pipe_b) = (Arc::new(Pipe::default()), Arc::new(Pipe::default()));
+    let port_a = VirtualPort::new("virtual0", pipe_b.clone(), pipe_a.clone());
+    let port_b = VirtualPort::new("virtual1", pipe_a, pipe_b);
+    (port_a, port_b)
+}
+
+/// One end of the in-memory port pair created by `virtual_pair()`. Dropping it closes
+/// the pair: the other end gets `NotConnected` errors after reading the remaining data.
+#[derive(Debug)]
+pub struct VirtualPort {
+    rx: Arc<Pipe>, // written by the other end
+    tx: Arc<Pipe>, // read by the other end
+
+    timeout: Duration,              // standard `Read` timeout
+    latency: Duration,              // delay before written data is available to the other end
+    ser_conf: Option<SerialConfig>, // keeps the latest settings
+    break_state: AtomicBool,        // keeps the latest settings
+
+    #[cfg(feature = "serialport")]
+    name: &'static str,
+    #[cfg(feature = "serialport")]
+    flow_control: serialport::FlowControl, // keeps the latest settings
+}
+
+#[derive(Debug, Default)]
+struct Pipe {
+    state: Mutex<PipeState>,
+    cond: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct PipeState {
+    chunks: VecDeque<(Instant, Vec<u8>)>, // written data with the time of arrival
+    pos: usize,                           // amount of consumed bytes in the front chunk
+    dtr_rts: (bool, bool),                // line states of the writing end
+    closed: bool,                         // one of the ends is dropped
+}
+
+impl Pipe {
+    fn lock(&self) -> MutexGuard<'_, PipeState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl PipeState {
+    /// Returns the amount of bytes arrived at `now`.
+    fn available(&self, now: Instant) -> usize {
+        self.chunks
+            .iter()
+            .take_while(|(t, _)| *t <= now)
+            .map(|(_, data)| data.len())
+            .sum::<usize>()
+            - self.pos
+    }
+
+    fn in_flight(&self, now: Instant) -> usize {
+        self.chunks
+            .iter()
+            .skip_while(|(t, _)| *t <= now)
+            .map(|(_, data)| data.len())
+            .sum()
+    }
+}
+
+impl VirtualPort {
+    fn new(name: &'static str, rx: Arc<Pipe>, tx: Arc<Pipe>) -> Self {
+        #[cfg(not(feature = "serialport"))]
+        let _ = name;
+        Self {
+            rx,
+            tx,
+            timeout: Duration::from_millis(100),
+            latency: Duration::ZERO,
+            ser_conf: None,
+            break_state: AtomicBool::new(false),
+            #[cfg(feature = "serialport")]
+            name,
+            #[cfg(feature = "serialport")]
+            flow_control: serialport::FlowControl::None,
+        }
+    }
+
+    /// Applies serial parameters.
+    pub fn set_config(&mut self, conf: SerialConfig) -> io::Result<()> {
+        self.ser_conf.replace(conf);
+        Ok(())
+    }
+
+    /// Returns the latest serial parameters applied by `set_config()`.
+    pub fn config(&self) -> Option<SerialConfig> {
+        self.ser_conf
+    }
+
+    /// Sets the delay before written data becomes available to the other end,
+    /// zero by default. It doesn't block the writer.
+    pub fn set_latency(&mut self, latency: Duration) {
+        self.latency = latency;
+    }
+
+    /// Returns the delay set by `set_latency()`.
+    pub fn latency(&self) -> Duration {
+        self.latency
+    }
+
+    /// Returns the latest DTR and RTS states, (false, false) by default.
+    pub fn dtr_rts(&self) -> (bool, bool) {
+        self.tx.lock().dtr_rts
+    }
+
+    /// Sets DTR and RTS states, which are visible to the other end.
+    pub fn set_dtr_rts(&mut self, dtr: bool, rts: bool) -> io::Result<()> {
+        self.tx.lock().dtr_rts = (dtr, rts);
+        Ok(())
+    }
+
+    /// Returns the DTR and RTS states of the other end.
+    pub fn peer_dtr_rts(&self) -> (bool, bool) {
+        self.rx.lock().dtr_rts
+    }
+
+    /// Returns the latest break state. It has no effect on the data.
+    pub fn break_state(&self) -> bool {
+        self.break_state.load(Ordering::Relaxed)
+    }
+
+    /// Sets the break state. It has no effect on the data.
+    pub fn set_break_state(&self, val: bool) {
+        self.break_state.store(val, Ordering::Relaxed);
+    }
+
+    /// Returns the timeout for the standard `Read` implementation.
+    /// Equals `SerialPort::timeout()` if the `serialport` feature is enabled.
+    #[cfg(not(feature = "serialport"))]
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Sets timeout for the standard `Read` implementation; writing never blocks.
+    /// Equals `SerialPort::set_timeout()` if the `serialport` feature is enabled.
+    #[cfg(not(feature = "serialport"))]
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Returns the amount of bytes which can be read without waiting.
+    pub fn available(&self) -> usize {
+        self.rx.lock().available(Instant::now())
+    }
+
+    /// Returns the amount of written bytes not arrived at the other end because of latency.
+    pub fn in_flight(&self) -> usize {
+        self.tx.lock().in_flight(Instant::now())
+    }
+
+    /// Clears the arrived data of the pipe, or the data not arrived yet.
+    #[cfg(feature = "serialport")]
+    fn clear_pipe(pipe: &Pipe, arrived: bool) {
+        let mut state = pipe.lock();
+        let now = Instant::now();
+        let n = state.chunks.iter().take_while(|(t, _)| *t <= now).count();
+        if arrived {
+            state.chunks.drain(..n);
+            state.pos = 0;
+        } else {
+            state.chunks.truncate(n);
+        }
+    }
+}
+
+impl Read for VirtualPort {
+    /// Waits until some data arrives. Returns `TimedOut` error if nothing is received,
+    /// or `NotConnected` error if the other end is dropped and nothing is left.
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let deadline = Instant::now() + self.timeout;
+        let mut state = self.rx.lock();
+        loop {
+            let now = Instant::now();
+            if state.available(now) > 0 {
+                break;
+            }
+            if state.closed && state.chunks.is_empty() {
+                return Err(Error::from(ErrorKind::NotConnected));
+            }
+            if now >= deadline {
+                return Err(Error::from(ErrorKind::TimedOut));
+            }
+            let wake_at = match state.chunks.front() {
+                Some((t, _)) => deadline.min(*t),
+                None => deadline,
+            };
+            state = self
+                .rx
+                .cond
+                .wait_timeout(state, wake_at.saturating_duration_since(now))
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+        let now = Instant::now();
+        let mut len = 0;
+        while len < buf.len() {
+            let pos = state.pos;
+            let Some((t, data)) = state.chunks.front() else {
+                break;
+            };
+            if *t > now {
+                break;
+            }
+            let n = (data.len() - pos).min(buf.len() - len);
+            buf[len..len + n].copy_from_slice(&data[pos..pos + n]);
+            len += n;
+            if pos + n < data.len() {
+                state.pos += n;
+            } else {
+                state.chunks.pop_front();
+                state.pos = 0;
+            }
+        }
+        Ok(len)
+    }
+}
+
+impl Write for VirtualPort {
+    /// Returns `NotConnected` error if the other end is dropped.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut state = self.tx.lock();
+        if state.closed {
+            return Err(Error::from(ErrorKind::NotConnected));
+        }
+        if !buf.is_empty() {
+            state
+                .chunks
+                .push_back((Instant::now() + self.latency, buf.to_vec()));
+            self.tx.cond.notify_all();
+        }
+        Ok(buf.len())
+    }
+    /// Does nothing.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for VirtualPort {
+    fn drop(&mut self) {
+        for pipe in [&self.rx, &self.tx] {
+            pipe.lock().closed = true;
+            pipe.cond.notify_all();
+        }
+    }
+}
+
+#[cfg(feature = "serialport")]
+impl SerialPort for VirtualPort {
+    fn name(&self) -> Option<String> {
+        Some(self.name.to_string())
+    }
+
+    fn baud_rate(&self) -> serialport::Result<u32> {
+        Ok(self.ser_conf.unwrap_or_default().baud_rate)
+    }
+    fn data_bits(&self) -> serialport::Result<serialport::DataBits> {
+        Ok(self.ser_conf.unwrap_or_default().data_bits)
+    }
+    fn parity(&self) -> serialport::Result<serialport::Parity> {
+        Ok(self.ser_conf.unwrap_or_default().parity)
+    }
+    fn stop_bits(&self) -> serialport::Result<serialport::StopBits> {
+        Ok(self.ser_conf.unwrap_or_default().stop_bits)
+    }
+
+    fn flow_control(&self) -> serialport::Result<serialport::FlowControl> {
+        Ok(self.flow_control)
+    }
+
+    fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    fn set_baud_rate(&mut self, baud_rate: u32) -> serialport::Result<()> {
+        self.ser_conf.get_or_insert_with(Default::default).baud_rate = baud_rate;
+        Ok(())
+    }
+
+    fn set_data_bits(&mut self, data_bits: serialport::DataBits) -> serialport::Result<()> {
+        self.ser_conf.get_or_insert_with(Default::default).data_bits = data_bits;
+        Ok(())
+    }
+
+    fn set_parity(&mut self, parity: serialport::Parity) -> serialport::Result<()> {
+        self.ser_conf.get_or_insert_with(Default::default).parity = parity;
+        Ok(())
+    }
+
+    fn set_stop_bits(&mut self, stop_bits: serialport::StopBits) -> serialport::Result<()> {
+        self.ser_conf.get_or_insert_with(Default::default).stop_bits = stop_bits;
+        Ok(())
+    }
+
+    /// The mode is stored but not simulated.
+    fn set_flow_control(
+        &mut self,
+        flow_control: serialport::FlowControl,
+    ) -> serialport::Result<()> {
+        self.flow_control = flow_control;
+        Ok(())
+    }
+
+    /// Sets timeout for the standard `Read` implementation; writing never blocks.
+    fn set_timeout(&mut self, timeout: Duration) -> serialport::Result<()> {
+        self.timeout = timeout;
+        Ok(())
+    }
+
+    fn write_request_to_send(&mut self, value: bool) -> serialport::Result<()> {
+        self.tx.lock().dtr_rts.1 = value;
+        Ok(())
+    }
+
+    fn write_data_terminal_ready(&mut self, value: bool) -> serialport::Result<()> {
+        self.tx.lock().dtr_rts.0 = value;
+        Ok(())
+    }
+
+    /// RTS of the other end.
+    fn read_clear_to_send(&mut self) -> serialport::Result<bool> {
+        Ok(self.peer_dtr_rts().1)
+    }
+    /// DTR of the other end.
+    fn read_data_set_ready(&mut self) -> serialport::Result<bool> {
+        Ok(self.peer_dtr_rts().0)
+    }
+    /// Always false.
+    fn read_ring_indicator(&mut self) -> serialport::Result<bool> {
+        Ok(false)
+    }
+    /// DTR of the other end.
+    fn read_carrier_detect(&mut self) -> serialport::Result<bool> {
+        Ok(self.peer_dtr_rts().0)
+    }
+
+    fn bytes_to_read(&self) -> serialport::Result<u32> {
+        Ok(self.available() as u32)
+    }
+    /// Returns the amount of bytes delayed by the latency.
+    fn bytes_to_write(&self) -> serialport::Result<u32> {
+        Ok(self.in_flight() as u32)
+    }
+    /// Clears the arrived input data and/or the output data delayed by the latency.
+    fn clear(&self, buffer_to_clear: serialport::ClearBuffer) -> serialport::Result<()> {
+        use serialport::ClearBuffer;
+        if let ClearBuffer::Input | ClearBuffer::All = buffer_to_clear {
+            Self::clear_pipe(&self.rx, true);
+        }
+        if let ClearBuffer::Output | ClearBuffer::All = buffer_to_clear {
+            Self::clear_pipe(&self.tx, false);
+        }
+        Ok(())
+    }
+
+    fn set_break(&self) -> serialport::Result<()> {
+        self.set_break_state(true);
+        Ok(())
+    }
+    fn clear_break(&self) -> serialport::Result<()> {
+        self.set_break_state(false);
+        Ok(())
+    }
+
+    /// Unsupported.
+    fn try_clone(&self) -> serialport::Result<Box<dyn serialport::SerialPort>> {
+        Err(Error::from(ErrorKind::Unsupported).into())
+    }
+}
+
+impl UsbSerial for VirtualPort {
+    fn configure(&mut self, conf: &SerialConfig) -> std::io::Result<()> {
+        self.set_config(*conf)
+    }
+
+    /// Returns `Unsupported` error: virtual ports have no `nusb` transfer queues.
+    fn into_queues(self) -> std::io::Result<(Queue<RequestBuffer>, Queue<Vec<u8>>)> {
+        Err(Error::new(
+            ErrorKind::Unsupported,
+            "`VirtualPort` has no USB transfer queues",
+        ))
+    }
+
+    fn set_dtr_rts(&mut self, dtr: bool, rts: bool) -> std::io::Result<()> {
+        VirtualPort::set_dtr_rts(self, dtr, rts)
+    }
+
+    /// Everything is supported; the flow control mode is only kept as a setting.
+    fn capabilities(&self) -> PortCapabilities {
+        PortCapabilities {
+            break_signal: true,
+            flow_control: cfg!(feature = "serialport"),
+            modem_lines: true,
+            baud_range: Some((1, u32::MAX)),
+            port_count: 1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn data_crosses_over() {
+        let (mut a, mut b) = virtual_pair();
+        a.write_all(b"ping").unwrap();
+        b.write_all(b"pong").unwrap();
+        let mut buf = [0u8; 8];
+        assert_eq!(b.read(&mut buf).unwrap(), 4);
+        assert_eq!(&buf[..4], b"ping");
+        assert_eq!(a.read(&mut buf).unwrap(), 4);
+        assert_eq!(&buf[..4], b"pong");
+    }
+
+    #[test]
+    fn latency_delays_arrival() {
+        let (mut a, mut b) = virtual_pair();
+        a.set_latency(Duration::from_millis(50));
+        b.timeout = Duration::from_secs(1);
+        let t_start = Instant::now();
+        a.write_all(b"data").unwrap();
+        assert_eq!(a.in_flight(), 4);
+        assert_eq!(b.available(), 0);
+        let mut buf = [0u8; 4];
+        assert_eq!(b.read(&mut buf).unwrap(), 4);
+        assert!(t_start.elapsed() >= Duration::from_millis(50));
+        assert_eq!(a.in_flight(), 0);
+    }
+
+    #[test]
+    fn read_times_out() {
+        let (_a, mut b) = virtual_pair();
+        b.timeout = Duration::from_millis(20);
+        let t_start = Instant::now();
+        let err = b.read(&mut [0u8; 4]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::TimedOut);
+        assert!(t_start.elapsed() >= Duration::from_millis(20));
+    }
+
+    #[test]
+    fn modem_lines_cross_over() {
+        let (mut a, b) = virtual_pair();
+        a.set_dtr_rts(true, false).unwrap();
+        assert_eq!(b.peer_dtr_rts(), (true, false));
+        a.set_dtr_rts(false, true).unwrap();
+        assert_eq!(b.peer_dtr_rts(), (false, true));
+        assert_eq!(b.dtr_rts(), (false, false));
+    }
+
+    #[cfg(feature = "serialport")]
+    #[test]
+    fn modem_lines_map_to_dsr_cts() {
+        let (mut a, mut b) = virtual_pair();
+        a.write_data_terminal_ready(true).unwrap();
+        assert!(b.read_data_set_ready().unwrap());
+        assert!(b.read_carrier_detect().unwrap());
+        assert!(!b.read_clear_to_send().unwrap());
+        a.write_request_to_send(true).unwrap();
+        assert!(b.read_clear_to_send().unwrap());
+    }
+
+    #[test]
+    fn peer_drop_disconnects() {
+        let (mut a, mut b) = virtual_pair();
+        a.write_all(b"left").unwrap();
+        drop(a);
+        let mut buf = [0u8; 8];
+        assert_eq!(b.read(&mut buf).unwrap(), 4);
+        assert_eq!(
+            b.read(&mut buf).unwrap_err().kind(),
+            ErrorKind::NotConnected
+        );
+        assert_eq!(b.write(b"x").unwrap_err().kind(), ErrorKind::NotConnected);
+    }
+
+    #[test]
+    fn into_queues_is_unsupported() {
+        let (a, _b) = virtual_pair();
+        let Err(err) = a.into_queues() else {
+            panic!("`VirtualPort` has no queues");
+        };
+        assert_eq!(err.kind(), ErrorKind::Unsupported);
+    }
+}
@@ -0,0 +1,216 @@
+//! Helper of the STM32 system memory bootloader protocol on USART, which makes it possible
+//! to update the firmware of STM32 devices behind USB serial bridges.
+//!
+//! The port should be configured as 8 data bits, even parity and 1 stop bit (the baudrate
+//! is detected by the bootloader from the init byte), and the device should be reset into
+//! the bootloader (BOOT0 high) before calling `Stm32Bootloader::init()`. Mass erasing can
+//! take seconds, so a long read timeout is required.
+//!
+//! Reference: *AN3155: USART protocol used in the STM32 bootloader*.
+
+use std::io::{ErrorKind, Read, Write};
+
+use crate::Error;
+
+const INIT: u8 = 0x7F;
+const ACK: u8 = 0x79;
+const NACK: u8 = 0x1F;
+
+const CMD_GET: u8 = 0x00;
+const CMD_GET_ID: u8 = 0x02;
+const CMD_READ_MEMORY: u8 = 0x11;
+const CMD_GO: u8 = 0x21;
+const CMD_WRITE_MEMORY: u8 = 0x31;
+const CMD_ERASE: u8 = 0x43;
+const CMD_EXTENDED_ERASE: u8 = 0x44;
+
+/// Maximum data length of a single read or write command.
+pub const MAX_BLOCK_SIZE: usize = 256;
+
+/// STM32 bootloader protocol handler over a serial port.
+#[derive(Debug)]
+pub struct Stm32Bootloader<P: Read + Write> {
+    port: P,
+    version: u8,
+    commands: Vec<u8>,
+}
+
+impl<P: Read + Write> Stm32Bootloader<P> {
+    /// Wraps the configured port. `init()` should be called before other commands.
+    pub fn new(port: P) -> Self {
+        Self {
+            port,
+            version: 0,
+            commands: Vec::new(),
+        }
+    }
+
+    /// Takes the inner port.
+    pub fn into_inner(self) -> P {
+        self.port
+    }
+
+    /// Sends the init byte for baudrate detection, then reads the bootloader version
+    /// and the supported commands by the `Get` command.
+    pub fn init(&mut self) -> Result<(), Error> {
+        self.port.write_all(&[INIT])?;
+        self.wait_ack()?;
+        self.get()
+    }
+
+    /// Bootloader protocol version read by `init()`, e.g. 0x31 for version 3.1.
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    /// Command codes supported by the bootloader, read by `init()`.
+    pub fn commands(&self) -> &[u8] {
+        &self.commands
+    }
+
+    fn get(&mut self) -> Result<(), Error> {
+        self.send_command(CMD_GET)?;
+        let mut len = [0u8; 1];
+        self.port.read_exact(&mut len)?;
+        let mut data = vec![0u8; len[0] as usize + 1];
+        self.port.read_exact(&mut data)?;
+        self.wait_ack()?;
+        self.version = data[0];
+        self.commands = data[1..].to_vec();
+        Ok(())
+    }
+
+    /// Reads the product ID of the chip, e.g. 0x0410 for STM32F10x medium-density devices.
+    pub fn get_id(&mut self) -> Result<u16, Error> {
+        self.send_command(CMD_GET_ID)?;
+        let mut len = [0u8; 1];
+        self.port.read_exact(&mut len)?;
+        let mut data = vec![0u8; len[0] as usize + 1];
+        self.port.read_exact(&mut data)?;
+        self.wait_ack()?;
+        if data.len() < 2 {
+            return Err(Error::new(ErrorKind::InvalidData, "Bad product ID"));
+        }
+        Ok(u16::from_be_bytes([data[0], data[1]]))
+    }
+
+    /// Reads up to `MAX_BLOCK_SIZE` bytes of memory from `address` into `buf`.
+    pub fn read_memory(&mut self, address: u32, buf: &mut [u8]) -> Result<(), Error> {
+        check_block_len(buf.len())?;
+        self.send_command(CMD_READ_MEMORY)?;
+        self.send_address(address)?;
+        let n = (buf.len() - 1) as u8;
+        self.port.write_all(&[n, !n])?;
+        self.wait_ack()?;
+        self.port.read_exact(buf)
+    }
+
+    /// Writes up to `MAX_BLOCK_SIZE` bytes to memory at `address`. The data is padded
+    /// with 0xFF to a multiple of 4 bytes.
+    pub fn write_memory(&mut self, address: u32, data: &[u8]) -> Result<(), Error> {
+        check_block_len(data.len())?;
+        let mut block = data.to_vec();
+        block.resize(data.len().div_ceil(4) * 4, 0xFF);
+        self.send_command(CMD_WRITE_MEMORY)?;
+        self.send_address(address)?;
+        let n = (block.len() - 1) as u8;
+        let checksum = block.iter().fold(n, |c, b| c ^ b);
+        let mut frame = Vec::with_capacity(block.len() + 2);
+        frame.push(n);
+        frame.extend_from_slice(&block);
+        frame.push(checksum);
+        self.port.write_all(&frame)?;
+        self.wait_ack()
+    }
+
+    /// Writes the whole firmware image from `address` block by block, calling `progress`
+    /// with the amount of bytes written after each block.
+    pub fn write_image(
+        &mut self,
+        address: u32,
+        image: &[u8],
+        mut progress: impl FnMut(usize),
+    ) -> Result<(), Error> {
+        for (i, block) in image.chunks(MAX_BLOCK_SIZE).enumerate() {
+            let offset = i * MAX_BLOCK_SIZE;
+            self.write_memory(address + offset as u32, block)?;
+            progress(offset + block.len());
+        }
+        Ok(())
+    }
+
+    /// Erases the given flash pages, or the whole flash memory if `pages` is empty.
+    /// The `Extended Erase` command is used if it is supported by the bootloader.
+    pub fn erase(&mut self, pages: &[u16]) -> Result<(), Error> {
+        if self.commands.contains(&CMD_EXTENDED_ERASE) {
+            self.send_command(CMD_EXTENDED_ERASE)?;
+            if pages.is_empty() {
+                self.port.write_all(&[0xFF, 0xFF, 0x00])?; // mass erase
+            } else {
+                let mut frame = ((pages.len() - 1) as u16).to_be_bytes().to_vec();
+                for page in pages {
+                    frame.extend_from_slice(&page.to_be_bytes());
+                }
+                frame.push(frame.iter().fold(0, |c, b| c ^ b));
+                self.port.write_all(&frame)?;
+            }
+        } else {
+            self.send_command(CMD_ERASE)?;
+            if pages.is_empty() {
+                self.port.write_all(&[0xFF, 0x00])?; // global erase
+            } else {
+                if pages.len() > 256 || pages.iter().any(|&p| p > 0xFF) {
+                    return Err(Error::new(
+                        ErrorKind::InvalidInput,
+                        "Page number out of range for the `Erase` command",
+                    ));
+                }
+                let mut frame = vec![(pages.len() - 1) as u8];
+                frame.extend(pages.iter().map(|&p| p as u8));
+                frame.push(frame.iter().fold(0, |c, b| c ^ b));
+                self.port.write_all(&frame)?;
+            }
+        }
+        self.wait_ack()
+    }
+
+    /// Jumps to the application code at `address` (usually the flash start 0x08000000).
+    pub fn go(&mut self, address: u32) -> Result<(), Error> {
+        self.send_command(CMD_GO)?;
+        self.send_address(address)
+    }
+
+    fn send_command(&mut self, cmd: u8) -> Result<(), Error> {
+        self.port.write_all(&[cmd, !cmd])?;
+        self.wait_ack()
+    }
+
+    fn send_address(&mut self, address: u32) -> Result<(), Error> {
+        let bytes = address.to_be_bytes();
+        let checksum = bytes.iter().fold(0, |c, b| c ^ b);
+        self.port
+            .write_all(&[bytes[0], bytes[1], bytes[2], bytes[3], checksum])?;
+        self.wait_ack()
+    }
+
+    fn wait_ack(&mut self) -> Result<(), Error> {
+        let mut byte = [0u8; 1];
+        self.port.read_exact(&mut byte)?;
+        match byte[0] {
+            ACK => Ok(()),
+            NACK => Err(Error::other("NACK received from the bootloader")),
+            _ => Err(Error::new(
+                ErrorKind::InvalidData,
+                "Unexpected byte received from the bootloader",
+            )),
+        }
+    }
+}
+
+fn check_block_len(len: usize) -> Result<(), Error> {
+    if len == 0 || len > MAX_BLOCK_SIZE {
+        Err(Error::new(ErrorKind::InvalidInput, "Bad block length"))
+    } else {
+        Ok(())
+    }
+}
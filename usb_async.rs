@@ -0,0 +1,260 @@
+//! Asynchronous wrappers of `nusb` transfer queues implementing `futures-io` traits.
+
+use std::{
+    io::ErrorKind,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_io::{AsyncBufRead, AsyncRead, AsyncWrite};
+use nusb::transfer::{Queue, RequestBuffer, TransferError};
+
+use crate::usb::map_transfer_error;
+use crate::Error;
+
+type ReadQueue = Queue<RequestBuffer>;
+type WriteQueue = Queue<Vec<u8>>;
+
+/// Asynchronous wrapper of a `nusb` IN transfer queue, implementing `AsyncRead` and
+/// `AsyncBufRead` with an internal staging buffer, so that combinators like `read_line()`
+/// and `lines()` work directly for text-based devices.
+pub struct AsyncReader {
+    queue: ReadQueue,
+    buf: Vec<u8>, // data of the latest completed transfer
+    pos: usize,   // amount of consumed bytes in `buf`
+    transfer_size: usize,
+    num_transfers: usize,
+}
+
+impl AsyncReader {
+    /// Wraps the queue, which keeps `num_transfers` transfers of `transfer_size` bytes
+    /// submitted while reading. `transfer_size` should be a multiple of the max packet size.
+    pub fn new(queue: ReadQueue, transfer_size: usize, num_transfers: usize) -> Self {
+        Self {
+            queue,
+            buf: Vec::new(),
+            pos: 0,
+            transfer_size,
+            num_transfers: num_transfers.max(1),
+        }
+    }
+
+    /// Returns the amount of received bytes not consumed yet.
+    pub fn buffered_len(&self) -> usize {
+        self.buf.len() - self.pos
+    }
+
+    /// Polls for received data without consuming it, returns the amount of bytes available.
+    /// This is `poll_fill_buf()` without the pinned receiver.
+    pub fn poll_readable(&mut self, cx: &mut Context<'_>) -> Poll<std::io::Result<usize>> {
+        Pin::new(self).poll_fill_buf(cx).map_ok(|data| data.len())
+    }
+
+    /// Reads received data into `buf`. This is `AsyncRead::poll_read()` without the pinned
+    /// receiver, for custom executors and combinators.
+    pub fn poll_read(
+        &mut self,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<std::io::Result<usize>> {
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        let data = match Pin::new(&mut *self).poll_fill_buf(cx) {
+            Poll::Ready(Ok(data)) => data,
+            Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+            Poll::Pending => return Poll::Pending,
+        };
+        let len = data.len().min(buf.len());
+        buf[..len].copy_from_slice(&data[..len]);
+        Pin::new(self).consume(len);
+        Poll::Ready(Ok(len))
+    }
+
+    /// Waits until some data is received without consuming it, returns the amount of
+    /// bytes available. Wrap it with a timer of the async runtime for a timeout.
+    pub async fn wait_readable(&mut self) -> std::io::Result<usize> {
+        std::future::poll_fn(|cx| self.poll_readable(cx)).await
+    }
+
+    /// Takes the queue back. Buffered data is dropped, pending transfers are not cancelled.
+    pub fn into_inner(self) -> ReadQueue {
+        self.queue
+    }
+}
+
+impl From<ReadQueue> for AsyncReader {
+    /// Uses transfers of 4 KiB, and keeps 2 transfers submitted.
+    fn from(value: ReadQueue) -> Self {
+        Self::new(value, 4096, 2)
+    }
+}
+
+impl AsyncBufRead for AsyncReader {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<&[u8]>> {
+        let this = self.get_mut();
+        while this.pos >= this.buf.len() {
+            while this.queue.pending() < this.num_transfers {
+                let buf_old = std::mem::take(&mut this.buf);
+                this.queue
+                    .submit(RequestBuffer::reuse(buf_old, this.transfer_size));
+            }
+            let comp = match this.queue.poll_next(cx) {
+                Poll::Ready(comp) => comp,
+                Poll::Pending => return Poll::Pending,
+            };
+            if let Err(TransferError::Stall) = comp.status {
+                let _ = this.queue.clear_halt();
+            }
+            this.buf = comp.data;
+            this.pos = 0;
+            match comp.status {
+                _ if !this.buf.is_empty() => (),
+                Ok(()) => (), // zero-length packet
+                Err(TransferError::Disconnected) => {
+                    return Poll::Ready(Err(Error::from(ErrorKind::NotConnected)))
+                }
+                Err(e) => return Poll::Ready(Err(map_transfer_error(e))),
+            }
+        }
+        Poll::Ready(Ok(&this.buf[this.pos..]))
+    }
+
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        let this = self.get_mut();
+        this.pos = (this.pos + amt).min(this.buf.len());
+    }
+}
+
+impl AsyncRead for AsyncReader {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<std::io::Result<usize>> {
+        AsyncReader::poll_read(self.get_mut(), cx, buf)
+    }
+}
+
+/// Asynchronous wrapper of a `nusb` OUT transfer queue, implementing `AsyncWrite`.
+///
+/// At most `max_in_flight` transfers are submitted at the same time; `poll_write()`
+/// returns `Pending` when the window is full, so a fast producer can't queue unbounded
+/// buffers when the device is slow. Errors of completed transfers are returned on the
+/// next `poll_write()` or `poll_flush()`.
+pub struct AsyncWriter {
+    queue: WriteQueue,
+    max_in_flight: usize,
+    spare: Vec<Vec<u8>>, // buffers of completed transfers for reuse
+    error: Option<Error>,
+}
+
+impl AsyncWriter {
+    /// Wraps the queue.
+    pub fn new(queue: WriteQueue, max_in_flight: usize) -> Self {
+        Self {
+            queue,
+            max_in_flight: max_in_flight.max(1),
+            spare: Vec::new(),
+            error: None,
+        }
+    }
+
+    /// Returns the amount of submitted transfers which haven't completed.
+    pub fn in_flight(&self) -> usize {
+        self.queue.pending()
+    }
+
+    /// Checks if a new buffer can be submitted without exceeding the window.
+    /// Errors of completed transfers are returned here.
+    pub fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        self.reap_ready(cx);
+        if let Some(e) = self.error.take() {
+            return Poll::Ready(Err(e));
+        }
+        if self.queue.pending() < self.max_in_flight {
+            Poll::Ready(Ok(()))
+        } else {
+            Poll::Pending
+        }
+    }
+
+    /// Submits a copy of `buf` as a transfer. This is `AsyncWrite::poll_write()` without
+    /// the pinned receiver, for custom executors and combinators.
+    pub fn poll_write(&mut self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<std::io::Result<usize>> {
+        match self.poll_ready(cx) {
+            Poll::Ready(Ok(())) => (),
+            Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+            Poll::Pending => return Poll::Pending,
+        }
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        let mut data = self.spare.pop().unwrap_or_default();
+        data.clear();
+        data.extend_from_slice(buf);
+        self.queue.submit(data);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    /// Waits until all submitted transfers have completed. This is `AsyncWrite::poll_flush()`
+    /// without the pinned receiver.
+    pub fn poll_flush(&mut self, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        self.reap_ready(cx);
+        if let Some(e) = self.error.take() {
+            return Poll::Ready(Err(e));
+        }
+        if self.queue.pending() > 0 {
+            Poll::Pending
+        } else {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    /// Takes the queue back. Pending transfers are not cancelled.
+    pub fn into_inner(self) -> WriteQueue {
+        self.queue
+    }
+
+    /// Takes all completed transfers without waiting.
+    fn reap_ready(&mut self, cx: &mut Context<'_>) {
+        while self.queue.pending() > 0 {
+            let Poll::Ready(comp) = self.queue.poll_next(cx) else {
+                break;
+            };
+            if let Err(e) = comp.status {
+                if let TransferError::Stall = e {
+                    let _ = self.queue.clear_halt();
+                }
+                self.error.get_or_insert(map_transfer_error(e));
+            }
+            self.spare.push(comp.data.reuse());
+        }
+    }
+}
+
+impl From<WriteQueue> for AsyncWriter {
+    /// Keeps at most 4 transfers in flight.
+    fn from(value: WriteQueue) -> Self {
+        Self::new(value, 4)
+    }
+}
+
+impl AsyncWrite for AsyncWriter {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        AsyncWriter::poll_write(self.get_mut(), cx, buf)
+    }
+
+    /// Waits until all submitted transfers have completed.
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        AsyncWriter::poll_flush(self.get_mut(), cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        self.poll_flush(cx)
+    }
+}
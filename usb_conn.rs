@@ -1,18 +1,33 @@
-use jni::objects::JObject;
+use jni::objects::{GlobalRef, JObject};
 use jni_min_helper::*;
 
 use crate::Error;
-use futures_lite::StreamExt;
-use std::{io::ErrorKind, pin::Pin, task, time::Duration};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    io::ErrorKind,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    time::{Duration, Instant},
+};
+#[cfg(feature = "async")]
+use std::{pin::Pin, task};
 
-use crate::usb::{jerr, list_devices, DeviceInfo};
+use crate::usb::{jerr, list_devices, with_local_frame, AccessoryInfo, DeviceFilter, DeviceInfo};
 
 const USB_SERVICE: &str = "usb";
 const ACTION_USB_DEVICE_ATTACHED: &str = "android.hardware.usb.action.USB_DEVICE_ATTACHED";
 const ACTION_USB_DEVICE_DETACHED: &str = "android.hardware.usb.action.USB_DEVICE_DETACHED";
+const ACTION_USB_ACCESSORY_ATTACHED: &str = "android.hardware.usb.action.USB_ACCESSORY_ATTACHED";
+const ACTION_USB_ACCESSORY_DETACHED: &str = "android.hardware.usb.action.USB_ACCESSORY_DETACHED";
 const EXTRA_DEVICE: &str = "device";
+const EXTRA_ACCESSORY: &str = "accessory";
+const CLASS_USB_DEVICE: &str = "android/hardware/usb/UsbDevice";
+const CLASS_USB_ACCESSORY: &str = "android/hardware/usb/UsbAccessory";
 const ACTION_USB_PERMISSION: &str = "rust.android_usbser.USB_PERMISSION"; // custom
 const EXTRA_PERMISSION_GRANTED: &str = "permission";
+const FEATURE_USB_HOST: &str = "android.hardware.usb.host";
 
 /// Gets a global reference of `android.hardware.usb.UsbManager`.
 #[inline(always)]
@@ -50,6 +65,79 @@ fn get_usb_manager() -> Result<jni::objects::GlobalRef, Error> {
     }
 }
 
+/// Initializes the Android context used by this crate, for apps loading the Rust library
+/// as a plain JNI library without `android_activity` (e.g. Kotlin-first apps). Call it
+/// from a native method before any other function of this crate; the application context
+/// (`Context.getApplicationContext()`) is preferred. A global reference of `context` is
+/// kept until the process exits. Returns `AlreadyExists` error if it is called again.
+///
+/// Note: If the context of `ndk-context` is already initialized (e.g. by `android_activity`
+/// or another crate), it is kept, `context` is not used and `Ok(())` is returned. This is
+/// checked by catching the panic of `ndk_context::android_context()`, which requires
+/// `panic = "unwind"`.
+pub fn init_with(env: &mut jni::JNIEnv<'_>, context: &JObject<'_>) -> Result<(), Error> {
+    use std::sync::OnceLock;
+    static CONTEXT: OnceLock<GlobalRef> = OnceLock::new();
+    if CONTEXT.get().is_none() && std::panic::catch_unwind(ndk_context::android_context).is_ok() {
+        return Ok(()); // initialized by others
+    }
+    let vm = env.get_java_vm().map_err(jerr)?;
+    let context = env.new_global_ref(context).map_err(jerr)?;
+    if CONTEXT.set(context).is_err() {
+        return Err(Error::new(
+            ErrorKind::AlreadyExists,
+            "The Android context is already initialized",
+        ));
+    }
+    let context = CONTEXT.get().unwrap();
+    // Safety: both pointers are valid until the process exits.
+    unsafe {
+        ndk_context::initialize_android_context(
+            vm.get_java_vm_pointer() as *mut std::ffi::c_void,
+            context.as_obj().as_raw() as *mut std::ffi::c_void,
+        );
+    }
+    Ok(())
+}
+
+/// Checks if the Android device supports USB host mode, which means the system feature
+/// `android.hardware.usb.host` is declared and the `UsbManager` service exists.
+///
+/// Note: Call it on startup to tell the user that the device cannot work as a USB host,
+/// instead of failing later with confusing errors.
+pub fn host_mode_supported() -> Result<bool, Error> {
+    let env = &mut jni_attach_vm().map_err(jerr)?;
+    let context = android_context();
+
+    let package_man = env
+        .call_method(
+            context,
+            "getPackageManager",
+            "()Landroid/content/pm/PackageManager;",
+            &[],
+        )
+        .get_object(env)
+        .map_err(jerr)?;
+    let feature = FEATURE_USB_HOST.new_jobject(env).map_err(jerr)?;
+    let has_feature = env
+        .call_method(
+            &package_man,
+            "hasSystemFeature",
+            "(Ljava/lang/String;)Z",
+            &[(&feature).into()],
+        )
+        .get_boolean()
+        .map_err(jerr)?;
+    if !has_feature {
+        return Ok(false);
+    }
+    match usb_manager() {
+        Ok(_) => Ok(true),
+        Err(e) if e.kind() == ErrorKind::Unsupported => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
 /// Checks if the Android context is an activity opened by an intent of
 /// `android.hardware.usb.action.USB_DEVICE_ATTACHED`. If so, it takes the `DeviceInfo`
 /// for the caller to open the device.
@@ -90,41 +178,380 @@ pub fn check_attached_intent() -> Result<DeviceInfo, Error> {
     }
 }
 
+/// Standard priority of application threads (`android.os.Process`).
+pub const THREAD_PRIORITY_DEFAULT: i32 = 0;
+/// Priority of background threads (`android.os.Process`).
+pub const THREAD_PRIORITY_BACKGROUND: i32 = 10;
+/// Priority of threads running foreground work (`android.os.Process`).
+pub const THREAD_PRIORITY_FOREGROUND: i32 = -2;
+/// Priority of important audio threads (`android.os.Process`).
+pub const THREAD_PRIORITY_AUDIO: i32 = -16;
+/// Priority of the most important audio threads (`android.os.Process`).
+pub const THREAD_PRIORITY_URGENT_AUDIO: i32 = -19;
+
+/// Sets the Android (Linux nice) priority of the current thread by
+/// `android.os.Process.setThreadPriority()`, useful for I/O threads doing high-rate
+/// transfers. Lower value means higher priority; check `THREAD_PRIORITY_*` constants.
+pub fn set_current_thread_priority(priority: i32) -> Result<(), Error> {
+    let env = &mut jni_attach_vm().map_err(jerr)?;
+    env.call_static_method(
+        "android/os/Process",
+        "setThreadPriority",
+        "(I)V",
+        &[priority.into()],
+    )
+    .map(|_| ())
+    .map_err(jerr)
+}
+
 fn get_extra_device(intent: &JObject<'_>) -> Result<DeviceInfo, Error> {
     let env = &mut jni_attach_vm().map_err(jerr)?;
-    let extra_device = EXTRA_DEVICE.new_jobject(env).map_err(jerr)?;
-    let java_dev = env
-        .call_method(
-            intent,
-            "getParcelableExtra",
-            // TODO: this is deprecated in API 33 and above without the class parameter.
-            "(Ljava/lang/String;)Landroid/os/Parcelable;",
-            &[(&extra_device).into()],
-        )
-        .get_object(env)
-        .map_err(jerr)?;
+    let java_dev = get_parcelable_extra(env, intent, EXTRA_DEVICE, CLASS_USB_DEVICE)?;
+    DeviceInfo::build(env, &java_dev)
+}
+
+fn get_extra_accessory(intent: &JObject<'_>) -> Result<AccessoryInfo, Error> {
+    let env = &mut jni_attach_vm().map_err(jerr)?;
+    let java_acc = get_parcelable_extra(env, intent, EXTRA_ACCESSORY, CLASS_USB_ACCESSORY)?;
+    AccessoryInfo::build(env, &java_acc)
+}
 
-    if !java_dev.is_null() {
-        DeviceInfo::build(env, &java_dev)
+/// Gets the extra of `name` and `class`. The typed `getParcelableExtra()` is used since
+/// API 33, in which the untyped version is deprecated.
+fn get_parcelable_extra<'a>(
+    env: &mut jni::JNIEnv<'a>,
+    intent: &JObject<'_>,
+    name: &str,
+    class: &str,
+) -> Result<JObject<'a>, Error> {
+    let extra_name = name.new_jobject(env).map_err(jerr)?;
+    let typed = if android_api_level() >= 33 {
+        env.find_class(class)
+            .and_then(|class| {
+                env.call_method(
+                    intent,
+                    "getParcelableExtra",
+                    "(Ljava/lang/String;Ljava/lang/Class;)Ljava/lang/Object;",
+                    &[(&extra_name).into(), (&class).into()],
+                )
+            })
+            .get_object(env)
+            .map_err(jni_clear_ex)
+            .ok()
+    } else {
+        None
+    };
+    let obj = match typed {
+        Some(obj) => obj,
+        None => env
+            .call_method(
+                intent,
+                "getParcelableExtra",
+                "(Ljava/lang/String;)Landroid/os/Parcelable;",
+                &[(&extra_name).into()],
+            )
+            .get_object(env)
+            .map_err(jerr)?,
+    };
+
+    if !obj.is_null() {
+        Ok(obj)
     } else {
         Err(Error::new(
             ErrorKind::NotFound,
-            "Unexpected: the Intent has no EXTRA_DEVICE",
+            format!("Unexpected: the Intent has no extra `{name}`"),
         ))
     }
 }
 
+/// Registers a broadcast receiver of given actions, `handler` is called in the main thread.
+/// Unregister it by `unregister_receiver()`.
+///
+/// Note: It is registered with `RECEIVER_NOT_EXPORTED` on API 33+, because the actions are
+/// either protected system broadcasts or sent through `PendingIntent`s of this app.
+pub(crate) fn register_receiver<'a>(
+    actions: impl IntoIterator<Item = &'a str>,
+    handler: impl Fn(&mut jni::JNIEnv, &JObject<'_>) -> Result<(), jni::errors::Error>
+        + Send
+        + Sync
+        + 'static,
+) -> Result<BroadcastReceiver, Error> {
+    const RECEIVER_NOT_EXPORTED: i32 = 0x4;
+    let receiver = BroadcastReceiver::build(move |env, _context, intent| handler(env, intent))
+        .map_err(jerr)?;
+    let env = &mut jni_attach_vm().map_err(jerr)?;
+    let filter = new_intent_filter(env, actions)?;
+    if android_api_level() < 33 {
+        receiver.register(&filter).map_err(jerr)?;
+        return Ok(receiver);
+    }
+    let receiver_obj: &JObject<'_> = receiver.as_ref();
+    env.call_method(
+        android_context(),
+        "registerReceiver",
+        "(Landroid/content/BroadcastReceiver;Landroid/content/IntentFilter;I)Landroid/content/Intent;",
+        &[receiver_obj.into(), (&filter).into(), RECEIVER_NOT_EXPORTED.into()],
+    )
+    .get_object(env)
+    .map_err(jerr)?;
+    Ok(receiver)
+}
+
+/// Unregisters the receiver registered by `register_receiver()`.
+/// Unregistering a receiver which is not registered does nothing.
+pub(crate) fn unregister_receiver(receiver: &BroadcastReceiver) {
+    let Ok(env) = &mut jni_attach_vm() else {
+        return;
+    };
+    let receiver_obj: &JObject<'_> = receiver.as_ref();
+    // `IllegalArgumentException` is thrown if it is not registered
+    let _ = env
+        .call_method(
+            android_context(),
+            "unregisterReceiver",
+            "(Landroid/content/BroadcastReceiver;)V",
+            &[receiver_obj.into()],
+        )
+        .map_err(jni_clear_ex_silent);
+}
+
+fn new_intent_filter<'a, 'e>(
+    env: &mut jni::JNIEnv<'e>,
+    actions: impl IntoIterator<Item = &'a str>,
+) -> Result<jni::objects::AutoLocal<'e, JObject<'e>>, Error> {
+    let filter = env
+        .new_object("android/content/IntentFilter", "()V", &[])
+        .auto_local(env)
+        .map_err(jerr)?;
+    for action in actions {
+        let action = action.new_jobject(env).map_err(jerr)?;
+        env.call_method(
+            &filter,
+            "addAction",
+            "(Ljava/lang/String;)V",
+            &[(&action).into()],
+        )
+        .map_err(jerr)?;
+    }
+    Ok(filter)
+}
+
+/// Device list kept by `list_devices_cached()`.
+struct DeviceCache {
+    receiver: Option<BroadcastReceiver>, // sets `DEVICE_CACHE_DIRTY` on attach/detach
+    devices: Option<Vec<DeviceInfo>>,
+}
+
+static DEVICE_CACHE: Mutex<DeviceCache> = Mutex::new(DeviceCache {
+    receiver: None,
+    devices: None,
+});
+static DEVICE_CACHE_DIRTY: AtomicBool = AtomicBool::new(true);
+
+/// Returns the device list cached since the latest attach/detach broadcast, so that
+/// `getDeviceList()` is not walked again in steady state. It calls `list_devices()`
+/// every time if the broadcast receiver cannot be registered.
+///
+/// Note: Broadcasts are received in the main thread; the cache may be outdated
+/// while the main thread is blocked. Call `invalidate_device_cache()` if in doubt.
+pub fn list_devices_cached() -> Result<Vec<DeviceInfo>, Error> {
+    let mut cache = DEVICE_CACHE.lock().unwrap_or_else(|e| e.into_inner());
+    if cache.receiver.is_none() {
+        cache.receiver = register_receiver(
+            [ACTION_USB_DEVICE_ATTACHED, ACTION_USB_DEVICE_DETACHED],
+            |_, _| {
+                DEVICE_CACHE_DIRTY.store(true, Ordering::Release);
+                Ok(())
+            },
+        )
+        .ok();
+        if cache.receiver.is_none() {
+            return list_devices();
+        }
+    }
+    // cleared before listing, so a broadcast received meanwhile is not missed
+    if DEVICE_CACHE_DIRTY.swap(false, Ordering::AcqRel) || cache.devices.is_none() {
+        match list_devices() {
+            Ok(devices) => cache.devices = Some(devices),
+            Err(e) => {
+                DEVICE_CACHE_DIRTY.store(true, Ordering::Release);
+                return Err(e);
+            }
+        }
+    }
+    Ok(cache.devices.clone().unwrap_or_default())
+}
+
+/// Makes the next `list_devices_cached()` call enumerate the devices again.
+pub fn invalidate_device_cache() {
+    DEVICE_CACHE_DIRTY.store(true, Ordering::Release);
+}
+
+/// Events shared between the producer and the consumer, so that they can be taken
+/// with or without an asynchronous executor.
+pub(crate) struct EventQueue<T> {
+    events: Mutex<VecDeque<T>>,
+    cond: Condvar,
+    #[cfg(feature = "async")]
+    waker: Mutex<Option<task::Waker>>,
+}
+
+impl<T> Default for EventQueue<T> {
+    fn default() -> Self {
+        Self {
+            events: Mutex::new(VecDeque::new()),
+            cond: Condvar::new(),
+            #[cfg(feature = "async")]
+            waker: Mutex::new(None),
+        }
+    }
+}
+
+impl<T> EventQueue<T> {
+    pub(crate) fn push(&self, event: T) {
+        self.events.lock().unwrap().push_back(event);
+        self.cond.notify_all();
+        #[cfg(feature = "async")]
+        if let Some(waker) = self.waker.lock().unwrap().take() {
+            waker.wake();
+        }
+    }
+
+    pub(crate) fn count(&self) -> usize {
+        self.events.lock().unwrap().len()
+    }
+
+    pub(crate) fn pop(&self) -> Option<T> {
+        self.events.lock().unwrap().pop_front()
+    }
+
+    /// Waits for an event; returns directly if an event is available.
+    pub(crate) fn wait(&self, timeout: Duration) -> Option<T> {
+        let events = self.events.lock().ok()?;
+        let (mut events, _) = self
+            .cond
+            .wait_timeout_while(events, timeout, |q| q.is_empty())
+            .ok()?;
+        events.pop_front()
+    }
+
+    #[cfg(feature = "async")]
+    pub(crate) fn poll_pop(&self, cx: &mut task::Context<'_>) -> task::Poll<T> {
+        // the waker is stored before checking to avoid missing the wake-up
+        self.waker.lock().unwrap().replace(cx.waker().clone());
+        self.pop().map_or(task::Poll::Pending, task::Poll::Ready)
+    }
+}
+
+/// Receives broadcasted intents of given actions and keeps them in a queue,
+/// so that they can be taken with or without an asynchronous executor.
+struct IntentQueue {
+    receiver: BroadcastReceiver,
+    shared: Arc<EventQueue<GlobalRef>>,
+}
+
+impl IntentQueue {
+    fn build<'a>(actions: impl IntoIterator<Item = &'a str>) -> Result<Self, Error> {
+        Self::build_filtered(actions, |_| true)
+    }
+
+    /// Only intents accepted by `accept` are queued, so consumers are not woken up by others.
+    fn build_filtered<'a>(
+        actions: impl IntoIterator<Item = &'a str>,
+        accept: impl Fn(&JObject<'_>) -> bool + Send + Sync + 'static,
+    ) -> Result<Self, Error> {
+        let shared = Arc::new(EventQueue::default());
+        let shared_recv = shared.clone();
+        let receiver = register_receiver(actions, move |env, intent| {
+            if accept(intent) {
+                shared_recv.push(env.new_global_ref(intent)?);
+            }
+            Ok(())
+        })?;
+        Ok(Self { receiver, shared })
+    }
+
+    fn count(&self) -> usize {
+        self.shared.count()
+    }
+
+    fn pop(&self) -> Option<GlobalRef> {
+        self.shared.pop()
+    }
+
+    /// Waits for an intent; returns directly if an intent is available.
+    fn wait(&self, timeout: Duration) -> Option<GlobalRef> {
+        self.shared.wait(timeout)
+    }
+
+    #[cfg(feature = "async")]
+    fn poll_pop(&self, cx: &mut task::Context<'_>) -> task::Poll<GlobalRef> {
+        self.shared.poll_pop(cx)
+    }
+
+    fn unregister(&self) {
+        unregister_receiver(&self.receiver);
+    }
+}
+
+impl std::fmt::Debug for IntentQueue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("IntentQueue")
+            .field("received", &self.count())
+            .finish_non_exhaustive()
+    }
+}
+
 /// Gets a watcher of device connection / disconnection events.
 pub fn watch_devices() -> Result<HotplugWatch, Error> {
-    BroadcastWaiter::build([ACTION_USB_DEVICE_ATTACHED, ACTION_USB_DEVICE_DETACHED])
-        .map(|waiter| HotplugWatch { waiter })
-        .map_err(jerr)
+    IntentQueue::build([ACTION_USB_DEVICE_ATTACHED, ACTION_USB_DEVICE_DETACHED]).map(|queue| {
+        HotplugWatch {
+            queue,
+            filter: None,
+            initial: VecDeque::new(),
+        }
+    })
+}
+
+/// Gets a watcher of device and accessory connection / disconnection events, for apps
+/// acting as both a USB host and an Android Open Accessory device.
+///
+/// Note: `USB_ACCESSORY_ATTACHED` is usually delivered to the activity only.
+pub fn watch_devices_and_accessories() -> Result<HotplugWatch, Error> {
+    IntentQueue::build([
+        ACTION_USB_DEVICE_ATTACHED,
+        ACTION_USB_DEVICE_DETACHED,
+        ACTION_USB_ACCESSORY_ATTACHED,
+        ACTION_USB_ACCESSORY_DETACHED,
+    ])
+    .map(|queue| HotplugWatch {
+        queue,
+        filter: None,
+        initial: VecDeque::new(),
+    })
+}
+
+/// Gets a watcher of connection / disconnection events of devices matching `filter`;
+/// events of other devices are dropped in the broadcast receiver.
+pub fn watch_devices_filtered(filter: DeviceFilter) -> Result<HotplugWatch, Error> {
+    let filter_kept = filter.clone();
+    IntentQueue::build_filtered(
+        [ACTION_USB_DEVICE_ATTACHED, ACTION_USB_DEVICE_DETACHED],
+        move |intent| get_extra_device(intent).is_ok_and(|dev| filter.matches(&dev)),
+    )
+    .map(|queue| HotplugWatch {
+        queue,
+        filter: Some(filter_kept),
+        initial: VecDeque::new(),
+    })
 }
 
 /// Stream of device connection / disconnection events.
 #[derive(Debug)]
 pub struct HotplugWatch {
-    waiter: BroadcastWaiter,
+    queue: IntentQueue,
+    filter: Option<DeviceFilter>,  // applied to the initial snapshot
+    initial: VecDeque<DeviceInfo>, // synthetic `Connected` events to be taken first
 }
 
 /// Event returned from the `HotplugWatch` stream.
@@ -132,85 +559,379 @@ pub struct HotplugWatch {
 pub enum HotplugEvent {
     Connected(DeviceInfo),
     Disconnected(DeviceInfo),
-}
-
-#[derive(Debug)]
-struct HotplugWatchFuture<'a> {
-    watch: &'a mut HotplugWatch,
+    /// Reported only by the watcher from `watch_devices_and_accessories()`.
+    AccessoryConnected(AccessoryInfo),
+    /// Reported only by the watcher from `watch_devices_and_accessories()`.
+    AccessoryDisconnected(AccessoryInfo),
 }
 
 impl HotplugWatch {
+    /// Emits synthetic `Connected` events for devices already connected (matching the
+    /// filter of `watch_devices_filtered()`) before received events, so apps don't need
+    /// to call `list_devices()` separately and race with new events.
+    ///
+    /// Note: A device connected while the watcher is being created may be reported twice.
+    pub fn with_initial_snapshot(mut self) -> Result<Self, Error> {
+        self.initial = list_devices()?
+            .into_iter()
+            .filter(|dev| self.filter.as_ref().map_or(true, |f| f.matches(dev)))
+            .collect();
+        Ok(self)
+    }
+
     /// Returns the amount of received events available for checking.
     pub fn count_available(&self) -> usize {
-        self.waiter.count_received()
+        self.initial.len() + self.queue.count()
     }
 
     /// Takes the next received event if available. This shouldn't conflict
     /// with the asynchonous feature (which requires a mutable reference).
     pub fn take_next(&mut self) -> Option<HotplugEvent> {
-        (self.count_available() > 0).then_some(())?;
-        self.wait_blocking(Duration::from_millis(1))
+        if let Some(dev) = self.initial.pop_front() {
+            return Some(HotplugEvent::Connected(dev));
+        }
+        while let Some(intent) = self.queue.pop() {
+            if let Some(event) = hotplug_event(intent.as_obj()) {
+                return Some(event);
+            }
+        }
+        None
     }
 
     /// Waits for receiving an event; returns directly if an event is available.
     /// Note: Waiting in the `android_main()` thread will prevent it from receiving.
     pub fn wait_blocking(&mut self, timeout: Duration) -> Option<HotplugEvent> {
-        let fut = HotplugWatchFuture { watch: self };
-        block_for_timeout(fut, timeout)
+        if let Some(dev) = self.initial.pop_front() {
+            return Some(HotplugEvent::Connected(dev));
+        }
+        let t_start = Instant::now();
+        loop {
+            let intent = self.queue.wait(timeout.saturating_sub(t_start.elapsed()))?;
+            if let Some(event) = hotplug_event(intent.as_obj()) {
+                return Some(event);
+            }
+        }
+    }
+}
+
+/// Converts the received intent to `HotplugEvent`. Returns `None` on unexpected errors.
+fn hotplug_event(intent: &JObject<'_>) -> Option<HotplugEvent> {
+    let env = &mut jni_attach_vm().ok()?;
+    let action = BroadcastReceiver::get_intent_action(intent, env).ok()?;
+    match action.trim() {
+        ACTION_USB_DEVICE_ATTACHED => get_extra_device(intent).ok().map(HotplugEvent::Connected),
+        ACTION_USB_DEVICE_DETACHED => get_extra_device(intent)
+            .ok()
+            .map(HotplugEvent::Disconnected),
+        ACTION_USB_ACCESSORY_ATTACHED => get_extra_accessory(intent)
+            .ok()
+            .map(HotplugEvent::AccessoryConnected),
+        ACTION_USB_ACCESSORY_DETACHED => get_extra_accessory(intent)
+            .ok()
+            .map(HotplugEvent::AccessoryDisconnected),
+        _ => None,
     }
 }
 
+#[cfg(feature = "async")]
 impl futures_core::Stream for HotplugWatch {
     type Item = HotplugEvent;
 
     fn poll_next(
-        mut self: Pin<&mut Self>,
+        self: Pin<&mut Self>,
         cx: &mut task::Context<'_>,
     ) -> task::Poll<Option<Self::Item>> {
-        // `BroadcastWaiter` implementation makes `Ready(None)` impossible here
-        if let task::Poll::Ready(Some(intent)) = self.waiter.poll_next(cx) {
-            let Ok(env) = &mut jni_attach_vm() else {
-                return task::Poll::Ready(None); // almost impossible
-            };
-            let Ok(action) = BroadcastWaiter::get_intent_action(&intent, env) else {
-                return task::Poll::Ready(None); // almost impossible
-            };
-            match action.trim() {
-                ACTION_USB_DEVICE_ATTACHED => {
-                    let Ok(dev) = get_extra_device(intent.as_obj()) else {
-                        return task::Poll::Ready(None);
-                    };
-                    task::Poll::Ready(Some(HotplugEvent::Connected(dev)))
+        let this = self.get_mut();
+        if let Some(dev) = this.initial.pop_front() {
+            return task::Poll::Ready(Some(HotplugEvent::Connected(dev)));
+        }
+        // `IntentQueue` never ends, so `Ready(None)` is impossible here
+        while let task::Poll::Ready(intent) = this.queue.poll_pop(cx) {
+            if let Some(event) = hotplug_event(intent.as_obj()) {
+                return task::Poll::Ready(Some(event));
+            }
+        }
+        task::Poll::Pending
+    }
+}
+
+/// Calls `callback` for each device connection / disconnection event, as an alternative
+/// of `HotplugWatch` without an asynchronous executor or polling. The callback is called
+/// in a dedicated thread (not the main thread) until the returned handler is dropped.
+pub fn watch_devices_callback(
+    mut callback: impl FnMut(HotplugEvent) + Send + 'static,
+) -> Result<HotplugCallback, Error> {
+    let (sender, events) = std::sync::mpsc::channel();
+    let sender = Arc::new(Mutex::new(Some(sender)));
+    let sender_recv = sender.clone();
+    let receiver = register_receiver(
+        [ACTION_USB_DEVICE_ATTACHED, ACTION_USB_DEVICE_DETACHED],
+        move |_, intent| {
+            if let Some(event) = hotplug_event(intent) {
+                let sender = sender_recv.lock().unwrap_or_else(|e| e.into_inner());
+                if let Some(sender) = sender.as_ref() {
+                    let _ = sender.send(event);
                 }
-                ACTION_USB_DEVICE_DETACHED => {
-                    let Ok(dev) = get_extra_device(intent.as_obj()) else {
-                        return task::Poll::Ready(None);
-                    };
-                    task::Poll::Ready(Some(HotplugEvent::Disconnected(dev)))
+            }
+            Ok(())
+        },
+    )?;
+    let thread = std::thread::spawn(move || {
+        for event in events {
+            callback(event);
+        }
+    });
+    Ok(HotplugCallback {
+        receiver,
+        sender,
+        thread: Some(thread),
+    })
+}
+
+/// Handler returned by `watch_devices_callback()`. The broadcast receiver is unregistered
+/// and the dispatching thread is stopped when it is dropped.
+pub struct HotplugCallback {
+    receiver: BroadcastReceiver,
+    sender: Arc<Mutex<Option<std::sync::mpsc::Sender<HotplugEvent>>>>,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+impl Drop for HotplugCallback {
+    fn drop(&mut self) {
+        unregister_receiver(&self.receiver);
+        drop(self.sender.lock().unwrap_or_else(|e| e.into_inner()).take());
+        if let Some(thread) = self.thread.take() {
+            // it cannot be joined if it is dropped inside the callback
+            if thread.thread().id() != std::thread::current().id() {
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for HotplugCallback {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HotplugCallback").finish_non_exhaustive()
+    }
+}
+
+/// Gets a watcher of all USB events, including device connection / disconnection,
+/// permission request results and accessory connection / disconnection.
+///
+/// Note: `USB_ACCESSORY_ATTACHED` is usually delivered to the activity only.
+pub fn watch_events() -> Result<UsbEventWatch, Error> {
+    IntentQueue::build(USB_EVENT_ACTIONS).map(|queue| UsbEventWatch { queue })
+}
+
+/// Actions of intents converted to `UsbEvent`.
+pub(crate) const USB_EVENT_ACTIONS: [&str; 5] = [
+    ACTION_USB_DEVICE_ATTACHED,
+    ACTION_USB_DEVICE_DETACHED,
+    ACTION_USB_PERMISSION,
+    ACTION_USB_ACCESSORY_ATTACHED,
+    ACTION_USB_ACCESSORY_DETACHED,
+];
+
+/// Stream of all USB events, check `watch_events()`.
+#[derive(Debug)]
+pub struct UsbEventWatch {
+    queue: IntentQueue,
+}
+
+/// Event returned from the `UsbEventWatch` stream.
+#[derive(Clone, Debug)]
+pub enum UsbEvent {
+    Connected(DeviceInfo),
+    Disconnected(DeviceInfo),
+    /// Result of a permission request made by `DeviceInfo::request_permission()`.
+    Permission {
+        device: DeviceInfo,
+        granted: bool,
+    },
+    AccessoryConnected(AccessoryInfo),
+    AccessoryDisconnected(AccessoryInfo),
+}
+
+impl From<HotplugEvent> for UsbEvent {
+    fn from(value: HotplugEvent) -> Self {
+        match value {
+            HotplugEvent::Connected(dev) => Self::Connected(dev),
+            HotplugEvent::Disconnected(dev) => Self::Disconnected(dev),
+            HotplugEvent::AccessoryConnected(acc) => Self::AccessoryConnected(acc),
+            HotplugEvent::AccessoryDisconnected(acc) => Self::AccessoryDisconnected(acc),
+        }
+    }
+}
+
+impl UsbEventWatch {
+    /// Returns the amount of received events available for checking.
+    pub fn count_available(&self) -> usize {
+        self.queue.count()
+    }
+
+    /// Takes the next received event if available.
+    pub fn take_next(&mut self) -> Option<UsbEvent> {
+        while let Some(intent) = self.queue.pop() {
+            if let Some(event) = usb_event(intent.as_obj()) {
+                return Some(event);
+            }
+        }
+        None
+    }
+
+    /// Waits for receiving an event; returns directly if an event is available.
+    /// Note: Waiting in the `android_main()` thread will prevent it from receiving.
+    pub fn wait_blocking(&mut self, timeout: Duration) -> Option<UsbEvent> {
+        let t_start = Instant::now();
+        loop {
+            let intent = self.queue.wait(timeout.saturating_sub(t_start.elapsed()))?;
+            if let Some(event) = usb_event(intent.as_obj()) {
+                return Some(event);
+            }
+        }
+    }
+}
+
+/// Converts the received intent to `UsbEvent`. Returns `None` on unexpected errors.
+pub(crate) fn usb_event(intent: &JObject<'_>) -> Option<UsbEvent> {
+    let env = &mut jni_attach_vm().ok()?;
+    let action = BroadcastReceiver::get_intent_action(intent, env).ok()?;
+    match action.trim() {
+        ACTION_USB_PERMISSION => {
+            let device = get_extra_device(intent).ok()?;
+            let granted = get_permission_granted(intent)?;
+            Some(UsbEvent::Permission { device, granted })
+        }
+        _ => hotplug_event(intent).map(UsbEvent::from),
+    }
+}
+
+#[cfg(feature = "async")]
+impl futures_core::Stream for UsbEventWatch {
+    type Item = UsbEvent;
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+    ) -> task::Poll<Option<Self::Item>> {
+        while let task::Poll::Ready(intent) = self.queue.poll_pop(cx) {
+            if let Some(event) = usb_event(intent.as_obj()) {
+                return task::Poll::Ready(Some(event));
+            }
+        }
+        task::Poll::Pending
+    }
+}
+
+/// Starts a thread checking the permission of connected devices every `interval`,
+/// and gets a watcher reporting the changes, e.g. the permission granted through a system
+/// dialog triggered elsewhere, or revoked by the user. Android doesn't broadcast them
+/// except results of `DeviceInfo::request_permission()`, so they are polled here.
+///
+/// Note: Devices connected later are tracked since their first check; their initial
+/// state is not reported. The thread is stopped when the watcher is dropped.
+pub fn watch_permissions(interval: Duration) -> Result<PermissionWatch, Error> {
+    let check = |dev: DeviceInfo| {
+        let granted = dev.has_permission().unwrap_or(false);
+        (dev, granted)
+    };
+    let mut tracked: Vec<_> = list_devices_cached()?.into_iter().map(check).collect();
+    let shared = Arc::new(EventQueue::default());
+    let shared_thread = shared.clone();
+    let (stop, stop_recv) = std::sync::mpsc::channel::<()>();
+    let thread = std::thread::spawn(move || {
+        use std::sync::mpsc::RecvTimeoutError;
+        while let Err(RecvTimeoutError::Timeout) = stop_recv.recv_timeout(interval) {
+            let Ok(devices) = list_devices_cached() else {
+                continue;
+            };
+            tracked.retain(|(dev, _)| devices.iter().any(|d| d.path_name() == dev.path_name()));
+            for (dev, granted) in devices.into_iter().map(check) {
+                match tracked
+                    .iter_mut()
+                    .find(|(d, _)| d.path_name() == dev.path_name())
+                {
+                    Some((_, state)) if *state != granted => {
+                        *state = granted;
+                        shared_thread.push(PermissionEvent {
+                            device: dev,
+                            granted,
+                        });
+                    }
+                    Some(_) => (),
+                    None => tracked.push((dev, granted)),
                 }
-                _ => task::Poll::Pending,
             }
-        } else {
-            task::Poll::Pending
         }
+    });
+    Ok(PermissionWatch {
+        shared,
+        stop: Some(stop),
+        thread: Some(thread),
+    })
+}
+
+/// Permission change reported by `PermissionWatch`.
+#[derive(Clone, Debug)]
+pub struct PermissionEvent {
+    pub device: DeviceInfo,
+    pub granted: bool,
+}
+
+/// Stream of permission changes, check `watch_permissions()`.
+pub struct PermissionWatch {
+    shared: Arc<EventQueue<PermissionEvent>>,
+    stop: Option<std::sync::mpsc::Sender<()>>,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+impl PermissionWatch {
+    /// Returns the amount of reported events available for checking.
+    pub fn count_available(&self) -> usize {
+        self.shared.count()
+    }
+
+    /// Takes the next reported event if available.
+    pub fn take_next(&mut self) -> Option<PermissionEvent> {
+        self.shared.pop()
     }
 
-    fn size_hint(&self) -> (usize, Option<usize>) {
-        self.waiter.size_hint()
+    /// Waits for an event; returns directly if an event is available.
+    pub fn wait_blocking(&mut self, timeout: Duration) -> Option<PermissionEvent> {
+        self.shared.wait(timeout)
     }
 }
 
-impl<'a> std::future::Future for HotplugWatchFuture<'a> {
-    type Output = HotplugEvent;
-    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> task::Poll<Self::Output> {
-        if let task::Poll::Ready(Some(event)) = self.watch.poll_next(cx) {
-            task::Poll::Ready(event)
-        } else {
-            task::Poll::Pending
+#[cfg(feature = "async")]
+impl futures_core::Stream for PermissionWatch {
+    type Item = PermissionEvent;
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+    ) -> task::Poll<Option<Self::Item>> {
+        // the thread never stops before the watcher is dropped
+        self.shared.poll_pop(cx).map(Some)
+    }
+}
+
+impl Drop for PermissionWatch {
+    fn drop(&mut self) {
+        drop(self.stop.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
         }
     }
 }
 
+impl std::fmt::Debug for PermissionWatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PermissionWatch")
+            .field("available", &self.count_available())
+            .finish_non_exhaustive()
+    }
+}
+
 impl DeviceInfo {
     /// Returns true if the caller has permission to access the device.
     pub fn has_permission(&self) -> Result<bool, Error> {
@@ -226,12 +947,86 @@ impl DeviceInfo {
         .map_err(jerr)
     }
 
-    /// Checks if the device is still in the list of connected devices.
-    /// Note: The implementation can be optimized.
-    #[inline(always)]
+    /// Checks if the device is still connected, by looking up `path_name` in the map
+    /// returned by `getDeviceList()` and comparing vendor and product IDs. Unlike
+    /// `list_devices()`, it doesn't read other devices.
     pub fn check_connection(&self) -> bool {
-        let vec_dev = list_devices().unwrap_or_default(); // heavy
-        vec_dev.into_iter().any(|ref d| d == self)
+        self.lookup_connected().unwrap_or(false)
+    }
+
+    fn lookup_connected(&self) -> Result<bool, Error> {
+        let usb_man = usb_manager()?;
+        let env = &mut jni_attach_vm().map_err(jerr)?;
+        with_local_frame(env, 8, |env| {
+            let dev_list = env
+                .call_method(usb_man, "getDeviceList", "()Ljava/util/HashMap;", &[])
+                .get_object(env)
+                .map_err(jerr)?;
+            let key = self.path_name().as_str().new_jobject(env).map_err(jerr)?;
+            let map_dev = env.get_map(&dev_list).map_err(jerr)?;
+            let Some(dev) = map_dev.get(env, &key).map_err(jerr)? else {
+                return Ok(false);
+            };
+            let vendor_id = env
+                .call_method(&dev, "getVendorId", "()I", &[])
+                .get_int()
+                .map_err(jerr)?;
+            let product_id = env
+                .call_method(&dev, "getProductId", "()I", &[])
+                .get_int()
+                .map_err(jerr)?;
+            Ok(vendor_id as u16 == self.vendor_id() && product_id as u16 == self.product_id())
+        })
+    }
+
+    /// Finds the device in the list of connected devices by `path_name`, then re-reads
+    /// its fields; interfaces, configurations and strings are read again on access (e.g.
+    /// the serial number after gaining permission). Returns `NotConnected` error if the
+    /// device has vanished, then `self` is not modified.
+    pub fn refresh(&mut self) -> Result<(), Error> {
+        let current = list_devices_cached()?
+            .into_iter()
+            .find(|dev| dev.path_name() == self.path_name())
+            .ok_or(Error::from(ErrorKind::NotConnected))?;
+        let env = &mut jni_attach_vm().map_err(jerr)?;
+        *self = DeviceInfo::build(env, current.internal.as_obj())?;
+        Ok(())
+    }
+
+    /// Returns true if any interface of the device is held by a handler in this process
+    /// (e.g. `CdcSerial`). Check `PortLock`.
+    pub fn is_in_use(&self) -> bool {
+        let device_id = self.device_id();
+        lock_ports()
+            .range((device_id, 0)..=(device_id, u8::MAX))
+            .next()
+            .is_some()
+    }
+
+    /// Starts a thread checking `has_permission()` every `interval`. Android may revoke
+    /// the permission of an opened device (e.g. on replugging or by user action), which
+    /// otherwise surfaces as opaque transfer failures. `on_revoked` is called once if
+    /// the permission is lost or cannot be checked, then the thread exits.
+    pub fn monitor_permission(
+        &self,
+        interval: Duration,
+        on_revoked: impl FnOnce(DeviceInfo) + Send + 'static,
+    ) -> PermissionMonitor {
+        let (stop, stop_recv) = std::sync::mpsc::channel::<()>();
+        let dev_info = self.clone();
+        let thread = std::thread::spawn(move || {
+            use std::sync::mpsc::RecvTimeoutError;
+            while let Err(RecvTimeoutError::Timeout) = stop_recv.recv_timeout(interval) {
+                if !dev_info.has_permission().unwrap_or(false) {
+                    on_revoked(dev_info);
+                    return;
+                }
+            }
+        });
+        PermissionMonitor {
+            stop: Some(stop),
+            thread: Some(thread),
+        }
     }
 
     /// Performs a permission request for the device.
@@ -262,6 +1057,21 @@ impl DeviceInfo {
             )
             .auto_local(env)
             .map_err(jerr)?;
+        if android_api_level() >= 31 {
+            // the `PendingIntent` must be mutable since API 31 (for the result extras),
+            // and a mutable one of an implicit intent is rejected since API 34
+            let package = env
+                .call_method(context, "getPackageName", "()Ljava/lang/String;", &[])
+                .get_object(env)
+                .map_err(jerr)?;
+            env.call_method(
+                &intent,
+                "setPackage",
+                "(Ljava/lang/String;)Landroid/content/Intent;",
+                &[(&package).into()],
+            )
+            .map_err(jerr)?;
+        }
 
         let flags = if android_api_level() < 31 {
             0 // should it be FLAG_IMMUTABLE since API 23?
@@ -290,53 +1100,362 @@ impl DeviceInfo {
         if self.has_permission()? {
             return Ok(None); // almost impossible
         }
-        BroadcastWaiter::build([ACTION_USB_PERMISSION])
-            .map(|waiter| {
-                Some(PermissionRequest {
-                    dev_info: self.clone(),
-                    waiter,
-                })
+        IntentQueue::build([ACTION_USB_PERMISSION]).map(|queue| {
+            Some(PermissionRequest {
+                dev_info: self.clone(),
+                queue,
+                cancelled: false,
             })
-            .map_err(jerr)
+        })
+    }
+
+    /// Requests the permission if it is not granted, waits for the user's response, then
+    /// opens the device. Returns `PermissionDenied` error if the user denies it, `TimedOut`
+    /// error if there's no response within `timeout`, `NotConnected` error if the device
+    /// is not connected.
+    /// Note: Blocking the `android_main()` thread will prevent it from receiving the result.
+    pub fn request_and_open(&self, timeout: Duration) -> Result<nusb::Device, Error> {
+        if let Some(request) = self.request_permission()? {
+            if !request.wait_blocking(timeout)? {
+                return Err(permission_denied());
+            }
+        }
+        self.open_device()
+    }
+
+    /// Asynchronous version of `request_and_open()`, without the timeout.
+    #[cfg(feature = "async")]
+    pub async fn request_and_open_async(&self) -> Result<nusb::Device, Error> {
+        if let Some(request) = self.request_permission()? {
+            if !request.await {
+                return Err(permission_denied());
+            }
+        }
+        self.open_device()
     }
 
     /// Opens the device. Returns error `PermissionDenied` if the permission is not granted.
+    ///
+    /// The Java `UsbDeviceConnection` is closed here; `nusb::Device` keeps a duplicated
+    /// file descriptor. Use `open_connection()` to keep the Java connection.
     pub fn open_device(&self) -> Result<nusb::Device, Error> {
-        if !self.has_permission()? {
-            return Err(Error::from(ErrorKind::PermissionDenied));
-        }
-        let raw_fd = {
-            let usb_man = usb_manager()?;
-            let env = &mut jni_attach_vm().map_err(jerr)?;
-            let conn = env
-                .call_method(
-                    usb_man,
-                    "openDevice",
-                    "(Landroid/hardware/usb/UsbDevice;)Landroid/hardware/usb/UsbDeviceConnection;",
-                    &[(&self.internal).into()],
-                )
+        let (owned_fd, _) = self.open_fd()?;
+        nusb::Device::from_fd(owned_fd)
+    }
+
+    /// Opens the device and returns the usbfs file descriptor, for handing it to another
+    /// library (e.g. `libusb_wrap_sys_device()`), with the length of the raw descriptors
+    /// which can be read from the start of the file. Returns error `PermissionDenied` if
+    /// the permission is not granted.
+    ///
+    /// Note: The Java `UsbDeviceConnection` is closed here; the returned descriptor is
+    /// a duplicate, the device is released after it is closed.
+    pub fn open_fd(&self) -> Result<(std::os::fd::OwnedFd, usize), Error> {
+        let env = &mut jni_attach_vm().map_err(jerr)?;
+        let conn = self.open_java_connection(env)?;
+        let result = dup_connection_fd(env, &conn).and_then(|owned_fd| {
+            let descs = env
+                .call_method(&conn, "getRawDescriptors", "()[B", &[])
                 .get_object(env)
                 .map_err(jerr)?;
-            if conn.is_null() {
-                return Err(Error::new(ErrorKind::NotFound, "`openDevice()` failed`"));
+            let len = if descs.is_null() {
+                0
+            } else {
+                let descs = jni::objects::JByteArray::from(descs);
+                env.get_array_length(&descs).map_err(jerr)? as usize
+            };
+            Ok((owned_fd, len))
+        });
+        let _ = env.call_method(&conn, "close", "()V", &[]).clear_ex();
+        result
+    }
+
+    /// Opens the device, keeping the Java `UsbDeviceConnection` until the returned
+    /// `UsbConnection` is dropped. Returns error `PermissionDenied` if the permission
+    /// is not granted.
+    pub fn open_connection(&self) -> Result<UsbConnection, Error> {
+        let env = &mut jni_attach_vm().map_err(jerr)?;
+        let conn = self.open_java_connection(env)?;
+        let device = dup_connection_fd(env, &conn).and_then(nusb::Device::from_fd);
+        match device {
+            Ok(device) => Ok(UsbConnection {
+                device,
+                conn,
+                dev_info: self.clone(),
+            }),
+            Err(e) => {
+                let _ = env.call_method(&conn, "close", "()V", &[]).clear_ex();
+                Err(e)
             }
-            env.call_method(&conn, "getFileDescriptor", "()I", &[])
-                .get_int()
-                .map_err(jerr)?
+        }
+    }
+
+    /// Calls `UsbManager.openDevice()` after checking the permission.
+    pub(crate) fn open_java_connection(
+        &self,
+        env: &mut jni::JNIEnv<'_>,
+    ) -> Result<GlobalRef, Error> {
+        if !self.has_permission()? {
+            return Err(Error::from(ErrorKind::PermissionDenied));
+        }
+        let usb_man = usb_manager()?;
+        let conn = env
+            .call_method(
+                usb_man,
+                "openDevice",
+                "(Landroid/hardware/usb/UsbDevice;)Landroid/hardware/usb/UsbDeviceConnection;",
+                &[(&self.internal).into()],
+            )
+            .get_object(env)
+            .map_err(jerr)?;
+        if conn.is_null() {
+            return Err(Error::new(ErrorKind::NotFound, "`openDevice()` failed`"));
+        }
+        env.new_global_ref(&conn).map_err(jerr)
+    }
+
+    /// Reads the raw descriptors (the device descriptor followed by configuration descriptors,
+    /// including class-specific descriptors not exposed by the Java API) through a temporary
+    /// connection. Returns error `PermissionDenied` if the permission is not granted.
+    pub fn raw_descriptors(&self) -> Result<Vec<u8>, Error> {
+        let env = &mut jni_attach_vm().map_err(jerr)?;
+        let conn = self.open_java_connection(env)?;
+        let descs = env
+            .call_method(&conn, "getRawDescriptors", "()[B", &[])
+            .get_object(env)
+            .map_err(jerr);
+        let _ = env.call_method(&conn, "close", "()V", &[]).clear_ex();
+        let descs = descs?;
+        if descs.is_null() {
+            return Err(Error::other("`getRawDescriptors()` failed"));
+        }
+        env.convert_byte_array(jni::objects::JByteArray::from(descs))
+            .map_err(jerr)
+    }
+
+    /// Opens the device like `open_device()`, then selects the configuration of given
+    /// `bConfigurationValue` if it is not active. This must be done before claiming
+    /// interfaces; some adapters put their UART function in a non-default configuration.
+    pub fn open_device_with_configuration(&self, configuration: u8) -> Result<nusb::Device, Error> {
+        if !self
+            .try_configurations()?
+            .any(|conf| conf.id() == configuration)
+        {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Configuration not found",
+            ));
+        }
+        let device = self.open_device()?;
+        let active = device
+            .active_configuration()
+            .map(|conf| conf.configuration_value())
+            .ok();
+        if active != Some(configuration) {
+            device.set_configuration(configuration)?;
+        }
+        Ok(device)
+    }
+}
+
+/// Opened device holding the Java `UsbDeviceConnection`, returned by
+/// `DeviceInfo::open_connection()`. The Java connection is closed when it is dropped,
+/// so repeated opening doesn't leak connections.
+///
+/// Note: Clones of the `nusb::Device` (and its interfaces) keep a duplicated file
+/// descriptor, the device is released after all of them are dropped.
+pub struct UsbConnection {
+    device: nusb::Device, // uses a duplicated file descriptor
+    conn: GlobalRef,      // `android.hardware.usb.UsbDeviceConnection`
+    dev_info: DeviceInfo,
+}
+
+impl UsbConnection {
+    /// Returns the `nusb` device handle, which can be cloned.
+    pub fn device(&self) -> &nusb::Device {
+        &self.device
+    }
+
+    /// Returns a reference of the associated `DeviceInfo`.
+    pub fn device_info(&self) -> &DeviceInfo {
+        &self.dev_info
+    }
+
+    /// Returns the `android.hardware.usb.UsbDeviceConnection` object.
+    pub fn java_connection(&self) -> &JObject<'static> {
+        self.conn.as_obj()
+    }
+
+    /// Reads the serial number string through `UsbDeviceConnection.getSerial()`. Returns
+    /// `None` if the device has no serial number. It works for devices that reveal it
+    /// only after the permission is granted (`DeviceInfo::serial_number()` is `None`
+    /// before gaining permission on Android 10 and above).
+    pub fn serial(&self) -> Result<Option<String>, Error> {
+        let env = &mut jni_attach_vm().map_err(jerr)?;
+        let serial = env
+            .call_method(&self.conn, "getSerial", "()Ljava/lang/String;", &[])
+            .get_object(env)
+            .map_err(jerr)?;
+        if serial.is_null() {
+            return Ok(None);
+        }
+        serial.get_string(env).map(Some).map_err(jerr)
+    }
+
+    /// Closes the Java connection and returns the `nusb` device handle.
+    pub fn into_device(self) -> nusb::Device {
+        self.device.clone()
+    }
+}
+
+impl Drop for UsbConnection {
+    fn drop(&mut self) {
+        if let Ok(env) = &mut jni_attach_vm() {
+            let _ = env.call_method(&self.conn, "close", "()V", &[]).clear_ex();
+        }
+    }
+}
+
+impl std::fmt::Debug for UsbConnection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("UsbConnection")
+            .field("dev_info", &self.dev_info)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Handler of the thread started by `DeviceInfo::monitor_permission()`.
+/// The thread is stopped when this handler is dropped.
+#[derive(Debug)]
+pub struct PermissionMonitor {
+    stop: Option<std::sync::mpsc::Sender<()>>,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+impl PermissionMonitor {
+    /// Returns true if the thread has exited after the permission is revoked.
+    pub fn is_finished(&self) -> bool {
+        self.thread.as_ref().map_or(true, |t| t.is_finished())
+    }
+}
+
+impl Drop for PermissionMonitor {
+    fn drop(&mut self) {
+        drop(self.stop.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Interface held by a handler in this process.
+struct HeldPort {
+    dev_info: DeviceInfo,
+    owner: String, // name given to `PortLock::acquire_as()`
+}
+
+/// Interfaces held by handlers in this process, keyed by `(device_id, interface_number)`.
+static LOCKED_PORTS: Mutex<BTreeMap<(i32, u8), HeldPort>> = Mutex::new(BTreeMap::new());
+
+fn lock_ports() -> std::sync::MutexGuard<'static, BTreeMap<(i32, u8), HeldPort>> {
+    LOCKED_PORTS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Returns devices having any interface held by a handler in this process.
+pub(crate) fn held_devices() -> Vec<DeviceInfo> {
+    let mut devices: Vec<DeviceInfo> = Vec::new();
+    for ((device_id, _), held) in lock_ports().iter() {
+        if devices.last().map_or(true, |d| d.device_id() != *device_id) {
+            devices.push(held.dev_info.clone());
+        }
+    }
+    devices
+}
+
+/// In-process lock of a device interface, held by handlers to prevent two parts of the
+/// same app (or two probes) from claiming the same port simultaneously, which otherwise
+/// fails confusingly inside `nusb`. It is released on drop.
+#[derive(Debug)]
+pub struct PortLock {
+    key: (i32, u8),
+}
+
+impl PortLock {
+    /// Acquires the lock of the interface without an owner name. Check `acquire_as()`.
+    pub fn acquire(dev_info: &DeviceInfo, interface_number: u8) -> Result<Self, Error> {
+        Self::acquire_as(dev_info, interface_number, "an unnamed handler")
+    }
+
+    /// Acquires the lock of the interface for the handler named `owner` (e.g. `CdcSerial`).
+    /// Returns `AlreadyExists` error with the existing owner's name if it is held by
+    /// another handler in this process.
+    pub fn acquire_as(
+        dev_info: &DeviceInfo,
+        interface_number: u8,
+        owner: &str,
+    ) -> Result<Self, Error> {
+        let key = (dev_info.device_id(), interface_number);
+        let mut ports = lock_ports();
+        if let Some(held) = ports.get(&key) {
+            return Err(Error::new(
+                ErrorKind::AlreadyExists,
+                format!(
+                    "Interface {interface_number} of {} is claimed by {}",
+                    dev_info.path_name(),
+                    held.owner
+                ),
+            ));
+        }
+        let held = HeldPort {
+            dev_info: dev_info.clone(),
+            owner: owner.to_string(),
         };
-        // Safety: `close()` is not called automatically when the JNI `AutoLocal` of `conn`
-        // and the corresponding Java object is destroyed. (check `UsbDeviceConnection` source)
-        use std::os::fd::*;
-        let owned_fd = unsafe { OwnedFd::from_raw_fd(raw_fd as RawFd) };
-        nusb::Device::from_fd(owned_fd)
+        ports.insert(key, held);
+        Ok(Self { key })
+    }
+
+    /// Returns the owner name of the interface held by a handler in this process.
+    pub fn owner_of(dev_info: &DeviceInfo, interface_number: u8) -> Option<String> {
+        lock_ports()
+            .get(&(dev_info.device_id(), interface_number))
+            .map(|held| held.owner.clone())
     }
 }
 
+impl Drop for PortLock {
+    fn drop(&mut self) {
+        lock_ports().remove(&self.key);
+    }
+}
+
+/// Duplicates the file descriptor of the opened `UsbDeviceConnection`. The duplicate
+/// shares the usbfs file with it, so it stays usable after the connection is closed.
+fn dup_connection_fd(
+    env: &mut jni::JNIEnv<'_>,
+    conn: &GlobalRef,
+) -> Result<std::os::fd::OwnedFd, Error> {
+    use std::os::fd::*;
+    let raw_fd = env
+        .call_method(conn, "getFileDescriptor", "()I", &[])
+        .get_int()
+        .map_err(jerr)?;
+    // Safety: the descriptor is owned by `conn`, which is still open here.
+    unsafe { BorrowedFd::borrow_raw(raw_fd as RawFd) }.try_clone_to_owned()
+}
+
+fn permission_denied() -> Error {
+    Error::new(
+        ErrorKind::PermissionDenied,
+        "USB permission denied by the user",
+    )
+}
+
 /// Represents an ongoing permission request.
 #[derive(Debug)]
 pub struct PermissionRequest {
     dev_info: DeviceInfo,
-    waiter: BroadcastWaiter,
+    queue: IntentQueue,
+    cancelled: bool, // the receiver is unregistered on expiry of `poll_with_timeout()`
 }
 
 impl PermissionRequest {
@@ -347,55 +1466,105 @@ impl PermissionRequest {
 
     /// Checks if the request has completed.
     pub fn responsed(&self) -> bool {
-        self.waiter.count_received() > 0
+        self.queue.count() > 0
     }
 
     /// Takes the `EXTRA_PERMISSION_GRANTED` extra from the received result.
     /// This can be called *after* `responsed()` returned true.
     pub fn take_response(self) -> Option<bool> {
-        self.responsed().then_some(())?;
-        block_for_timeout(self, Duration::from_millis(10))
+        while let Some(intent) = self.queue.pop() {
+            if let Some(granted) = self.check_response(intent.as_obj()) {
+                return Some(granted);
+            }
+        }
+        None
     }
 
     /// Blocking permission request. Returns directly if the permission is already granted.
+    /// The receiver is unregistered on expiry.
+    /// Note: Blocking the `android_main()` thread will prevent it from receiving the result.
+    pub fn wait_blocking(mut self, timeout: Duration) -> Result<bool, Error> {
+        self.poll_with_timeout(timeout)?
+            .ok_or(Error::from(ErrorKind::TimedOut))
+    }
+
+    /// Waits for the result up to `timeout`. Returns `Ok(None)` on expiry, and the
+    /// request is cancelled in this case: the receiver is unregistered, later calls
+    /// return `Ok(None)` directly, so an abandoned request doesn't leak the receiver.
     /// Note: Blocking the `android_main()` thread will prevent it from receiving the result.
-    pub fn wait_blocking(self, timeout: Duration) -> Result<bool, Error> {
-        block_for_timeout(self, timeout).ok_or(Error::from(ErrorKind::TimedOut))
+    pub fn poll_with_timeout(&mut self, timeout: Duration) -> Result<Option<bool>, Error> {
+        if self.cancelled {
+            return Ok(None);
+        }
+        let t_start = Instant::now();
+        loop {
+            let Some(intent) = self.queue.wait(timeout.saturating_sub(t_start.elapsed())) else {
+                self.queue.unregister();
+                self.cancelled = true;
+                return Ok(None);
+            };
+            if let Some(granted) = self.check_response(intent.as_obj()) {
+                return Ok(Some(granted));
+            }
+        }
+    }
+
+    /// Stops waiting for the result and unregisters the receiver. This is done
+    /// automatically when the request is dropped.
+    ///
+    /// Note: The system permission dialog is not dismissed; the user's response is
+    /// still applied to the device, which can be checked by `DeviceInfo::has_permission()`.
+    pub fn cancel(self) {
+        drop(self)
+    }
+
+    /// Returns the `EXTRA_PERMISSION_GRANTED` extra if the intent is the result for
+    /// this device, and unregisters the receiver in this case.
+    fn check_response(&self, intent: &JObject<'_>) -> Option<bool> {
+        let dev_info = get_extra_device(intent).ok()?;
+        if dev_info != self.dev_info {
+            return None;
+        }
+        let granted = get_permission_granted(intent)?;
+        self.queue.unregister();
+        Some(granted)
     }
 }
 
+impl Drop for PermissionRequest {
+    fn drop(&mut self) {
+        if !self.cancelled {
+            self.queue.unregister();
+        }
+    }
+}
+
+/// Gets the `EXTRA_PERMISSION_GRANTED` extra from the permission request result.
+fn get_permission_granted(intent: &JObject<'_>) -> Option<bool> {
+    let env = &mut jni_attach_vm().ok()?;
+    let extra_name = EXTRA_PERMISSION_GRANTED.new_jobject(env).ok()?;
+    let granted = env
+        .call_method(
+            intent,
+            "getBooleanExtra",
+            "(Ljava/lang/String;Z)Z",
+            &[(&extra_name).into(), false.into()],
+        )
+        .get_boolean()
+        .unwrap_or(false);
+    Some(granted)
+}
+
+#[cfg(feature = "async")]
 impl std::future::Future for PermissionRequest {
     type Output = bool;
 
-    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> task::Poll<Self::Output> {
-        // `BroadcastWaiter` implementation makes `Ready(None)` impossible here
-        if let task::Poll::Ready(Some(intent)) = self.waiter.poll_next(cx) {
-            let Ok(env) = &mut jni_attach_vm() else {
-                return task::Poll::Ready(false); // almost impossible
-            };
-            let Ok(dev_info) = get_extra_device(intent.as_obj()) else {
-                return task::Poll::Ready(false);
-            };
-            if dev_info == self.dev_info {
-                let Ok(extra_name) = EXTRA_PERMISSION_GRANTED.new_jobject(env) else {
-                    return task::Poll::Ready(false); // almost impossible
-                };
-                let granted = env
-                    .call_method(
-                        &intent,
-                        "getBooleanExtra",
-                        "(Ljava/lang/String;Z)Z",
-                        &[(&extra_name).into(), false.into()],
-                    )
-                    .get_boolean()
-                    .unwrap_or(false);
-                let _ = self.waiter.receiver().unregister();
-                task::Poll::Ready(granted)
-            } else {
-                task::Poll::Pending
+    fn poll(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> task::Poll<Self::Output> {
+        while let task::Poll::Ready(intent) = self.queue.poll_pop(cx) {
+            if let Some(granted) = self.check_response(intent.as_obj()) {
+                return task::Poll::Ready(granted);
             }
-        } else {
-            task::Poll::Pending
         }
+        task::Poll::Pending
     }
 }
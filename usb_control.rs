@@ -0,0 +1,232 @@
+use crate::Error;
+
+use std::{io::ErrorKind, thread, time::Duration};
+
+use nusb::transfer::{Control, ControlType, Recipient, TransferError};
+
+/// Synchronous control transfers through a claimed interface, with the retry policy and
+/// the error mapping of other wrappers here (timeouts are reported as `TimedOut` errors).
+/// It can be cloned and moved to other threads.
+#[derive(Clone)]
+pub struct SyncControl {
+    intr: nusb::Interface,
+    retry: RetryPolicy,
+}
+
+impl SyncControl {
+    /// Wraps the claimed interface.
+    pub fn new(intr: nusb::Interface) -> Self {
+        Self {
+            intr,
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    /// Returns the wrapped interface.
+    pub fn interface(&self) -> &nusb::Interface {
+        &self.intr
+    }
+
+    /// Sets the retry policy for transient transfer failures, no retry by default.
+    pub fn set_retry_policy(&mut self, retry: RetryPolicy) {
+        self.retry = retry;
+    }
+
+    /// Does a control IN transfer, returns the received length.
+    pub fn control_in(
+        &self,
+        control: Control,
+        buf: &mut [u8],
+        timeout: Duration,
+    ) -> std::io::Result<usize> {
+        run_control_in(&self.retry, || {
+            self.intr.control_in_blocking(control, &mut *buf, timeout)
+        })
+    }
+
+    /// Does a control OUT transfer. Returns `Interrupted` error if `data` is not sent completely.
+    pub fn control_out(
+        &self,
+        control: Control,
+        data: &[u8],
+        timeout: Duration,
+    ) -> std::io::Result<()> {
+        run_control_out(&self.retry, data.len(), || {
+            self.intr.control_out_blocking(control, data, timeout)
+        })
+    }
+
+    /// Sends a class-specific request to the interface, e.g. `SET_LINE_CODING` of CDC-ACM.
+    /// `index` is usually the interface number.
+    pub fn class_out(
+        &self,
+        request: u8,
+        value: u16,
+        index: u16,
+        data: &[u8],
+        timeout: Duration,
+    ) -> std::io::Result<()> {
+        self.control_out(class_request(request, value, index), data, timeout)
+    }
+
+    /// Gets a class-specific request from the interface, returns the received length.
+    /// `index` is usually the interface number.
+    pub fn class_in(
+        &self,
+        request: u8,
+        value: u16,
+        index: u16,
+        buf: &mut [u8],
+        timeout: Duration,
+    ) -> std::io::Result<usize> {
+        self.control_in(class_request(request, value, index), buf, timeout)
+    }
+}
+
+impl std::fmt::Debug for SyncControl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SyncControl")
+            .field("retry", &self.retry)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Class-specific request with the interface as the recipient.
+pub(crate) fn class_request(request: u8, value: u16, index: u16) -> Control {
+    Control {
+        control_type: ControlType::Class,
+        recipient: Recipient::Interface,
+        request,
+        value,
+        index,
+    }
+}
+
+/// Runs the control IN transfer with the retry policy, mapping the failure.
+pub(crate) fn run_control_in(
+    retry: &RetryPolicy,
+    transfer: impl FnMut() -> Result<usize, TransferError>,
+) -> std::io::Result<usize> {
+    retry.run(transfer).map_err(sync_transfer_error)
+}
+
+/// Runs the control OUT transfer of `len` bytes with the retry policy, mapping the failure.
+pub(crate) fn run_control_out(
+    retry: &RetryPolicy,
+    len: usize,
+    transfer: impl FnMut() -> Result<usize, TransferError>,
+) -> std::io::Result<()> {
+    let len_sent = retry.run(transfer).map_err(sync_transfer_error)?;
+    if len_sent == len {
+        Ok(())
+    } else {
+        Err(Error::new(
+            ErrorKind::Interrupted,
+            "Control OUT transfer, wrong written size",
+        ))
+    }
+}
+
+/// Retry policy for transient transfer failures (`TransferError::Fault`, e.g. `EPROTO`
+/// on long cables, and `TransferError::Unknown`) and stalls, which are retried only if
+/// no data has been transferred. The timeout is applied for each attempt.
+///
+/// A stalled bulk or interrupt transfer is retried only if the halt is cleared
+/// automatically and successfully (check `SyncReader::set_auto_clear_halt()`); otherwise
+/// the failure of clearing the halt is returned as a `BrokenPipe` error. Control endpoints
+/// recover from stalls by themselves, but a stall is usually the device's answer of
+/// an unsupported request, so `stall_retries` should be kept 0 for them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Maximum amount of retries of transient failures after the first attempt,
+    /// 0 by default.
+    pub retries: u32,
+    /// Maximum amount of retries after stalls, 0 by default.
+    pub stall_retries: u32,
+    /// Delay before the first retry, doubled for each following retry. 10 ms by default.
+    pub backoff: Duration,
+    /// Maximum delay between retries, 1 s by default.
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            retries: 0,
+            stall_retries: 0,
+            backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_secs(1),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Retries up to `retries` times with the default backoff.
+    pub fn with_retries(retries: u32) -> Self {
+        Self {
+            retries,
+            ..Default::default()
+        }
+    }
+
+    /// Calls `f` until it succeeds, fails with an error not to be retried or no retry is
+    /// left. It is meant for control transfers, so stalls are retried without clearing.
+    pub fn run<T>(
+        &self,
+        mut f: impl FnMut() -> Result<T, TransferError>,
+    ) -> Result<T, TransferError> {
+        let mut attempts = Attempts::default();
+        loop {
+            match f() {
+                Err(e) => match self.next_retry(e, &mut attempts, true) {
+                    Some(delay) => thread::sleep(delay),
+                    None => return Err(e),
+                },
+                result => return result,
+            }
+        }
+    }
+
+    /// Returns the delay before retrying after the failure, or `None` if it should not
+    /// be retried. `halt_cleared`: whether the halt is cleared after a stall.
+    pub(crate) fn next_retry(
+        &self,
+        err: TransferError,
+        attempts: &mut Attempts,
+        halt_cleared: bool,
+    ) -> Option<Duration> {
+        let attempt = match err {
+            TransferError::Fault | TransferError::Unknown if attempts.transient < self.retries => {
+                &mut attempts.transient
+            }
+            TransferError::Stall if halt_cleared && attempts.stall < self.stall_retries => {
+                &mut attempts.stall
+            }
+            _ => return None,
+        };
+        let delay = self.delay(*attempt);
+        *attempt += 1;
+        Some(delay)
+    }
+
+    fn delay(&self, attempt: u32) -> Duration {
+        self.backoff
+            .saturating_mul(1u32 << attempt.min(16))
+            .min(self.max_backoff)
+    }
+}
+
+/// Retries done for an operation, counted separately for transient failures and stalls.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct Attempts {
+    transient: u32,
+    stall: u32,
+}
+
+/// Transfers are cancelled only on timeout in synchronous wrappers.
+pub(crate) fn sync_transfer_error(e: TransferError) -> Error {
+    match e {
+        TransferError::Cancelled => Error::new(ErrorKind::TimedOut, e),
+        e => crate::usb::map_transfer_error(e),
+    }
+}
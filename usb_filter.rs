@@ -0,0 +1,307 @@
+use crate::usb::{list_devices_cached, DeviceInfo};
+use crate::Error;
+use std::io::ErrorKind;
+
+/// Selects devices by IDs, serial number or usbfs path. It can be parsed from strings:
+/// - `"303a:1001"`: vendor ID and product ID in hexadecimal; `"303a:*"` matches any product;
+/// - `"/dev/bus/usb/001/002"`: path of the device in the usbfs file system;
+/// - any other string: the serial number.
+///
+/// Note: On Android 10 and above, the serial number can be read only after gaining
+/// permission for the device. Check `DeviceInfo::serial_number()`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DeviceSelector {
+    Ids {
+        vendor_id: u16,
+        product_id: Option<u16>,
+    },
+    SerialNumber(String),
+    Path(String),
+}
+
+impl DeviceSelector {
+    /// Returns true if the device is selected.
+    pub fn matches(&self, dev_info: &DeviceInfo) -> bool {
+        match self {
+            Self::Ids {
+                vendor_id,
+                product_id,
+            } => {
+                dev_info.vendor_id() == *vendor_id
+                    && product_id.map_or(true, |pid| dev_info.product_id() == pid)
+            }
+            Self::SerialNumber(serial) => dev_info.serial_number().as_ref() == Some(serial),
+            Self::Path(path) => dev_info.path_name() == path,
+        }
+    }
+}
+
+impl std::str::FromStr for DeviceSelector {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidInput, "Empty device selector"));
+        }
+        if s.starts_with('/') {
+            return Ok(Self::Path(s.to_string()));
+        }
+        let parse_id = |id: &str| u16::from_str_radix(id, 16).ok();
+        if let Some((vid, pid)) = s.split_once(':') {
+            if let Some(vendor_id) = parse_id(vid) {
+                let product_id = match pid {
+                    "*" => Some(None),
+                    pid => parse_id(pid).map(Some),
+                };
+                if let Some(product_id) = product_id {
+                    return Ok(Self::Ids {
+                        vendor_id,
+                        product_id,
+                    });
+                }
+            }
+        }
+        Ok(Self::SerialNumber(s.to_string()))
+    }
+}
+
+impl std::fmt::Display for DeviceSelector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Ids {
+                vendor_id,
+                product_id: Some(pid),
+            } => write!(f, "{vendor_id:04x}:{pid:04x}"),
+            Self::Ids { vendor_id, .. } => write!(f, "{vendor_id:04x}:*"),
+            Self::SerialNumber(serial) => write!(f, "{serial}"),
+            Self::Path(path) => write!(f, "{path}"),
+        }
+    }
+}
+
+impl DeviceInfo {
+    /// Returns connected devices selected by `selector`, e.g. `"303a:1001"`.
+    /// Check `DeviceSelector` for the syntax.
+    pub fn find(selector: &str) -> Result<Vec<DeviceInfo>, Error> {
+        let selector: DeviceSelector = selector.parse()?;
+        Ok(list_devices_cached()?
+            .into_iter()
+            .filter(|dev| selector.matches(dev))
+            .collect())
+    }
+}
+
+/// Opens the first connected device selected by `selector`, e.g. `"303a:1001"`.
+/// Check `DeviceSelector` for the syntax. Returns `NotFound` error if no device is
+/// selected, `PermissionDenied` error if the permission is not granted.
+pub fn open_matching(selector: &str) -> Result<(DeviceInfo, nusb::Device), Error> {
+    let dev_info = DeviceInfo::find(selector)?
+        .into_iter()
+        .next()
+        .ok_or(Error::new(ErrorKind::NotFound, "No matching device found"))?;
+    let device = dev_info.open_device()?;
+    Ok((dev_info, device))
+}
+
+/// Device filter with the semantics of `<usb-device>` entries in Android's
+/// `device_filter.xml`; fields of `None` match anything.
+///
+/// IDs and strings must all match. Class, subclass and protocol match if they match
+/// the device itself or any of its interfaces.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DeviceFilter {
+    pub vendor_id: Option<u16>,
+    pub product_id: Option<u16>,
+    pub class: Option<u8>,
+    pub subclass: Option<u8>,
+    pub protocol: Option<u8>,
+    pub manufacturer_name: Option<String>,
+    pub product_name: Option<String>,
+    pub serial_number: Option<String>,
+}
+
+impl DeviceFilter {
+    /// Filter matching the vendor ID and the product ID.
+    pub fn with_ids(vendor_id: u16, product_id: u16) -> Self {
+        Self {
+            vendor_id: Some(vendor_id),
+            product_id: Some(product_id),
+            ..Default::default()
+        }
+    }
+
+    /// Returns true if the device matches the filter.
+    pub fn matches(&self, dev_info: &DeviceInfo) -> bool {
+        if self
+            .vendor_id
+            .is_some_and(|vid| vid != dev_info.vendor_id())
+            || self
+                .product_id
+                .is_some_and(|pid| pid != dev_info.product_id())
+        {
+            return false;
+        }
+        for (expected, actual) in [
+            (&self.manufacturer_name, dev_info.manufacturer_string()),
+            (&self.product_name, dev_info.product_string()),
+            (&self.serial_number, dev_info.serial_number()),
+        ] {
+            if expected.is_some() && expected != actual {
+                return false;
+            }
+        }
+        self.matches_class(dev_info.class(), dev_info.subclass(), dev_info.protocol())
+            || dev_info
+                .interfaces()
+                .any(|intr| self.matches_class(intr.class(), intr.sub_class(), intr.protocol()))
+    }
+
+    fn matches_class(&self, class: u8, subclass: u8, protocol: u8) -> bool {
+        self.class.map_or(true, |c| c == class)
+            && self.subclass.map_or(true, |c| c == subclass)
+            && self.protocol.map_or(true, |c| c == protocol)
+    }
+
+    /// Parses all `<usb-device>` entries of a `device_filter.xml` resource. Numbers are
+    /// decimal, or hexadecimal with the `0x` prefix. Returns `InvalidData` error if an
+    /// attribute is malformed; unknown attributes and comments are ignored.
+    pub fn parse_xml(xml: &str) -> Result<Vec<Self>, Error> {
+        let bad_data = |msg: &str| Error::new(ErrorKind::InvalidData, msg.to_string());
+        let mut filters = Vec::new();
+        let mut rest = xml;
+        while let Some(pos) = rest.find('<') {
+            rest = &rest[pos..];
+            if let Some(comment) = rest.strip_prefix("<!--") {
+                let end = comment.find("-->").ok_or(bad_data("Unclosed comment"))?;
+                rest = &comment[end + "-->".len()..];
+                continue;
+            }
+            let Some(mut attrs) = rest
+                .strip_prefix("<usb-device")
+                .filter(|s| s.starts_with(|c: char| c.is_whitespace() || c == '/' || c == '>'))
+            else {
+                rest = &rest[1..];
+                continue;
+            };
+
+            let mut filter = Self::default();
+            loop {
+                attrs = attrs.trim_start();
+                if let Some(after) = attrs.strip_prefix("/>").or(attrs.strip_prefix('>')) {
+                    rest = after;
+                    break;
+                }
+                // `>` may appear in quoted values, so the tag ends only between attributes
+                let eq = attrs
+                    .find(['=', '>'])
+                    .filter(|i| attrs[*i..].starts_with('='))
+                    .ok_or(bad_data("Malformed `usb-device` tag"))?;
+                let name = attrs[..eq].trim();
+                let quoted = attrs[eq + 1..].trim_start();
+                let quote = quoted
+                    .chars()
+                    .next()
+                    .filter(|c| *c == '"' || *c == '\'')
+                    .ok_or(bad_data("Unquoted attribute value"))?;
+                let len = quoted[1..]
+                    .find(quote)
+                    .ok_or(bad_data("Unclosed attribute value"))?;
+                let value = &quoted[1..1 + len];
+                attrs = &quoted[len + 2..];
+                match name {
+                    "vendor-id" => filter.vendor_id = Some(parse_number(name, value)?),
+                    "product-id" => filter.product_id = Some(parse_number(name, value)?),
+                    "class" => filter.class = Some(parse_number(name, value)?),
+                    "subclass" => filter.subclass = Some(parse_number(name, value)?),
+                    "protocol" => filter.protocol = Some(parse_number(name, value)?),
+                    "manufacturer-name" => filter.manufacturer_name = Some(value.to_string()),
+                    "product-name" => filter.product_name = Some(value.to_string()),
+                    "serial-number" => filter.serial_number = Some(value.to_string()),
+                    _ => (),
+                }
+            }
+            filters.push(filter);
+        }
+        Ok(filters)
+    }
+}
+
+/// Parses a decimal number, or a hexadecimal number with the `0x` prefix.
+fn parse_number<T: TryFrom<u32>>(name: &str, value: &str) -> Result<T, Error> {
+    let s = value.trim();
+    let number = match s.strip_prefix("0x").or(s.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    };
+    number.and_then(|n| T::try_from(n).ok()).ok_or(Error::new(
+        ErrorKind::InvalidData,
+        format!("Bad value of `{name}`: {value}"),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_entries() {
+        let xml = r#"<?xml version="1.0" encoding="utf-8"?>
+<resources>
+    <usb-device vendor-id="0x2341" product-id="67" />
+    <usb-device class="2" subclass='2' manufacturer-name="Acme"/>
+</resources>"#;
+        let filters = DeviceFilter::parse_xml(xml).unwrap();
+        assert_eq!(
+            filters,
+            [
+                DeviceFilter::with_ids(0x2341, 67),
+                DeviceFilter {
+                    class: Some(2),
+                    subclass: Some(2),
+                    manufacturer_name: Some("Acme".to_string()),
+                    ..Default::default()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn skip_comments() {
+        let xml = r#"<resources>
+    <!-- <usb-device vendor-id="0x1a86" /> -->
+    <!--<usb-device vendor-id="bad">-->
+    <usb-device vendor-id="0x0403"></usb-device>
+</resources>"#;
+        let filters = DeviceFilter::parse_xml(xml).unwrap();
+        assert_eq!(filters.len(), 1);
+        assert_eq!(filters[0].vendor_id, Some(0x0403));
+        assert!(DeviceFilter::parse_xml("<!-- <usb-device /> ").is_err());
+    }
+
+    #[test]
+    fn quoted_greater_than() {
+        let xml = r#"<usb-device product-name="A > B" vendor-id="16" /><usb-device />"#;
+        let filters = DeviceFilter::parse_xml(xml).unwrap();
+        assert_eq!(filters.len(), 2);
+        assert_eq!(filters[0].product_name.as_deref(), Some("A > B"));
+        assert_eq!(filters[0].vendor_id, Some(16));
+        assert_eq!(filters[1], DeviceFilter::default());
+    }
+
+    #[test]
+    fn other_tags_and_malformed_attributes() {
+        let filters = DeviceFilter::parse_xml("<usb-devices><usb-accessory model=\"x\"/>");
+        assert!(filters.unwrap().is_empty());
+        for xml in [
+            r#"<usb-device vendor-id=0x10 />"#,
+            r#"<usb-device vendor-id="0x10 />"#,
+            r#"<usb-device vendor-id="65536" />"#,
+            r#"<usb-device vendor-id>"#,
+            r#"<usb-device vendor-id="1""#,
+        ] {
+            let err = DeviceFilter::parse_xml(xml).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidData, "{xml}");
+        }
+    }
+}
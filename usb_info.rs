@@ -2,8 +2,19 @@ use crate::usb::{jerr, usb_manager, Error};
 use getset::*;
 use jni::{objects::JObject, sys::jint, JNIEnv};
 use jni_min_helper::*;
+use nusb::transfer::{Control, ControlType, Direction, EndpointType, Recipient};
+use std::{
+    io::ErrorKind,
+    sync::{Arc, Condvar, Mutex, OnceLock},
+    time::Duration,
+};
 
 /// Enumerates for all USB devices via Android Java API.
+///
+/// Note: It makes a few JNI calls for each device; interfaces, configurations and strings
+/// are read on their first access, which may take milliseconds for a composite device.
+/// Use `list_devices_background()` to do all of these without blocking the UI thread,
+/// or `list_devices_cached()` to avoid enumerating again until a hotplug event arrives.
 pub fn list_devices() -> Result<Vec<DeviceInfo>, Error> {
     let usb_man = usb_manager()?;
     let env = &mut jni_attach_vm().map_err(jerr)?;
@@ -21,9 +32,89 @@ pub fn list_devices() -> Result<Vec<DeviceInfo>, Error> {
     Ok(devices)
 }
 
+/// Calls `list_devices()` and `DeviceInfo::load_details()` in a background thread.
+pub fn list_devices_background() -> ListDevicesTask {
+    let shared = Arc::new(ListDevicesShared::default());
+    let shared_thread = shared.clone();
+    std::thread::spawn(move || {
+        let result = list_devices().and_then(|devices| {
+            devices.iter().try_for_each(DeviceInfo::load_details)?;
+            Ok(devices)
+        });
+        shared_thread.result.lock().unwrap().replace(result);
+        shared_thread.cond.notify_all();
+        #[cfg(feature = "async")]
+        if let Some(waker) = shared_thread.waker.lock().unwrap().take() {
+            waker.wake();
+        }
+    });
+    ListDevicesTask { shared }
+}
+
+/// Handler of the background `list_devices()` call. It is a `Future` if the `async`
+/// feature is enabled.
+#[derive(Debug)]
+pub struct ListDevicesTask {
+    shared: Arc<ListDevicesShared>,
+}
+
+type ListDevicesResult = Result<Vec<DeviceInfo>, Error>;
+
+#[derive(Debug, Default)]
+struct ListDevicesShared {
+    result: Mutex<Option<ListDevicesResult>>,
+    cond: Condvar,
+    #[cfg(feature = "async")]
+    waker: Mutex<Option<std::task::Waker>>,
+}
+
+impl ListDevicesTask {
+    /// Returns true if the enumeration is finished.
+    pub fn is_finished(&self) -> bool {
+        self.shared.result.lock().unwrap().is_some()
+    }
+
+    /// Waits for the result.
+    pub fn wait_blocking(self) -> ListDevicesResult {
+        let result = self.shared.result.lock().unwrap();
+        let mut result = self
+            .shared
+            .cond
+            .wait_while(result, |r| r.is_none())
+            .unwrap();
+        result.take().unwrap()
+    }
+}
+
+#[cfg(feature = "async")]
+impl std::future::Future for ListDevicesTask {
+    type Output = ListDevicesResult;
+
+    fn poll(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Self::Output> {
+        // the waker is stored before checking to avoid missing the wake-up
+        self.shared
+            .waker
+            .lock()
+            .unwrap()
+            .replace(cx.waker().clone());
+        match self.shared.result.lock().unwrap().take() {
+            Some(result) => std::task::Poll::Ready(result),
+            None => std::task::Poll::Pending,
+        }
+    }
+}
+
 /// Corresponds to `android.hardware.usb.UsbDevice`.
-/// Its fields and the `InterfaceInfo` list are read on creation and will not
-/// be updated automatically; however, `PartialEq` depends on these fields.
+/// Its fields and the `InterfaceInfo` list will not be updated automatically;
+/// however, `PartialEq` depends on these fields.
+///
+/// Note: Interfaces, configurations and strings are read on their first access (or by
+/// `load_details()`), so devices filtered out by IDs don't pay for these JNI calls. If the
+/// reading fails, nothing is cached and it is tried again on the next access; infallible
+/// accessors return empty values in this case, check `load_details()` for the error.
 #[derive(Clone, CopyGetters, Getters)]
 pub struct DeviceInfo {
     pub(crate) internal: jni::objects::GlobalRef,
@@ -44,93 +135,291 @@ pub struct DeviceInfo {
     #[getset(get_copy = "pub")]
     protocol: u8,
 
+    /// Integer ID assigned by Android, equals `bus_number * 1000 + device_address`.
+    /// It is unique among devices connected at the same time.
+    #[getset(get_copy = "pub")]
+    device_id: i32,
+
     /// (usually) Path of the device in the usbfs file system.
     #[getset(get = "pub")]
     path_name: String,
-    /// Vendor name.
-    #[getset(get = "pub")]
-    manufacturer_string: Option<String>,
-    /// Product name.
-    #[getset(get = "pub")]
-    product_string: Option<String>,
-    /// USB protocol version.
-    #[getset(get = "pub")]
+
+    strings: OnceLock<DeviceStrings>,
+    interfaces: OnceLock<Vec<InterfaceInfo>>,
+    configurations: OnceLock<Vec<ConfigurationInfo>>,
+}
+
+#[derive(Clone, Debug, Default)]
+struct DeviceStrings {
+    manufacturer: Option<String>,
+    product: Option<String>,
     version: Option<String>,
-    /// Device serial ID string. FIXME: On Android 10 and above, this is always `None`
-    /// if this struct is created before gaining permission for the device. To read it,
-    /// call `list_devices()` and find the device again after the permission is granted.
-    #[getset(get = "pub")]
     serial_number: Option<String>,
+}
 
-    interfaces: Vec<InterfaceInfo>,
+/// Returned by string accessors of `DeviceInfo` if reading the strings fails.
+static NO_STRINGS: DeviceStrings = DeviceStrings {
+    manufacturer: None,
+    product: None,
+    version: None,
+    serial_number: None,
+};
+
+/// Gets the value in `cell`, or loads it. Nothing is stored if `load` fails,
+/// so the next access tries again (`OnceLock::get_or_try_init()` is unstable).
+fn get_or_load<T>(
+    cell: &OnceLock<T>,
+    load: impl FnOnce() -> Result<T, Error>,
+) -> Result<&T, Error> {
+    if let Some(value) = cell.get() {
+        return Ok(value);
+    }
+    let _ = cell.set(load()?);
+    Ok(cell.get().unwrap())
 }
 
 impl DeviceInfo {
+    /// Reads the device information in a new local reference frame, so local references
+    /// of interfaces, endpoints, configurations and strings are freed together.
     pub(crate) fn build(env: &mut JNIEnv, dev: &JObject<'_>) -> Result<Self, Error> {
-        let num_interfaces = get_int_field(env, dev, "getInterfaceCount")? as u8;
-        let mut interface_refs = Vec::new();
-        for i in 0..num_interfaces {
-            interface_refs.push(
-                env.call_method(
-                    dev,
-                    "getInterface",
-                    "(I)Landroid/hardware/usb/UsbInterface;",
-                    &[(i as jint).into()],
-                )
-                .get_object(env)
-                .map_err(jerr)?,
-            );
-        }
-        let mut info = Self {
-            internal: env.new_global_ref(dev).map_err(jerr)?,
-
-            vendor_id: get_int_field(env, dev, "getVendorId")? as u16,
-            product_id: get_int_field(env, dev, "getProductId")? as u16,
-            class: get_int_field(env, dev, "getDeviceClass")? as u8,
-            subclass: get_int_field(env, dev, "getDeviceSubclass")? as u8,
-            protocol: get_int_field(env, dev, "getDeviceProtocol")? as u8,
-
-            path_name: get_string_field(env, dev, "getDeviceName")?,
-            manufacturer_string: None,
-            product_string: None,
-            version: None,
-            serial_number: None,
-
-            interfaces: {
+        with_local_frame(env, 16, |env| {
+            Ok(Self {
+                internal: env.new_global_ref(dev).map_err(jerr)?,
+
+                vendor_id: get_int_field(env, dev, "getVendorId")? as u16,
+                product_id: get_int_field(env, dev, "getProductId")? as u16,
+                class: get_int_field(env, dev, "getDeviceClass")? as u8,
+                subclass: get_int_field(env, dev, "getDeviceSubclass")? as u8,
+                protocol: get_int_field(env, dev, "getDeviceProtocol")? as u8,
+
+                device_id: get_int_field(env, dev, "getDeviceId")?,
+
+                path_name: get_string_field(env, dev, "getDeviceName")?,
+
+                strings: OnceLock::new(),
+                interfaces: OnceLock::new(),
+                configurations: OnceLock::new(),
+            })
+        })
+    }
+
+    /// Reads interfaces, configurations and strings now if they are not read yet, returns
+    /// the error of JNI calls. `list_devices_background()` does this in the background thread.
+    pub fn load_details(&self) -> Result<(), Error> {
+        self.strings()?;
+        self.interface_list()?;
+        self.configuration_list()?;
+        Ok(())
+    }
+
+    /// Vendor name.
+    pub fn manufacturer_string(&self) -> &Option<String> {
+        &self.strings().unwrap_or(&NO_STRINGS).manufacturer
+    }
+
+    /// Product name.
+    pub fn product_string(&self) -> &Option<String> {
+        &self.strings().unwrap_or(&NO_STRINGS).product
+    }
+
+    /// USB protocol version.
+    pub fn version(&self) -> &Option<String> {
+        &self.strings().unwrap_or(&NO_STRINGS).version
+    }
+
+    /// Device serial ID string. FIXME: On Android 10 and above, this is always `None`
+    /// if it is read before gaining permission for the device. To read it, call
+    /// `list_devices()` and find the device again after the permission is granted,
+    /// or call `fetch_strings()` after opening the device.
+    pub fn serial_number(&self) -> &Option<String> {
+        &self.strings().unwrap_or(&NO_STRINGS).serial_number
+    }
+
+    /// Runs `f` with the `UsbDevice` object in a new local reference frame.
+    fn load<T>(
+        &self,
+        f: impl FnOnce(&mut JNIEnv, &JObject<'_>) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let env = &mut jni_attach_vm().map_err(jerr)?;
+        with_local_frame(env, 32, |env| f(env, self.internal.as_obj()))
+    }
+
+    fn strings(&self) -> Result<&DeviceStrings, Error> {
+        get_or_load(&self.strings, || {
+            if android_api_level() < 21 {
+                return Ok(DeviceStrings::default());
+            }
+            self.load(|env, dev| {
+                Ok(DeviceStrings {
+                    manufacturer: get_string_field(env, dev, "getManufacturerName").ok(),
+                    product: get_string_field(env, dev, "getProductName").ok(),
+                    version: get_string_field(env, dev, "getVersion").ok(),
+                    serial_number: if android_api_level() < 29 {
+                        get_string_field(env, dev, "getSerialNumber").ok()
+                    } else {
+                        // Avoid printing `java.lang.SecurityException: User has not given permission...`
+                        env.call_method(dev, "getSerialNumber", "()Ljava/lang/String;", &[])
+                            .map_err(jni_clear_ex_silent)
+                            .get_object(env)
+                            .and_then(|o| o.get_string(env))
+                            .ok()
+                    },
+                })
+            })
+        })
+    }
+
+    fn interface_list(&self) -> Result<&Vec<InterfaceInfo>, Error> {
+        get_or_load(&self.interfaces, || {
+            self.load(|env, dev| {
+                let num_interfaces = get_int_field(env, dev, "getInterfaceCount")? as u8;
                 let mut interfaces = Vec::new();
-                for interface in interface_refs.into_iter() {
-                    interfaces.push(InterfaceInfo {
-                        interface_number: get_int_field(env, &interface, "getId")? as u8,
-                        class: get_int_field(env, &interface, "getInterfaceClass")? as u8,
-                        sub_class: get_int_field(env, &interface, "getInterfaceSubclass")? as u8,
-                        protocol: get_int_field(env, &interface, "getInterfaceProtocol")? as u8,
-                        num_endpoints: get_int_field(env, &interface, "getEndpointCount")? as u8,
-                    });
+                for i in 0..num_interfaces {
+                    let interface = env
+                        .call_method(
+                            dev,
+                            "getInterface",
+                            "(I)Landroid/hardware/usb/UsbInterface;",
+                            &[(i as jint).into()],
+                        )
+                        .get_object(env)
+                        .map_err(jerr)?;
+                    interfaces.push(InterfaceInfo::build(env, &interface)?);
+                    drop(env.auto_local(interface));
                 }
-                interfaces
-            },
-        };
-        if android_api_level() >= 21 {
-            info.version = Some(get_string_field(env, dev, "getVersion")?);
-            info.manufacturer_string = get_string_field(env, dev, "getManufacturerName").ok();
-            info.product_string = get_string_field(env, dev, "getProductName").ok();
-            info.serial_number = if android_api_level() < 29 {
-                get_string_field(env, dev, "getSerialNumber").ok()
-            } else {
-                // Avoid printing `java.lang.SecurityException: User has not given permission...`
-                env.call_method(dev, "getSerialNumber", "()Ljava/lang/String;", &[])
-                    .map_err(jni_clear_ex_silent)
-                    .get_object(env)
-                    .and_then(|o| o.get_string(env))
-                    .ok()
+                Ok(interfaces)
+            })
+        })
+    }
+
+    fn configuration_list(&self) -> Result<&Vec<ConfigurationInfo>, Error> {
+        get_or_load(&self.configurations, || {
+            if android_api_level() < 21 {
+                return Ok(Vec::new());
             }
-        }
-        Ok(info)
+            self.load(|env, dev| {
+                let num_configs = get_int_field(env, dev, "getConfigurationCount")? as u8;
+                let mut configurations = Vec::new();
+                for i in 0..num_configs {
+                    let config = env
+                        .call_method(
+                            dev,
+                            "getConfiguration",
+                            "(I)Landroid/hardware/usb/UsbConfiguration;",
+                            &[(i as jint).into()],
+                        )
+                        .get_object(env)
+                        .map_err(jerr)?;
+                    configurations.push(ConfigurationInfo::build(env, &config)?);
+                    drop(env.auto_local(config));
+                }
+                Ok(configurations)
+            })
+        })
     }
 
-    /// Iterator over the device's interfaces.
+    /// Iterator over the device's interfaces. Interfaces of all configurations are listed.
+    /// It is empty if reading them fails, check `try_interfaces()`.
     pub fn interfaces(&self) -> impl Iterator<Item = &InterfaceInfo> {
-        self.interfaces.iter()
+        self.interface_list()
+            .map(|l| l.as_slice())
+            .unwrap_or(&[])
+            .iter()
+    }
+
+    /// Iterator over the device's interfaces, or the error of reading them.
+    pub fn try_interfaces(&self) -> Result<impl Iterator<Item = &InterfaceInfo>, Error> {
+        self.interface_list().map(|l| l.iter())
+    }
+
+    /// Reads manufacturer, product and serial number strings with `GET_DESCRIPTOR` control
+    /// transfers for those fields which are `None` (no permission while this struct is
+    /// created, or Android 4.x); `device` should be opened by `open_device()`.
+    pub fn fetch_strings(&mut self, device: &nusb::Device, timeout: Duration) -> Result<(), Error> {
+        let strings = self.strings()?;
+        if strings.manufacturer.is_some()
+            && strings.product.is_some()
+            && strings.serial_number.is_some()
+        {
+            return Ok(());
+        }
+        let mut desc = [0u8; 18];
+        let len = get_descriptor(device, DESC_TYPE_DEVICE, 0, 0, &mut desc, timeout)?;
+        if len < desc.len() {
+            return Err(Error::new(ErrorKind::InvalidData, "Bad device descriptor"));
+        }
+        let (i_manufacturer, i_product, i_serial) = (desc[14], desc[15], desc[16]);
+
+        // the first language ID in string descriptor 0
+        let mut langs = [0u8; 4];
+        let len = get_descriptor(device, DESC_TYPE_STRING, 0, 0, &mut langs, timeout)?;
+        if len < 4 {
+            return Ok(()); // no string descriptor
+        }
+        let lang = u16::from_le_bytes([langs[2], langs[3]]);
+
+        let strings = self.strings.get_mut().unwrap(); // loaded above
+        for (field, index) in [
+            (&mut strings.manufacturer, i_manufacturer),
+            (&mut strings.product, i_product),
+            (&mut strings.serial_number, i_serial),
+        ] {
+            if field.is_none() && index != 0 {
+                *field = get_string_descriptor(device, index, lang, timeout).ok();
+            }
+        }
+        Ok(())
+    }
+
+    /// Iterator over the device's configurations. It is empty before Android 5.0, or if
+    /// reading them fails (check `try_configurations()`).
+    pub fn configurations(&self) -> impl Iterator<Item = &ConfigurationInfo> {
+        self.configuration_list()
+            .map(|l| l.as_slice())
+            .unwrap_or(&[])
+            .iter()
+    }
+
+    /// Iterator over the device's configurations, or the error of reading them.
+    pub fn try_configurations(&self) -> Result<impl Iterator<Item = &ConfigurationInfo>, Error> {
+        self.configuration_list().map(|l| l.iter())
+    }
+
+    /// Finds the configuration of given `bConfigurationValue`.
+    pub fn configuration(&self, id: u8) -> Option<&ConfigurationInfo> {
+        self.configurations().find(|conf| conf.id == id)
+    }
+
+    /// Bus number, parsed from `path_name` (`/dev/bus/usb/BBB/DDD`) or decomposed from
+    /// `device_id`. It matches `nusb::DeviceInfo::bus_number()` and `lsusb` output.
+    pub fn bus_number(&self) -> u16 {
+        self.usbfs_location()
+            .map(|(bus, _)| bus)
+            .unwrap_or((self.device_id / 1000) as u16)
+    }
+
+    /// Device address on the bus, parsed from `path_name` or decomposed from `device_id`.
+    /// It matches `nusb::DeviceInfo::device_address()` and `lsusb` output.
+    pub fn device_address(&self) -> u16 {
+        self.usbfs_location()
+            .map(|(_, addr)| addr)
+            .unwrap_or((self.device_id % 1000) as u16)
+    }
+
+    /// Parses the bus number and the device address from the usbfs path.
+    fn usbfs_location(&self) -> Option<(u16, u16)> {
+        let mut parts = self.path_name.strip_prefix("/dev/bus/usb/")?.split('/');
+        let bus = parts.next()?.parse().ok()?;
+        let addr = parts.next()?.parse().ok()?;
+        parts.next().is_none().then_some((bus, addr))
+    }
+
+    /// Returns the count of alternate settings of the given interface number,
+    /// 0 if the interface doesn't exist.
+    pub fn alternate_setting_count(&self, interface_number: u8) -> usize {
+        self.interfaces()
+            .filter(|intr| intr.interface_number == interface_number)
+            .count()
     }
 }
 
@@ -144,14 +433,21 @@ impl std::fmt::Debug for DeviceInfo {
         s.field("subclass", &format_args!("0x{:02X}", self.subclass));
         s.field("protocol", &format_args!("0x{:02X}", self.protocol));
 
+        s.field("device_id", &self.device_id);
         s.field("path_name", &self.path_name);
-        s.field("version", &self.version);
-        s.field("manufacturer_string", &self.manufacturer_string);
-        s.field("product_string", &self.product_string);
-        s.field("serial_number", &self.serial_number);
+        s.field("version", self.version());
+        s.field("manufacturer_string", self.manufacturer_string());
+        s.field("product_string", self.product_string());
+        s.field("serial_number", self.serial_number());
 
-        for intr in self.interfaces.iter() {
-            s.field("Interface", &intr);
+        if self.configurations().next().is_none() {
+            for intr in self.interfaces() {
+                s.field("Interface", &intr);
+            }
+        } else {
+            for conf in self.configurations() {
+                s.field("Configuration", &conf);
+            }
         }
         s.finish()
     }
@@ -161,9 +457,12 @@ impl PartialEq for DeviceInfo {
     fn eq(&self, other: &Self) -> bool {
         // Check `android.hardware.usb.UsbDevice.equals()` source code:
         // it may compare both `UsbDevice` only by name (`path_name`).
-        if let (Some(self_ser), Some(other_ser)) =
-            (self.serial_number.as_ref(), other.serial_number.as_ref())
-        {
+        // Serial numbers are compared only if both are loaded, avoiding JNI calls here.
+        let serial_numbers = (
+            self.strings.get().and_then(|s| s.serial_number.as_ref()),
+            other.strings.get().and_then(|s| s.serial_number.as_ref()),
+        );
+        if let (Some(self_ser), Some(other_ser)) = serial_numbers {
             if self_ser != other_ser {
                 return false;
             }
@@ -174,20 +473,198 @@ impl PartialEq for DeviceInfo {
     }
 }
 
-/// Corresponds to `android.hardware.usb.UsbInterface`.
-#[derive(Clone, Copy, CopyGetters)]
-#[getset(get_copy = "pub")]
+/// Corresponds to `android.hardware.usb.UsbConfiguration` (Android 5.0 and above).
+#[derive(Clone, CopyGetters, Getters)]
+pub struct ConfigurationInfo {
+    /// Equals `bConfigurationValue`.
+    #[getset(get_copy = "pub")]
+    id: u8,
+    /// Configuration name from the `iConfiguration` string descriptor.
+    #[getset(get = "pub")]
+    name: Option<String>,
+    /// Equals the self-powered bit of `bmAttributes`.
+    #[getset(get_copy = "pub")]
+    self_powered: bool,
+    /// Equals the remote wakeup bit of `bmAttributes`.
+    #[getset(get_copy = "pub")]
+    remote_wakeup: bool,
+    /// Maximum power consumption from the bus in milliamps (`bMaxPower` is converted).
+    #[getset(get_copy = "pub")]
+    max_power: u16,
+
+    interfaces: Vec<InterfaceInfo>,
+}
+
+impl ConfigurationInfo {
+    fn build(env: &mut JNIEnv, config: &JObject<'_>) -> Result<Self, Error> {
+        let num_interfaces = get_int_field(env, config, "getInterfaceCount")? as u8;
+        let mut interfaces = Vec::new();
+        for i in 0..num_interfaces {
+            let interface = env
+                .call_method(
+                    config,
+                    "getInterface",
+                    "(I)Landroid/hardware/usb/UsbInterface;",
+                    &[(i as jint).into()],
+                )
+                .get_object(env)
+                .map_err(jerr)?;
+            interfaces.push(InterfaceInfo::build(env, &interface)?);
+            drop(env.auto_local(interface));
+        }
+        Ok(Self {
+            id: get_int_field(env, config, "getId")? as u8,
+            name: get_string_field(env, config, "getName").ok(),
+            self_powered: get_bool_field(env, config, "isSelfPowered")?,
+            remote_wakeup: get_bool_field(env, config, "isRemoteWakeup")?,
+            max_power: get_int_field(env, config, "getMaxPower")? as u16,
+            interfaces,
+        })
+    }
+
+    /// Iterator over interfaces of this configuration.
+    pub fn interfaces(&self) -> impl Iterator<Item = &InterfaceInfo> {
+        self.interfaces.iter()
+    }
+
+    /// Returns true if the interface of given number belongs to this configuration.
+    pub fn has_interface(&self, interface_number: u8) -> bool {
+        self.interfaces()
+            .any(|intr| intr.interface_number() == interface_number)
+    }
+}
+
+impl std::fmt::Debug for ConfigurationInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut s = f.debug_struct("ConfigurationInfo");
+        s.field("id", &self.id);
+        s.field("name", &self.name);
+        s.field("self_powered", &self.self_powered);
+        s.field("remote_wakeup", &self.remote_wakeup);
+        s.field("max_power", &self.max_power);
+        for intr in self.interfaces.iter() {
+            s.field("Interface", &intr);
+        }
+        s.finish()
+    }
+}
+
+/// Corresponds to `android.hardware.usb.UsbInterface`. Each alternate setting of an
+/// interface is listed as a separate `InterfaceInfo` with the same interface number.
+#[derive(Clone, CopyGetters, Getters)]
 pub struct InterfaceInfo {
     /// Equals `bInterfaceNumber`.
+    #[getset(get_copy = "pub")]
     interface_number: u8,
     /// Equals `bInterfaceClass`.
+    #[getset(get_copy = "pub")]
     class: u8,
     /// Equals `bInterfaceSubClass`.
+    #[getset(get_copy = "pub")]
     sub_class: u8,
     /// Equals `bInterfaceProtocol`.
+    #[getset(get_copy = "pub")]
     protocol: u8,
     /// Equals `bNumEndpoints`.
+    #[getset(get_copy = "pub")]
     num_endpoints: u8,
+    /// Equals `bAlternateSetting`. It is always 0 before Android 5.0.
+    #[getset(get_copy = "pub")]
+    alternate_setting: u8,
+    /// Interface name from the `iInterface` string descriptor, e.g. "Debug UART".
+    /// It is always `None` before Android 5.0.
+    #[getset(get = "pub")]
+    name: Option<String>,
+
+    endpoints: Vec<EndpointInfo>,
+}
+
+impl InterfaceInfo {
+    fn build(env: &mut JNIEnv, interface: &JObject<'_>) -> Result<Self, Error> {
+        let num_endpoints = get_int_field(env, interface, "getEndpointCount")? as u8;
+        let mut endpoints = Vec::new();
+        for i in 0..num_endpoints {
+            let endpoint = env
+                .call_method(
+                    interface,
+                    "getEndpoint",
+                    "(I)Landroid/hardware/usb/UsbEndpoint;",
+                    &[(i as jint).into()],
+                )
+                .get_object(env)
+                .map_err(jerr)?;
+            endpoints.push(EndpointInfo {
+                address: get_int_field(env, &endpoint, "getAddress")? as u8,
+                attributes: get_int_field(env, &endpoint, "getAttributes")? as u8,
+                max_packet_size: get_int_field(env, &endpoint, "getMaxPacketSize")? as u16,
+                interval: get_int_field(env, &endpoint, "getInterval")? as u8,
+            });
+            drop(env.auto_local(endpoint));
+        }
+        let (alternate_setting, name) = if android_api_level() >= 21 {
+            (
+                get_int_field(env, interface, "getAlternateSetting")? as u8,
+                get_string_field(env, interface, "getName").ok(),
+            )
+        } else {
+            (0, None)
+        };
+        Ok(Self {
+            interface_number: get_int_field(env, interface, "getId")? as u8,
+            class: get_int_field(env, interface, "getInterfaceClass")? as u8,
+            sub_class: get_int_field(env, interface, "getInterfaceSubclass")? as u8,
+            protocol: get_int_field(env, interface, "getInterfaceProtocol")? as u8,
+            num_endpoints,
+            alternate_setting,
+            name,
+            endpoints,
+        })
+    }
+
+    /// Iterator over the interface's endpoints.
+    pub fn endpoints(&self) -> impl Iterator<Item = &EndpointInfo> {
+        self.endpoints.iter()
+    }
+
+    /// Finds the first endpoint of given direction and transfer type, so endpoints can be
+    /// picked before opening the device through `nusb`.
+    pub fn find_endpoint(
+        &self,
+        direction: Direction,
+        transfer_type: EndpointType,
+    ) -> Option<&EndpointInfo> {
+        self.endpoints()
+            .find(|endp| endp.direction() == direction && endp.transfer_type() == transfer_type)
+    }
+}
+
+#[cfg(test)]
+impl InterfaceInfo {
+    /// Creates an interface of `(class, sub_class)` with `(address, attributes)` endpoints.
+    pub(crate) fn with_endpoints(
+        interface_number: u8,
+        (class, sub_class): (u8, u8),
+        endpoints: &[(u8, u8)],
+    ) -> Self {
+        Self {
+            interface_number,
+            class,
+            sub_class,
+            protocol: 0,
+            num_endpoints: endpoints.len() as u8,
+            alternate_setting: 0,
+            name: None,
+            endpoints: endpoints
+                .iter()
+                .map(|&(address, attributes)| EndpointInfo {
+                    address,
+                    attributes,
+                    max_packet_size: 64,
+                    interval: 0,
+                })
+                .collect(),
+        }
+    }
 }
 
 impl std::fmt::Debug for InterfaceInfo {
@@ -198,10 +675,166 @@ impl std::fmt::Debug for InterfaceInfo {
             .field("sub_class", &format_args!("0x{:02X}", self.sub_class))
             .field("protocol", &format_args!("0x{:02X}", self.protocol))
             .field("num_endpoints", &self.num_endpoints)
+            .field("alternate_setting", &self.alternate_setting)
+            .field("name", &self.name)
+            .field("endpoints", &self.endpoints)
+            .finish()
+    }
+}
+
+/// Corresponds to `android.hardware.usb.UsbEndpoint`.
+#[derive(Clone, Copy, PartialEq, Eq, CopyGetters)]
+#[getset(get_copy = "pub")]
+pub struct EndpointInfo {
+    /// Equals `bEndpointAddress`, including the direction bit.
+    address: u8,
+    /// Equals `bmAttributes`.
+    attributes: u8,
+    /// Equals `wMaxPacketSize`.
+    max_packet_size: u16,
+    /// Equals `bInterval`.
+    interval: u8,
+}
+
+impl EndpointInfo {
+    /// Gets the direction from the endpoint address.
+    pub fn direction(&self) -> Direction {
+        if self.address & 0x80 != 0 {
+            Direction::In
+        } else {
+            Direction::Out
+        }
+    }
+
+    /// Gets the endpoint number (address without the direction bit).
+    pub fn number(&self) -> u8 {
+        self.address & 0x0F
+    }
+
+    /// Gets the transfer type from the endpoint attributes.
+    pub fn transfer_type(&self) -> EndpointType {
+        match self.attributes & 0x03 {
+            0 => EndpointType::Control,
+            1 => EndpointType::Isochronous,
+            2 => EndpointType::Bulk,
+            _ => EndpointType::Interrupt,
+        }
+    }
+}
+
+impl std::fmt::Debug for EndpointInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EndpointInfo")
+            .field("address", &format_args!("0x{:02X}", self.address))
+            .field("direction", &self.direction())
+            .field("transfer_type", &self.transfer_type())
+            .field("max_packet_size", &self.max_packet_size)
+            .field("interval", &self.interval)
             .finish()
     }
 }
 
+/// Corresponds to `android.hardware.usb.UsbAccessory`, which is a USB host device
+/// connected to the Android device in accessory mode (Android Open Accessory).
+#[derive(Clone, Debug, PartialEq, Eq, Getters)]
+#[getset(get = "pub")]
+pub struct AccessoryInfo {
+    /// Manufacturer name.
+    manufacturer: String,
+    /// Model name.
+    model: String,
+    /// User visible description.
+    description: Option<String>,
+    /// Accessory version.
+    version: Option<String>,
+    /// URI of a web page for the accessory.
+    uri: Option<String>,
+    /// Unique serial number; it might be `None` without permission.
+    serial: Option<String>,
+}
+
+impl AccessoryInfo {
+    pub(crate) fn build(env: &mut JNIEnv, acc: &JObject<'_>) -> Result<Self, Error> {
+        Ok(Self {
+            manufacturer: get_string_field(env, acc, "getManufacturer")?,
+            model: get_string_field(env, acc, "getModel")?,
+            description: get_string_field(env, acc, "getDescription").ok(),
+            version: get_string_field(env, acc, "getVersion").ok(),
+            uri: get_string_field(env, acc, "getUri").ok(),
+            serial: env
+                .call_method(acc, "getSerial", "()Ljava/lang/String;", &[])
+                .map_err(jni_clear_ex_silent)
+                .get_object(env)
+                .and_then(|o| o.get_string(env))
+                .ok(),
+        })
+    }
+}
+
+const DESC_TYPE_DEVICE: u8 = 0x01;
+const DESC_TYPE_STRING: u8 = 0x03;
+
+fn get_descriptor(
+    device: &nusb::Device,
+    desc_type: u8,
+    desc_index: u8,
+    language: u16,
+    buf: &mut [u8],
+    timeout: Duration,
+) -> Result<usize, Error> {
+    device
+        .control_in_blocking(
+            Control {
+                control_type: ControlType::Standard,
+                recipient: Recipient::Device,
+                request: 0x06, // GET_DESCRIPTOR
+                value: ((desc_type as u16) << 8) | desc_index as u16,
+                index: language,
+            },
+            buf,
+            timeout,
+        )
+        .map_err(crate::usb::map_transfer_error)
+}
+
+fn get_string_descriptor(
+    device: &nusb::Device,
+    desc_index: u8,
+    language: u16,
+    timeout: Duration,
+) -> Result<String, Error> {
+    let mut buf = [0u8; 255];
+    let len = get_descriptor(
+        device,
+        DESC_TYPE_STRING,
+        desc_index,
+        language,
+        &mut buf,
+        timeout,
+    )?;
+    // bLength, bDescriptorType, then UTF-16LE characters
+    let len = (buf[0] as usize).min(len);
+    if len < 2 || buf[1] != DESC_TYPE_STRING {
+        return Err(Error::new(ErrorKind::InvalidData, "Bad string descriptor"));
+    }
+    let chars: Vec<u16> = buf[2..len]
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .collect();
+    Ok(String::from_utf16_lossy(&chars))
+}
+
+/// Runs `f` in a new JNI local reference frame of the given capacity, which avoids hitting
+/// the local reference limit while enumerating many devices (e.g. behind big hubs).
+pub(crate) fn with_local_frame<T>(
+    env: &mut JNIEnv,
+    capacity: i32,
+    f: impl FnOnce(&mut JNIEnv) -> Result<T, Error>,
+) -> Result<T, Error> {
+    env.with_local_frame(capacity, |env| Ok::<_, jni::errors::Error>(f(env)))
+        .map_err(jerr)?
+}
+
 // These functions call java methods without parameter. Error::Other on failure.
 #[inline(always)]
 fn get_int_field(env: &mut JNIEnv, dev: &JObject<'_>, method: &str) -> Result<jint, Error> {
@@ -210,6 +843,12 @@ fn get_int_field(env: &mut JNIEnv, dev: &JObject<'_>, method: &str) -> Result<ji
         .map_err(jerr)
 }
 #[inline(always)]
+fn get_bool_field(env: &mut JNIEnv, dev: &JObject<'_>, method: &str) -> Result<bool, Error> {
+    env.call_method(dev, method, "()Z", &[])
+        .get_boolean()
+        .map_err(jerr)
+}
+#[inline(always)]
 fn get_string_field(env: &mut JNIEnv, dev: &JObject<'_>, method: &str) -> Result<String, Error> {
     env.call_method(dev, method, "()Ljava/lang/String;", &[])
         .get_object(env)
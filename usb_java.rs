@@ -0,0 +1,697 @@
+// The JNI backends are used by `CdcSerial` only.
+#![cfg_attr(not(feature = "blocking"), allow(dead_code))]
+
+use jni::objects::{GlobalRef, JObject};
+use jni::sys::{jint, jlong};
+use jni_min_helper::*;
+
+use crate::usb::{jerr, with_local_frame, DeviceInfo};
+use crate::Error;
+use nusb::transfer::{Control, ControlType, Direction, Recipient, TransferError};
+use std::{
+    collections::VecDeque,
+    io::ErrorKind,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+const REQUEST_SIZE: usize = 16384; // maximum `UsbRequest` buffer size before API 28
+const IN_QUEUE_DEPTH: usize = 2; // amount of IN requests kept queued
+const OUT_QUEUE_DEPTH: usize = 4; // amount of OUT requests queued before waiting
+const WAIT_SLICE: Duration = Duration::from_millis(100); // for checking reaped requests
+const CANCEL_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Opened `android.hardware.usb.UsbDeviceConnection` doing transfers by its own methods
+/// instead of `nusb`, for devices or ROMs on which `nusb::Device::from_fd()` misbehaves.
+/// The connection is closed on drop, which releases claimed interfaces.
+pub(crate) struct JavaConnection {
+    conn: GlobalRef,      // `android.hardware.usb.UsbDeviceConnection`
+    dev_info: DeviceInfo, // for telling disconnection from other failures
+
+    completed: Mutex<Vec<GlobalRef>>, // `UsbRequest`s reaped by waiters of other queues
+}
+
+impl JavaConnection {
+    /// Opens the device. Returns `PermissionDenied` error if the permission is not granted.
+    pub(crate) fn open(dev_info: &DeviceInfo) -> Result<Self, Error> {
+        let env = &mut jni_attach_vm().map_err(jerr)?;
+        let conn = dev_info.open_java_connection(env)?;
+        Ok(Self {
+            conn,
+            dev_info: dev_info.clone(),
+            completed: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// Returns the `android.hardware.usb.UsbDeviceConnection` object.
+    pub(crate) fn java_connection(&self) -> &JObject<'static> {
+        self.conn.as_obj()
+    }
+
+    /// Returns the raw descriptors, like `DeviceInfo::raw_descriptors()`.
+    pub(crate) fn raw_descriptors(&self) -> Result<Vec<u8>, Error> {
+        let env = &mut jni_attach_vm().map_err(jerr)?;
+        with_local_frame(env, 4, |env| {
+            let descs = env
+                .call_method(&self.conn, "getRawDescriptors", "()[B", &[])
+                .get_object(env)
+                .map_err(jerr)?;
+            if descs.is_null() {
+                return Err(Error::other("`getRawDescriptors()` failed"));
+            }
+            env.convert_byte_array(jni::objects::JByteArray::from(descs))
+                .map_err(jerr)
+        })
+    }
+
+    /// Claims the interface (alternate setting 0) forcibly, returns the Java
+    /// `UsbInterface` object for looking up its endpoints.
+    pub(crate) fn claim_interface(&self, interface_number: u8) -> Result<GlobalRef, Error> {
+        let env = &mut jni_attach_vm().map_err(jerr)?;
+        with_local_frame(env, 16, |env| {
+            let dev = self.dev_info.internal.as_obj();
+            let count = env
+                .call_method(dev, "getInterfaceCount", "()I", &[])
+                .get_int()
+                .map_err(jerr)?;
+            for i in 0..count {
+                let intr = env
+                    .call_method(
+                        dev,
+                        "getInterface",
+                        "(I)Landroid/hardware/usb/UsbInterface;",
+                        &[i.into()],
+                    )
+                    .get_object(env)
+                    .map_err(jerr)?;
+                let id = env
+                    .call_method(&intr, "getId", "()I", &[])
+                    .get_int()
+                    .map_err(jerr)?;
+                let alt = if android_api_level() >= 21 {
+                    env.call_method(&intr, "getAlternateSetting", "()I", &[])
+                        .get_int()
+                        .map_err(jerr)?
+                } else {
+                    0
+                };
+                if id != interface_number as jint || alt != 0 {
+                    continue;
+                }
+                let claimed = env
+                    .call_method(
+                        &self.conn,
+                        "claimInterface",
+                        "(Landroid/hardware/usb/UsbInterface;Z)Z",
+                        &[(&intr).into(), true.into()],
+                    )
+                    .get_boolean()
+                    .map_err(jerr)?;
+                if !claimed {
+                    return Err(Error::other("`claimInterface()` failed"));
+                }
+                return env.new_global_ref(&intr).map_err(jerr);
+            }
+            Err(Error::new(ErrorKind::NotFound, "Interface not found"))
+        })
+    }
+
+    /// Finds the Java `UsbEndpoint` object of `address` in the claimed interface.
+    pub(crate) fn endpoint(&self, interface: &GlobalRef, address: u8) -> Result<GlobalRef, Error> {
+        let env = &mut jni_attach_vm().map_err(jerr)?;
+        with_local_frame(env, 16, |env| {
+            let count = env
+                .call_method(interface, "getEndpointCount", "()I", &[])
+                .get_int()
+                .map_err(jerr)?;
+            for i in 0..count {
+                let endp = env
+                    .call_method(
+                        interface,
+                        "getEndpoint",
+                        "(I)Landroid/hardware/usb/UsbEndpoint;",
+                        &[i.into()],
+                    )
+                    .get_object(env)
+                    .map_err(jerr)?;
+                let addr = env
+                    .call_method(&endp, "getAddress", "()I", &[])
+                    .get_int()
+                    .map_err(jerr)?;
+                if addr == address as jint {
+                    return env.new_global_ref(&endp).map_err(jerr);
+                }
+            }
+            Err(Error::new(ErrorKind::NotFound, "Endpoint not found"))
+        })
+    }
+
+    /// Does a control IN transfer by `UsbDeviceConnection.controlTransfer()`.
+    pub(crate) fn control_in(
+        &self,
+        control: Control,
+        buf: &mut [u8],
+        timeout: Duration,
+    ) -> Result<usize, TransferError> {
+        let t_start = Instant::now();
+        let request_type = request_type(Direction::In, &control);
+        let result = self.with_env(|env| {
+            call_control_transfer(
+                env,
+                &self.conn,
+                (request_type, control.request, control.value, control.index),
+                ControlData::In(buf),
+                timeout,
+            )
+        });
+        self.map_result(result, t_start, timeout)
+    }
+
+    /// Does a control OUT transfer by `UsbDeviceConnection.controlTransfer()`.
+    pub(crate) fn control_out(
+        &self,
+        control: Control,
+        data: &[u8],
+        timeout: Duration,
+    ) -> Result<usize, TransferError> {
+        let t_start = Instant::now();
+        let request_type = request_type(Direction::Out, &control);
+        let result = self.with_env(|env| {
+            call_control_transfer(
+                env,
+                &self.conn,
+                (request_type, control.request, control.value, control.index),
+                ControlData::Out(data),
+                timeout,
+            )
+        });
+        self.map_result(result, t_start, timeout)
+    }
+
+    /// Does a bulk IN transfer by `UsbDeviceConnection.bulkTransfer()`.
+    pub(crate) fn bulk_in(
+        &self,
+        endpoint: &GlobalRef,
+        buf: &mut [u8],
+        timeout: Duration,
+    ) -> Result<usize, Error> {
+        let t_start = Instant::now();
+        let result = self.with_env(|env| {
+            let arr = env.new_byte_array(buf.len() as jint).map_err(jerr)?;
+            let len = env
+                .call_method(
+                    &self.conn,
+                    "bulkTransfer",
+                    "(Landroid/hardware/usb/UsbEndpoint;[BII)I",
+                    &[
+                        endpoint.as_obj().into(),
+                        (&arr).into(),
+                        (buf.len() as jint).into(),
+                        timeout_millis(timeout).into(),
+                    ],
+                )
+                .get_int()
+                .map_err(jerr)?;
+            if len > 0 {
+                let data = env.convert_byte_array(&arr).map_err(jerr)?;
+                buf[..len as usize].copy_from_slice(&data[..len as usize]);
+            }
+            Ok(len)
+        });
+        self.map_bulk_result(result, t_start, timeout)
+    }
+
+    /// Does a bulk OUT transfer by `UsbDeviceConnection.bulkTransfer()`.
+    pub(crate) fn bulk_out(
+        &self,
+        endpoint: &GlobalRef,
+        data: &[u8],
+        timeout: Duration,
+    ) -> Result<usize, Error> {
+        let t_start = Instant::now();
+        let result = self.with_env(|env| {
+            let arr = env.byte_array_from_slice(data).map_err(jerr)?;
+            env.call_method(
+                &self.conn,
+                "bulkTransfer",
+                "(Landroid/hardware/usb/UsbEndpoint;[BII)I",
+                &[
+                    endpoint.as_obj().into(),
+                    (&arr).into(),
+                    (data.len() as jint).into(),
+                    timeout_millis(timeout).into(),
+                ],
+            )
+            .get_int()
+            .map_err(jerr)
+        });
+        self.map_bulk_result(result, t_start, timeout)
+    }
+
+    fn with_env(
+        &self,
+        f: impl FnOnce(&mut jni::JNIEnv) -> Result<jint, Error>,
+    ) -> Result<jint, Error> {
+        let env = &mut jni_attach_vm().map_err(jerr)?;
+        with_local_frame(env, 4, f)
+    }
+
+    /// The Java API returns -1 on any failure; the cause is guessed here.
+    fn map_result(
+        &self,
+        result: Result<jint, Error>,
+        t_start: Instant,
+        timeout: Duration,
+    ) -> Result<usize, TransferError> {
+        match result {
+            Ok(len) if len >= 0 => Ok(len as usize),
+            _ if !self.dev_info.check_connection() => Err(TransferError::Disconnected),
+            _ if t_start.elapsed() >= timeout => Err(TransferError::Cancelled),
+            _ => Err(TransferError::Unknown),
+        }
+    }
+
+    fn map_bulk_result(
+        &self,
+        result: Result<jint, Error>,
+        t_start: Instant,
+        timeout: Duration,
+    ) -> Result<usize, Error> {
+        self.map_result(result, t_start, timeout)
+            .map_err(|e| match e {
+                TransferError::Cancelled => Error::from(ErrorKind::TimedOut),
+                e => crate::usb::map_transfer_error(e),
+            })
+    }
+
+    /// Calls `UsbDeviceConnection.requestWait(long)` (API 26+). Returns `Ok(None)` on
+    /// timeout.
+    fn request_wait(&self, timeout: Duration) -> Result<Option<GlobalRef>, Error> {
+        let env = &mut jni_attach_vm().map_err(jerr)?;
+        with_local_frame(env, 4, |env| {
+            let result = env.call_method(
+                &self.conn,
+                "requestWait",
+                "(J)Landroid/hardware/usb/UsbRequest;",
+                &[(timeout_millis(timeout) as jlong).into()],
+            );
+            let request = match result {
+                Ok(request) => request.l().map_err(jerr)?,
+                Err(jni::errors::Error::JavaException) => {
+                    let ex = env.exception_occurred().map_err(jerr)?;
+                    env.exception_clear().map_err(jerr)?;
+                    if env
+                        .is_instance_of(&ex, "java/util/concurrent/TimeoutException")
+                        .map_err(jerr)?
+                    {
+                        return Ok(None);
+                    }
+                    JObject::null()
+                }
+                Err(e) => return Err(jerr(e)),
+            };
+            if request.is_null() {
+                return Err(if self.dev_info.check_connection() {
+                    Error::other("`requestWait()` failed")
+                } else {
+                    Error::from(ErrorKind::NotConnected)
+                });
+            }
+            env.new_global_ref(&request).map(Some).map_err(jerr)
+        })
+    }
+
+    /// Takes `request` from requests reaped by other waiters, returns true if it is found.
+    fn take_completed(&self, env: &mut jni::JNIEnv, request: &GlobalRef) -> bool {
+        let mut completed = self.completed.lock().unwrap_or_else(|e| e.into_inner());
+        let found = completed
+            .iter()
+            .position(|req| env.is_same_object(req, request).unwrap_or(false));
+        found.map(|i| completed.swap_remove(i)).is_some()
+    }
+
+    fn put_completed(&self, request: GlobalRef) {
+        let mut completed = self.completed.lock().unwrap_or_else(|e| e.into_inner());
+        completed.push(request);
+    }
+}
+
+impl Drop for JavaConnection {
+    fn drop(&mut self) {
+        if let Ok(env) = &mut jni_attach_vm() {
+            let _ = env.call_method(&self.conn, "close", "()V", &[]).clear_ex();
+        }
+    }
+}
+
+impl std::fmt::Debug for JavaConnection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JavaConnection")
+            .field("dev_info", &self.dev_info)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Queued `android.hardware.usb.UsbRequest`s of an endpoint, reaped by
+/// `UsbDeviceConnection.requestWait(long)`, which requires API 26.
+pub(crate) struct JavaRequestQueue {
+    conn: Arc<JavaConnection>,
+    endpoint: GlobalRef, // `android.hardware.usb.UsbEndpoint`
+    pending: VecDeque<JavaRequest>,
+}
+
+struct JavaRequest {
+    request: GlobalRef, // `android.hardware.usb.UsbRequest`
+    buffer: GlobalRef,  // direct `java.nio.ByteBuffer` of `data`
+    data: Box<[u8]>,    // accessed by the kernel until the request is reaped
+}
+
+impl JavaRequestQueue {
+    /// Returns `Unsupported` error before API 26.
+    pub(crate) fn new(conn: Arc<JavaConnection>, endpoint: GlobalRef) -> Result<Self, Error> {
+        if android_api_level() < 26 {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                "`UsbRequest` backend requires Android 8.0",
+            ));
+        }
+        Ok(Self {
+            conn,
+            endpoint,
+            pending: VecDeque::new(),
+        })
+    }
+
+    /// Amount of requests not reaped yet.
+    pub(crate) fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Queues a request of `data` (OUT), or a request of `data.len()` bytes (IN).
+    pub(crate) fn submit(&mut self, data: Vec<u8>) -> Result<(), Error> {
+        let mut data = data.into_boxed_slice();
+        let env = &mut jni_attach_vm().map_err(jerr)?;
+        let (request, buffer) = with_local_frame(env, 8, |env| {
+            let request = env
+                .new_object("android/hardware/usb/UsbRequest", "()V", &[])
+                .map_err(jerr)?;
+            let initialized = env
+                .call_method(
+                    &request,
+                    "initialize",
+                    "(Landroid/hardware/usb/UsbDeviceConnection;Landroid/hardware/usb/UsbEndpoint;)Z",
+                    &[self.conn.conn.as_obj().into(), self.endpoint.as_obj().into()],
+                )
+                .get_boolean()
+                .map_err(jerr)?;
+            if !initialized {
+                return Err(Error::other("`UsbRequest.initialize()` failed"));
+            }
+            // Safety: `data` is kept in `JavaRequest` until the request is reaped.
+            let buffer = unsafe { env.new_direct_byte_buffer(data.as_mut_ptr(), data.len()) }
+                .map_err(jerr)?;
+            let queued = env
+                .call_method(
+                    &request,
+                    "queue",
+                    "(Ljava/nio/ByteBuffer;)Z",
+                    &[(&buffer).into()],
+                )
+                .get_boolean()
+                .map_err(jerr)?;
+            if !queued {
+                let _ = env.call_method(&request, "close", "()V", &[]).clear_ex();
+                return Err(if self.conn.dev_info.check_connection() {
+                    Error::other("`UsbRequest.queue()` failed")
+                } else {
+                    Error::from(ErrorKind::NotConnected)
+                });
+            }
+            Ok((
+                env.new_global_ref(&request).map_err(jerr)?,
+                env.new_global_ref(&buffer).map_err(jerr)?,
+            ))
+        })?;
+        self.pending.push_back(JavaRequest {
+            request,
+            buffer,
+            data,
+        });
+        Ok(())
+    }
+
+    /// Waits for the oldest request, returns its buffer and the transferred length.
+    /// Returns `TimedOut` error if it is not completed, in which case it is kept queued.
+    pub(crate) fn wait_next(&mut self, timeout: Duration) -> Result<(Vec<u8>, usize), Error> {
+        let head = self
+            .pending
+            .front()
+            .ok_or(Error::new(ErrorKind::NotFound, "No pending request"))?;
+        let t_start = Instant::now();
+        let env = &mut jni_attach_vm().map_err(jerr)?;
+        loop {
+            if self.conn.take_completed(env, &head.request) {
+                break;
+            }
+            let remaining = timeout.saturating_sub(t_start.elapsed());
+            if remaining.is_zero() {
+                return Err(Error::from(ErrorKind::TimedOut));
+            }
+            match self.conn.request_wait(remaining.min(WAIT_SLICE))? {
+                Some(req) if env.is_same_object(&req, &head.request).map_err(jerr)? => break,
+                Some(req) => self.conn.put_completed(req),
+                None => (),
+            }
+        }
+        let req = self.pending.pop_front().unwrap();
+        let len = env
+            .call_method(&req.buffer, "position", "()I", &[])
+            .get_int()
+            .map_err(jerr)?;
+        let _ = env
+            .call_method(&req.request, "close", "()V", &[])
+            .clear_ex();
+        Ok((req.data.into_vec(), len.max(0) as usize))
+    }
+
+    /// Cancels all pending requests and reaps them.
+    pub(crate) fn cancel_all(&mut self) {
+        let Ok(env) = &mut jni_attach_vm() else {
+            return;
+        };
+        for req in self.pending.iter() {
+            let _ = env
+                .call_method(&req.request, "cancel", "()Z", &[])
+                .clear_ex();
+        }
+        while !self.pending.is_empty() {
+            if self.wait_next(CANCEL_TIMEOUT).is_err() {
+                // the kernel may still access the buffers
+                let leaked = std::mem::take(&mut self.pending);
+                std::mem::forget(leaked);
+                return;
+            }
+        }
+    }
+}
+
+impl Drop for JavaRequestQueue {
+    fn drop(&mut self) {
+        self.cancel_all();
+    }
+}
+
+/// Bulk IN reader keeping `UsbRequest`s queued, so data arriving between reads is not
+/// dropped by the device; data beyond the caller's buffer is kept for the next read.
+pub(crate) struct JavaReader {
+    queue: JavaRequestQueue,
+    staged: Vec<u8>,   // received data not consumed yet
+    staged_pos: usize, // amount of consumed bytes in `staged`
+}
+
+impl JavaReader {
+    pub(crate) fn new(queue: JavaRequestQueue) -> Self {
+        Self {
+            queue,
+            staged: Vec::new(),
+            staged_pos: 0,
+        }
+    }
+
+    pub(crate) fn read(&mut self, buf: &mut [u8], timeout: Duration) -> Result<usize, Error> {
+        if self.staged_pos >= self.staged.len() {
+            while self.queue.pending() < IN_QUEUE_DEPTH {
+                self.queue.submit(vec![0u8; REQUEST_SIZE])?;
+            }
+            let (data, len) = self.queue.wait_next(timeout)?;
+            self.staged = data;
+            self.staged.truncate(len);
+            self.staged_pos = 0;
+        }
+        let staged = &self.staged[self.staged_pos..];
+        let len = staged.len().min(buf.len());
+        buf[..len].copy_from_slice(&staged[..len]);
+        self.staged_pos += len;
+        Ok(len)
+    }
+}
+
+/// Bulk OUT writer queueing `UsbRequest`s; a write returns once the data is queued,
+/// unless too many requests are pending.
+pub(crate) struct JavaWriter {
+    queue: JavaRequestQueue,
+}
+
+impl JavaWriter {
+    pub(crate) fn new(queue: JavaRequestQueue) -> Self {
+        Self { queue }
+    }
+
+    pub(crate) fn write(&mut self, buf: &[u8], timeout: Duration) -> Result<usize, Error> {
+        if self.queue.pending() >= OUT_QUEUE_DEPTH {
+            self.reap_next(timeout)?;
+        }
+        let len = buf.len().min(REQUEST_SIZE);
+        self.queue.submit(buf[..len].to_vec())?;
+        Ok(len)
+    }
+
+    /// Waits until all queued data is sent.
+    pub(crate) fn drain(&mut self, timeout: Duration) -> Result<(), Error> {
+        let t_start = Instant::now();
+        while self.queue.pending() > 0 {
+            self.reap_next(timeout.saturating_sub(t_start.elapsed()))?;
+        }
+        Ok(())
+    }
+
+    fn reap_next(&mut self, timeout: Duration) -> Result<(), Error> {
+        let (data, len) = self.queue.wait_next(timeout)?;
+        if len < data.len() {
+            return Err(Error::new(
+                ErrorKind::WriteZero,
+                "Queued data is not fully sent",
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Does a control transfer through `UsbDeviceConnection.controlTransfer()`, so vendor
+/// requests can be issued on a connection used by the JNI backends (check
+/// `CdcSerial::java_connection()`) or kept by `UsbConnection::java_connection()`.
+/// The direction is given by bit 7 of `request_type`: `buf` is filled for IN transfers,
+/// sent for OUT transfers. Returns the transferred length.
+///
+/// The Java API doesn't report the cause of failures: `TimedOut` error is returned if
+/// the timeout has elapsed, otherwise an error of `ErrorKind::Other`.
+pub fn control_transfer(
+    conn: &JObject<'_>,
+    request_type: u8,
+    request: u8,
+    value: u16,
+    index: u16,
+    buf: &mut [u8],
+    timeout: Duration,
+) -> Result<usize, Error> {
+    let t_start = Instant::now();
+    let data = if request_type & 0x80 != 0 {
+        ControlData::In(buf)
+    } else {
+        ControlData::Out(buf)
+    };
+    let env = &mut jni_attach_vm().map_err(jerr)?;
+    let len = with_local_frame(env, 4, |env| {
+        call_control_transfer(
+            env,
+            conn,
+            (request_type as jint, request, value, index),
+            data,
+            timeout,
+        )
+    })?;
+    match len {
+        len if len >= 0 => Ok(len as usize),
+        _ if t_start.elapsed() >= timeout => Err(Error::from(ErrorKind::TimedOut)),
+        _ => Err(Error::other("`controlTransfer()` failed")),
+    }
+}
+
+/// Data stage of a control transfer.
+enum ControlData<'a> {
+    In(&'a mut [u8]),
+    Out(&'a [u8]),
+}
+
+/// Calls `UsbDeviceConnection.controlTransfer()`, returns its result (-1 on failure).
+/// - `setup`: `bmRequestType`, `bRequest`, `wValue` and `wIndex`.
+fn call_control_transfer(
+    env: &mut jni::JNIEnv,
+    conn: &JObject<'_>,
+    setup: (jint, u8, u16, u16),
+    data: ControlData<'_>,
+    timeout: Duration,
+) -> Result<jint, Error> {
+    let (request_type, request, value, index) = setup;
+    let len = match &data {
+        ControlData::In(buf) => buf.len(),
+        ControlData::Out(data) => data.len(),
+    };
+    let arr: JObject = match &data {
+        _ if len == 0 => JObject::null(),
+        ControlData::In(_) => env.new_byte_array(len as jint).map_err(jerr)?.into(),
+        ControlData::Out(data) => env.byte_array_from_slice(data).map_err(jerr)?.into(),
+    };
+    let result = env
+        .call_method(
+            conn,
+            "controlTransfer",
+            "(IIII[BII)I",
+            &[
+                request_type.into(),
+                (request as jint).into(),
+                (value as jint).into(),
+                (index as jint).into(),
+                (&arr).into(),
+                (len as jint).into(),
+                timeout_millis(timeout).into(),
+            ],
+        )
+        .get_int()
+        .map_err(jerr)?;
+    if let ControlData::In(buf) = data {
+        if result > 0 {
+            let received = env
+                .convert_byte_array(jni::objects::JByteArray::from(arr))
+                .map_err(jerr)?;
+            let result = (result as usize).min(len);
+            buf[..result].copy_from_slice(&received[..result]);
+        }
+    }
+    Ok(result)
+}
+
+/// Gets `bmRequestType` of the control transfer.
+fn request_type(direction: Direction, control: &Control) -> jint {
+    let direction = match direction {
+        Direction::In => 0x80,
+        Direction::Out => 0x00,
+    };
+    let control_type = match control.control_type {
+        ControlType::Standard => 0x00,
+        ControlType::Class => 0x20,
+        ControlType::Vendor => 0x40,
+    };
+    let recipient = match control.recipient {
+        Recipient::Device => 0x00,
+        Recipient::Interface => 0x01,
+        Recipient::Endpoint => 0x02,
+        Recipient::Other => 0x03,
+    };
+    direction | control_type | recipient
+}
+
+/// Converts the timeout for the Java API, in which 0 means infinite.
+fn timeout_millis(timeout: Duration) -> jint {
+    timeout.as_millis().clamp(1, jint::MAX as u128) as jint
+}
@@ -0,0 +1,137 @@
+use crate::usb::{held_devices, invalidate_device_cache, DeviceInfo};
+use crate::Error;
+use std::{
+    any::Any,
+    collections::BTreeMap,
+    io::ErrorKind,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Condvar, Mutex, MutexGuard,
+    },
+    time::{Duration, Instant},
+};
+
+static PAUSED: Mutex<bool> = Mutex::new(false);
+static RESUMED: Condvar = Condvar::new();
+
+/// Call it when the Activity is paused (e.g. `MainEvent::Pause` of `android_activity`).
+/// Transfers of the synchronous wrappers (`SyncReader` and `SyncWriter`, used by
+/// `CdcSerial` of the default backend and other handlers) in progress are aborted,
+/// returning `Interrupted` error if no data has been transferred; new transfers wait
+/// for `on_resume()` within their timeouts, otherwise `TimedOut` error is returned.
+///
+/// Note: USB file descriptors may be invalidated and broadcasts may be missed while the
+/// app is in background, especially on screen rotation and app switching. Asynchronous
+/// queues and the Java backends are not suspended.
+pub fn on_pause() {
+    *lock_paused() = true;
+    #[cfg(feature = "blocking")]
+    crate::usb::abort_for_pause();
+}
+
+/// Call it when the Activity is resumed (e.g. `MainEvent::Resume` of `android_activity`).
+/// Suspended transfers are resumed, and the device list cache is invalidated because
+/// attach/detach broadcasts may be missed. Returns devices held by handlers in this
+/// process which are disconnected (checked by `DeviceInfo::check_connection()`), whose
+/// handlers should be dropped and reopened.
+pub fn on_resume() -> Vec<DeviceInfo> {
+    *lock_paused() = false;
+    RESUMED.notify_all();
+    invalidate_device_cache();
+    held_devices()
+        .into_iter()
+        .filter(|dev| !dev.check_connection())
+        .collect()
+}
+
+/// Returns true if `on_pause()` is called and `on_resume()` is not called after it.
+pub fn is_paused() -> bool {
+    *lock_paused()
+}
+
+/// Waits until the app is resumed, returns the remaining time of `timeout`.
+/// Returns `TimedOut` error if it is still paused.
+#[cfg_attr(not(feature = "blocking"), allow(dead_code))]
+pub(crate) fn wait_resumed(timeout: Duration) -> Result<Duration, Error> {
+    let mut paused = lock_paused();
+    if !*paused {
+        return Ok(timeout);
+    }
+    let t_start = Instant::now();
+    while *paused {
+        let remaining = timeout.saturating_sub(t_start.elapsed());
+        if remaining.is_zero() {
+            return Err(Error::new(
+                ErrorKind::TimedOut,
+                "Transfers are suspended while the app is paused",
+            ));
+        }
+        paused = RESUMED
+            .wait_timeout(paused, remaining)
+            .unwrap_or_else(|e| e.into_inner())
+            .0;
+    }
+    Ok(timeout.saturating_sub(t_start.elapsed()))
+}
+
+/// Handlers parked by `retain()`, keyed by token values.
+static RETAINED: Mutex<BTreeMap<u64, Box<dyn Any + Send>>> = Mutex::new(BTreeMap::new());
+static NEXT_TOKEN: AtomicU64 = AtomicU64::new(1);
+
+/// Token of a handler parked by `retain()`. It can be converted to an integer and
+/// stored in the saved instance state `Bundle` of the Activity.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RetainToken(u64);
+
+impl RetainToken {
+    /// Returns the integer value of the token.
+    pub fn into_raw(self) -> u64 {
+        self.0
+    }
+
+    /// Restores the token from the value returned by `into_raw()`.
+    pub fn from_raw(raw: u64) -> Self {
+        Self(raw)
+    }
+}
+
+/// Parks the handler (e.g. `CdcSerial`) with its opened file descriptor and claimed
+/// interfaces in this process, so it can be picked up by `recover()` in the Activity
+/// instance recreated on configuration changes (e.g. screen rotation).
+///
+/// Note: The native library stays loaded across Activity recreation, but the handler
+/// is lost if the process is killed. It is never dropped if `recover()` isn't called.
+pub fn retain<T: Any + Send>(handler: T) -> RetainToken {
+    let token = RetainToken(NEXT_TOKEN.fetch_add(1, Ordering::Relaxed));
+    lock_retained().insert(token.0, Box::new(handler));
+    token
+}
+
+/// Takes the handler parked by `retain()`. Returns `NotFound` error if the token
+/// is unknown or the handler is already taken, `InvalidInput` error if the handler is
+/// not of type `T` (then it is kept for another attempt).
+pub fn recover<T: Any + Send>(token: RetainToken) -> Result<T, Error> {
+    let mut retained = lock_retained();
+    let handler = retained.remove(&token.0).ok_or(Error::new(
+        ErrorKind::NotFound,
+        "No handler is retained with the token",
+    ))?;
+    match handler.downcast::<T>() {
+        Ok(handler) => Ok(*handler),
+        Err(handler) => {
+            retained.insert(token.0, handler);
+            Err(Error::new(
+                ErrorKind::InvalidInput,
+                "The retained handler is of another type",
+            ))
+        }
+    }
+}
+
+fn lock_retained() -> MutexGuard<'static, BTreeMap<u64, Box<dyn Any + Send>>> {
+    RETAINED.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn lock_paused() -> MutexGuard<'static, bool> {
+    PAUSED.lock().unwrap_or_else(|e| e.into_inner())
+}
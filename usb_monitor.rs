@@ -0,0 +1,239 @@
+use jni_min_helper::BroadcastReceiver;
+
+use crate::usb::{
+    invalidate_device_cache, list_devices_cached, register_receiver, unregister_receiver,
+    DeviceInfo, EventQueue, USB_EVENT_ACTIONS,
+};
+use crate::Error;
+#[cfg(feature = "async")]
+use std::{pin::Pin, task};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc, Mutex, MutexGuard,
+    },
+    time::Duration,
+};
+
+/// State of a device tracked by `UsbDeviceMonitor`.
+#[derive(Clone, Debug)]
+pub struct DeviceState {
+    pub device: DeviceInfo,
+    pub has_permission: bool,
+    /// The device is held by a handler in this process, check `DeviceInfo::is_in_use()`.
+    pub in_use: bool,
+}
+
+/// Event reported to subscribers of `UsbDeviceMonitor`.
+#[derive(Clone, Debug)]
+pub enum MonitorEvent {
+    Connected(DeviceState),
+    Disconnected(DeviceInfo),
+    /// The permission is granted or revoked.
+    PermissionChanged(DeviceState),
+}
+
+/// Long-lived registry of connected devices, owning enumeration, hotplug and permission
+/// tracking, so apps don't need to combine `list_devices()`, `HotplugWatch` and
+/// `has_permission()` by hand.
+///
+/// A background thread updates the snapshot on USB broadcasts, and checks permissions
+/// every `interval` (Android doesn't broadcast all permission changes). It is stopped
+/// when the monitor is dropped.
+pub struct UsbDeviceMonitor {
+    shared: Arc<MonitorShared>,
+    receiver: BroadcastReceiver,
+    stop: Arc<AtomicBool>,
+    poke: mpsc::Sender<()>,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+#[derive(Default)]
+struct MonitorShared {
+    devices: Mutex<Vec<(DeviceInfo, bool)>>, // connected devices with permission states
+    subscribers: Mutex<Vec<Arc<EventQueue<MonitorEvent>>>>,
+}
+
+impl UsbDeviceMonitor {
+    /// Enumerates the devices, then starts the tracking thread.
+    /// - `interval`: Period of checking permissions.
+    pub fn start(interval: Duration) -> Result<Self, Error> {
+        let shared = Arc::new(MonitorShared::default());
+        shared.update();
+
+        let (poke, poke_recv) = mpsc::channel();
+        let poke_broadcast = Mutex::new(poke.clone());
+        let receiver = register_receiver(USB_EVENT_ACTIONS, move |_, _| {
+            invalidate_device_cache();
+            let _ = lock(&poke_broadcast).send(());
+            Ok(())
+        })?;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let (shared_thread, stop_thread) = (shared.clone(), stop.clone());
+        let thread = std::thread::spawn(move || loop {
+            match poke_recv.recv_timeout(interval) {
+                Ok(()) | Err(mpsc::RecvTimeoutError::Timeout) => (),
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            }
+            if stop_thread.load(Ordering::Acquire) {
+                break;
+            }
+            shared_thread.update();
+        });
+        Ok(Self {
+            shared,
+            receiver,
+            stop,
+            poke,
+            thread: Some(thread),
+        })
+    }
+
+    /// Returns the current states of connected devices.
+    pub fn snapshot(&self) -> Vec<DeviceState> {
+        lock(&self.shared.devices)
+            .iter()
+            .map(|(dev, granted)| device_state(dev, *granted))
+            .collect()
+    }
+
+    /// Finds the current state of the device by `path_name`.
+    pub fn state_of(&self, dev_info: &DeviceInfo) -> Option<DeviceState> {
+        lock(&self.shared.devices)
+            .iter()
+            .find(|(dev, _)| dev.path_name() == dev_info.path_name())
+            .map(|(dev, granted)| device_state(dev, *granted))
+    }
+
+    /// Updates the snapshot now, e.g. after the permission is granted.
+    pub fn refresh(&self) {
+        let _ = self.poke.send(());
+    }
+
+    /// Adds a subscription of changes since now. Dropping it unsubscribes.
+    pub fn subscribe(&self) -> MonitorSubscription {
+        let queue = Arc::new(EventQueue::default());
+        lock(&self.shared.subscribers).push(queue.clone());
+        MonitorSubscription { queue }
+    }
+}
+
+impl Drop for UsbDeviceMonitor {
+    fn drop(&mut self) {
+        unregister_receiver(&self.receiver);
+        self.stop.store(true, Ordering::Release);
+        let _ = self.poke.send(());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl std::fmt::Debug for UsbDeviceMonitor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("UsbDeviceMonitor")
+            .field("devices", &lock(&self.shared.devices).len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl MonitorShared {
+    /// Enumerates the devices and reports the differences to subscribers.
+    fn update(&self) {
+        let Ok(current) = list_devices_cached() else {
+            return;
+        };
+        let mut events = Vec::new();
+        {
+            let mut devices = lock(&self.devices);
+            devices.retain(|(dev, _)| {
+                let connected = current.iter().any(|d| d.path_name() == dev.path_name());
+                if !connected {
+                    events.push(MonitorEvent::Disconnected(dev.clone()));
+                }
+                connected
+            });
+            for dev in current {
+                let granted = dev.has_permission().unwrap_or(false);
+                match devices
+                    .iter_mut()
+                    .find(|(d, _)| d.path_name() == dev.path_name())
+                {
+                    Some((_, state)) if *state != granted => {
+                        *state = granted;
+                        events.push(MonitorEvent::PermissionChanged(device_state(&dev, granted)));
+                    }
+                    Some(_) => (),
+                    None => {
+                        events.push(MonitorEvent::Connected(device_state(&dev, granted)));
+                        devices.push((dev, granted));
+                    }
+                }
+            }
+        }
+        let mut subscribers = lock(&self.subscribers);
+        subscribers.retain(|queue| Arc::strong_count(queue) > 1);
+        for queue in subscribers.iter() {
+            for event in events.iter() {
+                queue.push(event.clone());
+            }
+        }
+    }
+}
+
+fn device_state(dev: &DeviceInfo, granted: bool) -> DeviceState {
+    DeviceState {
+        device: dev.clone(),
+        has_permission: granted,
+        in_use: dev.is_in_use(),
+    }
+}
+
+/// Subscription of `UsbDeviceMonitor` events.
+pub struct MonitorSubscription {
+    queue: Arc<EventQueue<MonitorEvent>>,
+}
+
+impl MonitorSubscription {
+    /// Returns the amount of events available for checking.
+    pub fn count_available(&self) -> usize {
+        self.queue.count()
+    }
+
+    /// Takes the next event if available.
+    pub fn take_next(&mut self) -> Option<MonitorEvent> {
+        self.queue.pop()
+    }
+
+    /// Waits for an event; returns directly if an event is available.
+    pub fn wait_blocking(&mut self, timeout: Duration) -> Option<MonitorEvent> {
+        self.queue.wait(timeout)
+    }
+}
+
+#[cfg(feature = "async")]
+impl futures_core::Stream for MonitorSubscription {
+    type Item = MonitorEvent;
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+    ) -> task::Poll<Option<Self::Item>> {
+        // it doesn't end even if the monitor is dropped
+        self.queue.poll_pop(cx).map(Some)
+    }
+}
+
+impl std::fmt::Debug for MonitorSubscription {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MonitorSubscription")
+            .field("available", &self.count_available())
+            .finish()
+    }
+}
+
+#[inline(always)]
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
@@ -1,76 +1,289 @@
 // Related issue: <https://github.com/kevinmehall/nusb/issues/4>.
 
+use crate::usb::{sync_transfer_error, Attempts, RetryPolicy};
 use crate::Error;
-use jni_min_helper::block_for_timeout;
 
-use futures_lite::future::block_on;
-use std::{io::ErrorKind, time::Duration};
+use std::{
+    future::Future,
+    io::ErrorKind,
+    mem::MaybeUninit,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+    task::{Context, Poll, Wake, Waker},
+    thread::{self, Thread},
+    time::{Duration, Instant},
+};
 
 use nusb::transfer::{Queue, RequestBuffer, TransferError};
 type ReadQueue = Queue<RequestBuffer>;
 type WriteQueue = Queue<Vec<u8>>;
 
+/// Maximum time of waiting for a transfer to be cancelled after timeout.
+const CANCEL_TIMEOUT: Duration = Duration::from_secs(1);
+
 /// Synchronous wrapper of a `nusb` IN transfer queue.
+///
+/// It is `Send` and `Sync`, so the reader and the writer taken from a serial handler
+/// can be moved to different threads.
 pub struct SyncReader {
-    queue: ReadQueue,
-    buf: Option<Vec<u8>>,
+    queue: Exclusive<ReadQueue>,
+    buf: Vec<u8>,      // reused for each transfer
+    staged: Vec<u8>,   // data received by `wait_readable()` and not consumed yet
+    staged_pos: usize, // amount of consumed bytes in `staged`
+    halt: HaltState,
+    retry: RetryPolicy,
+    cancel: CancelHandle,
 }
+
 impl SyncReader {
     /// Wraps the asynchronous queue.
     pub fn new(queue: ReadQueue) -> Self {
         Self {
-            queue,
-            buf: Some(Vec::new()),
+            queue: Exclusive(queue),
+            buf: Vec::new(),
+            staged: Vec::new(),
+            staged_pos: 0,
+            halt: HaltState::default(),
+            retry: RetryPolicy::default(),
+            cancel: CancelHandle::default(),
+        }
+    }
+    /// Waits until some data is received without consuming it, returns the amount of bytes
+    /// available for the next reads. Returns `TimedOut` error if nothing is received.
+    ///
+    /// Note: A transfer of `max_len` bytes is done, the received data is staged here and
+    /// returned by the following reads before doing new transfers.
+    pub fn wait_readable(&mut self, max_len: usize, timeout: Duration) -> std::io::Result<usize> {
+        if self.staged_pos < self.staged.len() {
+            return Ok(self.staged.len() - self.staged_pos);
         }
+        let t_start = Instant::now();
+        let mut staged = std::mem::take(&mut self.staged);
+        staged.clear();
+        self.staged_pos = 0;
+        let result = loop {
+            let remaining = timeout.saturating_sub(t_start.elapsed());
+            match self.read_with(max_len, remaining, |data| staged.extend_from_slice(data)) {
+                Ok(0) if !remaining.is_zero() => continue, // zero-length packet
+                Ok(0) => break Err(Error::from(ErrorKind::TimedOut)),
+                Ok(_) => break Ok(()),
+                Err(e) => break Err(e),
+            }
+        };
+        // remainder of a previously submitted transfer larger than `max_len`
+        staged.extend_from_slice(&self.staged[self.staged_pos..]);
+        self.staged = staged;
+        self.staged_pos = 0;
+        result.map(|_| self.staged.len())
+    }
+
+    /// Clears the halt (stall) condition of the endpoint, for recovering from device-side
+    /// protocol errors deliberately. Data staged by `wait_readable()` is dropped.
+    pub fn clear_halt(&mut self) -> std::io::Result<()> {
+        self.staged.clear();
+        self.staged_pos = 0;
+        self.queue.get_mut().clear_halt()
+    }
+
+    /// Returns the amount of stalled transfers since the reader is created.
+    pub fn stall_count(&self) -> usize {
+        self.halt.count
+    }
+
+    /// Sets whether the halt condition is cleared automatically when a transfer stalls,
+    /// true by default. If disabled, transfers keep failing with `BrokenPipe` errors until
+    /// `clear_halt()` is called.
+    pub fn set_auto_clear_halt(&mut self, auto: bool) {
+        self.halt.manual = !auto;
+    }
+
+    /// Sets the retry policy for transient transfer failures, no retry by default.
+    pub fn set_retry_policy(&mut self, retry: RetryPolicy) {
+        self.retry = retry;
     }
+
+    /// Returns the handle for aborting reads in progress from another thread.
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.cancel.clone()
+    }
+
+    /// Replaces the cancellation handle, e.g. to share one handle with a `SyncWriter`.
+    pub fn set_cancel_handle(&mut self, cancel: CancelHandle) {
+        self.cancel = cancel;
+    }
+
+    /// Returns the amount of bytes staged by `wait_readable()` and not consumed yet.
+    pub fn staged_len(&self) -> usize {
+        self.staged.len() - self.staged_pos
+    }
+
     /// It is similar to `read()` in the standard `Read` trait, requiring timeout parameter.
+    /// If the transfer fails after receiving some data, the data is returned without error.
+    ///
+    /// Note: `Duration::ZERO` means polling: it checks for an already-completed transfer
+    /// and returns immediately, `TimedOut` error if nothing is received. The transfer is
+    /// submitted if none is pending, and it is kept pending (not cancelled) for the next
+    /// read, so callers can schedule polling by themselves without extra threads.
     pub fn read(&mut self, buf: &mut [u8], timeout: Duration) -> std::io::Result<usize> {
-        if buf.is_empty() {
-            return Ok(0);
+        self.read_with(buf.len(), timeout, |data| {
+            buf[..data.len()].copy_from_slice(data)
+        })
+    }
+
+    /// Reads up to `buf.capacity()` bytes, replacing the content of `buf`, and returns the
+    /// received length. The completed transfer buffer is swapped into `buf`, and the previous
+    /// allocation of `buf` is reused for the next transfer, so large high-rate transfers
+    /// are not copied. Reserve the capacity before the first call.
+    ///
+    /// Note: Data staged by `wait_readable()` or received by a transfer left pending by
+    /// a previous read is copied. If a larger transfer has been pending, the exceeding data
+    /// is staged for the following reads. If the transfer is still pending on return (e.g.
+    /// a polling read returns `TimedOut`), `buf` is replaced by another allocation with the
+    /// same capacity, and the data is returned by the next read.
+    pub fn read_buf(&mut self, buf: &mut Vec<u8>, timeout: Duration) -> std::io::Result<usize> {
+        let len = buf.capacity();
+        if self.staged_pos < self.staged.len() || self.queue.get_mut().pending() > 0 {
+            // the pending transfer doesn't hold the allocation of `buf`
+            buf.clear();
+            return self.read_with(len, timeout, |data| buf.extend_from_slice(data));
         }
-        let buf_async = self.buf.take().unwrap();
-        // Safety: `RequestBuffer::reuse()` may reserve larger capacity to reach buf.len()
-        let req = nusb::transfer::RequestBuffer::reuse(buf_async, buf.len());
-
-        self.queue.submit(req);
-        let fut = self.queue.next_complete();
-        let comp = {
-            let mut maybe_comp = block_for_timeout(fut, timeout);
-            if maybe_comp.is_none() {
-                self.queue.cancel_all(); // the only one
-                if self.queue.pending() == 0 {
-                    self.buf.replace(Vec::new());
-                    return Err(Error::other("Unable to get the transfer result"));
+        // `self.buf` is submitted by `read_once()` and replaced by the completed buffer
+        std::mem::swap(buf, &mut self.buf);
+        let result = self.read_with(len, timeout, |_| ());
+        let pending = self.queue.get_mut().pending() > 0;
+        give_back_read_buf(buf, &mut self.buf, pending, len, &result);
+        result
+    }
+
+    /// Reads into a buffer which may be uninitialized, returns the filled part. This avoids
+    /// zero-filling large buffers before reading (`Read::read_buf()` is still unstable).
+    pub fn read_uninit<'b>(
+        &mut self,
+        buf: &'b mut [MaybeUninit<u8>],
+        timeout: Duration,
+    ) -> std::io::Result<&'b mut [u8]> {
+        let ptr = buf.as_mut_ptr() as *mut u8;
+        let len = self.read_with(buf.len(), timeout, |data| {
+            // Safety: `data.len()` never exceeds the requested length `buf.len()`
+            unsafe { std::ptr::copy_nonoverlapping(data.as_ptr(), ptr, data.len()) }
+        })?;
+        // Safety: the first `len` bytes are initialized above
+        Ok(unsafe { std::slice::from_raw_parts_mut(ptr, len) })
+    }
+
+    /// Keeps reading and appending to `buf` until the deadline is reached or the device
+    /// is disconnected, returns the amount of bytes appended. Timeouts of single transfers
+    /// are not treated as errors, unlike the standard `read_to_end()`.
+    pub fn read_to_end_deadline(
+        &mut self,
+        buf: &mut Vec<u8>,
+        deadline: Instant,
+    ) -> std::io::Result<usize> {
+        const CHUNK_SIZE: usize = 16 * 1024;
+        let len_start = buf.len();
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break;
+            }
+            buf.reserve(CHUNK_SIZE);
+            let spare = buf.spare_capacity_mut();
+            let len_spare = spare.len().min(CHUNK_SIZE);
+            match self.read_uninit(&mut spare[..len_spare], remaining) {
+                Ok(data) => {
+                    let len = data.len();
+                    // Safety: these bytes are initialized by `read_uninit()`
+                    unsafe { buf.set_len(buf.len() + len) };
                 }
-                let comp = block_on(self.queue.next_complete());
-                maybe_comp.replace(comp);
+                Err(e) if e.kind() == ErrorKind::TimedOut => (),
+                Err(e) if e.kind() == ErrorKind::NotConnected => break,
+                Err(e) => return Err(e),
             }
-            maybe_comp.unwrap()
-        };
-        let len_reveived = comp.data.len();
+        }
+        Ok(buf.len() - len_start)
+    }
 
-        let result = match comp.status {
-            Ok(()) => {
-                buf[..len_reveived].copy_from_slice(&comp.data);
-                Ok(len_reveived)
+    /// Does an IN transfer of `len` bytes at most, the received data is passed to `copy`.
+    /// Transient failures are retried according to the retry policy.
+    fn read_with(
+        &mut self,
+        len: usize,
+        timeout: Duration,
+        mut copy: impl FnMut(&[u8]),
+    ) -> std::io::Result<usize> {
+        if len == 0 {
+            return Ok(0);
+        }
+        if self.staged_pos < self.staged.len() {
+            let staged = &self.staged[self.staged_pos..];
+            let len = staged.len().min(len);
+            copy(&staged[..len]);
+            self.staged_pos += len;
+            return Ok(len);
+        }
+        let mut attempts = Attempts::default();
+        loop {
+            let err = match self.read_once(len, timeout, &mut copy)? {
+                Ok(len) => return Ok(len),
+                Err(e) => e,
+            };
+            match self.retry.next_retry(err, &mut attempts, !self.halt.manual) {
+                Some(delay) => thread::sleep(delay),
+                None => return Err(sync_transfer_error(err)),
             }
-            Err(TransferError::Cancelled) => {
-                if len_reveived > 0 {
-                    buf[..len_reveived].copy_from_slice(&comp.data);
-                    Ok(len_reveived)
-                } else {
-                    Err(Error::from(ErrorKind::TimedOut))
+        }
+    }
+
+    /// Does a single IN transfer. The inner error is returned only if no data is received.
+    ///
+    /// Note: If the queue still has a pending transfer (submitted before it is wrapped,
+    /// or not finished in time after cancellation), it is waited instead of submitting
+    /// a new one; the data exceeding `len` is staged for the following reads.
+    fn read_once(
+        &mut self,
+        len: usize,
+        timeout: Duration,
+        copy: &mut impl FnMut(&[u8]),
+    ) -> std::io::Result<Result<usize, TransferError>> {
+        let cancel = self.cancel.register();
+        let _pause = pause_handle().register_flag(cancel.flag.clone());
+        let timeout = crate::usb::wait_resumed(timeout)?;
+        if self.queue.get_mut().pending() == 0 {
+            let buf_async = std::mem::take(&mut self.buf);
+            // Safety: `RequestBuffer::reuse()` may reserve larger capacity to reach len
+            let req = RequestBuffer::reuse(buf_async, len);
+            self.queue.get_mut().submit(req);
+        }
+        let (comp, cancelled) = wait_completion(self.queue.get_mut(), timeout, &cancel)?;
+        let len_reveived = comp.data.len().min(len);
+
+        let halt = match comp.status {
+            Err(TransferError::Stall) => self.halt.on_stall(self.queue.get_mut()),
+            _ => Ok(()),
+        };
+        let result = match comp.status {
+            _ if len_reveived > 0 => {
+                copy(&comp.data[..len_reveived]);
+                if comp.data.len() > len_reveived {
+                    self.staged.drain(..self.staged_pos);
+                    self.staged.extend_from_slice(&comp.data[len_reveived..]);
+                    self.staged_pos = 0;
                 }
+                Ok(len_reveived)
             }
-            Err(TransferError::Disconnected) => Err(Error::from(ErrorKind::NotConnected)),
-            Err(TransferError::Stall) => {
-                let _ = self.queue.clear_halt();
-                Err(Error::other(TransferError::Stall))
-            }
-            Err(e) => Err(Error::other(e)),
+            Ok(()) => Ok(0), // zero-length packet
+            Err(e) => Err(e),
         };
-        self.buf.replace(comp.data);
-        result
+        self.buf = comp.data;
+        if cancelled && matches!(result, Err(TransferError::Cancelled)) {
+            return Err(Error::from(ErrorKind::Interrupted));
+        }
+        match (halt, result) {
+            (Err(e), Err(_)) => Err(e), // the halt is not cleared
+            (_, result) => Ok(result),
+        }
     }
 }
 
@@ -81,70 +294,199 @@ impl From<ReadQueue> for SyncReader {
 }
 
 impl From<SyncReader> for ReadQueue {
+    /// Note: Data staged by `SyncReader::wait_readable()` is dropped.
     fn from(value: SyncReader) -> Self {
-        value.queue
+        value.queue.into_inner()
     }
 }
 
 /// Synchronous wrapper of a `nusb` OUT transfer queue.
+///
+/// It is `Send` and `Sync`, check `SyncReader`.
 pub struct SyncWriter {
-    queue: WriteQueue,
-    buf: Option<Vec<u8>>,
+    queue: Option<Exclusive<WriteQueue>>, // `None` only after it is taken by `From<SyncWriter>`
+    buf: Vec<u8>,                         // reused for each transfer
+    drain_on_drop: Option<Duration>,
+    halt: HaltState,
+    retry: RetryPolicy,
+    cancel: CancelHandle,
 }
 
 impl SyncWriter {
     /// Wraps the asynchronous queue.
     pub fn new(queue: WriteQueue) -> Self {
         Self {
-            queue,
-            buf: Some(Vec::new()),
+            queue: Some(Exclusive(queue)),
+            buf: Vec::new(),
+            drain_on_drop: None,
+            halt: HaltState::default(),
+            retry: RetryPolicy::default(),
+            cancel: CancelHandle::default(),
         }
     }
+    /// Clears the halt (stall) condition of the endpoint. Check `SyncReader::clear_halt()`.
+    pub fn clear_halt(&mut self) -> std::io::Result<()> {
+        queue_mut(&mut self.queue).clear_halt()
+    }
+
+    /// Returns the amount of stalled transfers since the writer is created.
+    pub fn stall_count(&self) -> usize {
+        self.halt.count
+    }
+
+    /// Sets whether the halt condition is cleared automatically when a transfer stalls,
+    /// true by default. Check `SyncReader::set_auto_clear_halt()`.
+    pub fn set_auto_clear_halt(&mut self, auto: bool) {
+        self.halt.manual = !auto;
+    }
+
+    /// Sets the retry policy for transient transfer failures, no retry by default.
+    pub fn set_retry_policy(&mut self, retry: RetryPolicy) {
+        self.retry = retry;
+    }
+
+    /// Returns the handle for aborting writes in progress from another thread.
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.cancel.clone()
+    }
+
+    /// Replaces the cancellation handle, e.g. to share one handle with a `SyncReader`.
+    pub fn set_cancel_handle(&mut self, cancel: CancelHandle) {
+        self.cancel = cancel;
+    }
+
+    /// Sets the maximum time of `drain()` when the writer is dropped, `None` by default.
+    /// It doesn't apply when the queue is taken by `From<SyncWriter>`.
+    ///
+    /// Note: Data of the latest polling write (zero timeout) may still be pending, so it
+    /// may be cancelled on drop without this setting.
+    pub fn set_drain_on_drop(&mut self, timeout: Option<Duration>) {
+        self.drain_on_drop = timeout;
+    }
     /// It is similar to `write()` in the standard `Write` trait, requiring timeout parameter.
-    /// It is always synchronous, and `flush()` is not needed.
+    /// It is always synchronous, and `flush()` is not needed. If the transfer fails after
+    /// sending some data, the sent length is returned without error.
+    ///
+    /// `timeout` covers both waiting for a transfer left pending and the new transfer.
+    ///
+    /// Note: `Duration::ZERO` means polling: if the previous transfer is still pending,
+    /// `TimedOut` error is returned immediately and nothing is submitted; otherwise the data
+    /// is submitted and its length is returned without waiting. An error of the polled
+    /// transfer is reported by the next `write()` or `drain()` (`flush()` of handlers
+    /// implementing `Write`), not by the polling write itself.
     pub fn write(&mut self, buf: &[u8], timeout: Duration) -> std::io::Result<usize> {
         if buf.is_empty() {
             return Ok(0);
         }
-        let mut buf_async = self.buf.take().unwrap();
+        let mut attempts = Attempts::default();
+        loop {
+            let err = match self.write_once(buf, timeout)? {
+                Ok(len) => return Ok(len),
+                Err(e) => e,
+            };
+            match self.retry.next_retry(err, &mut attempts, !self.halt.manual) {
+                Some(delay) => thread::sleep(delay),
+                None => return Err(sync_transfer_error(err)),
+            }
+        }
+    }
+
+    /// Does a single OUT transfer. The inner error is returned only if nothing is sent.
+    ///
+    /// Note: Pending transfers left in the queue (submitted before it is wrapped, or not
+    /// finished in time after cancellation) are waited before submitting a new one.
+    fn write_once(
+        &mut self,
+        buf: &[u8],
+        timeout: Duration,
+    ) -> std::io::Result<Result<usize, TransferError>> {
+        let t_start = Instant::now();
+        let polling = timeout.is_zero();
+        if queue_mut(&mut self.queue).pending() > 0 {
+            self.drain(timeout)?;
+        }
+        // the transfer shares the deadline with `drain()`
+        let timeout = timeout.saturating_sub(t_start.elapsed());
+        if !polling && timeout.is_zero() {
+            return Err(Error::from(ErrorKind::TimedOut));
+        }
+        let cancel = self.cancel.register();
+        let _pause = pause_handle().register_flag(cancel.flag.clone());
+        let timeout = crate::usb::wait_resumed(timeout)?;
+
+        let mut buf_async = std::mem::take(&mut self.buf);
         buf_async.clear(); // it has no effect on the allocated capacity
         buf_async.extend_from_slice(buf);
-
-        self.queue.submit(buf_async);
-        let fut = self.queue.next_complete();
-        let comp = {
-            let mut maybe_comp = block_for_timeout(fut, timeout);
-            if maybe_comp.is_none() {
-                self.queue.cancel_all(); // the only one
-                if self.queue.pending() == 0 {
-                    self.buf.replace(Vec::new());
-                    return Err(Error::other("Unable to get the transfer result"));
-                }
-                let comp = block_on(self.queue.next_complete());
-                maybe_comp.replace(comp);
-            }
-            maybe_comp.unwrap()
-        };
+        queue_mut(&mut self.queue).submit(buf_async);
+        if polling {
+            return Ok(Ok(buf.len())); // checked by the next write or `drain()`
+        }
+        let (comp, cancelled) = wait_completion(queue_mut(&mut self.queue), timeout, &cancel)?;
         let len_sent = comp.data.actual_length();
 
+        let halt = match comp.status {
+            Err(TransferError::Stall) => self.halt.on_stall(queue_mut(&mut self.queue)),
+            _ => Ok(()),
+        };
         let result = match comp.status {
             Ok(()) => Ok(len_sent),
-            Err(TransferError::Cancelled) => {
-                if len_sent > 0 {
-                    Ok(len_sent)
-                } else {
-                    Err(Error::from(ErrorKind::TimedOut))
-                }
+            _ if len_sent > 0 => Ok(len_sent),
+            Err(e) => Err(e),
+        };
+        self.buf = comp.data.reuse();
+        if cancelled && matches!(result, Err(TransferError::Cancelled)) {
+            return Err(Error::from(ErrorKind::Interrupted));
+        }
+        match (halt, result) {
+            (Err(e), Err(_)) => Err(e), // the halt is not cleared
+            (_, result) => Ok(result),
+        }
+    }
+
+    /// Writes the whole buffer in chunks of 16 KiB, calling `progress` with the amount
+    /// of bytes sent after each chunk. `timeout` is applied for each chunk.
+    pub fn write_all_with_progress(
+        &mut self,
+        buf: &[u8],
+        timeout: Duration,
+        mut progress: impl FnMut(usize),
+    ) -> std::io::Result<()> {
+        const CHUNK_SIZE: usize = 16 * 1024;
+        let mut len_sent = 0;
+        while len_sent < buf.len() {
+            let len_chunk = (buf.len() - len_sent).min(CHUNK_SIZE);
+            match self.write(&buf[len_sent..len_sent + len_chunk], timeout) {
+                Ok(0) => return Err(Error::from(ErrorKind::WriteZero)),
+                Ok(len) => len_sent += len,
+                Err(e) => return Err(e),
             }
-            Err(TransferError::Disconnected) => Err(Error::from(ErrorKind::NotConnected)),
-            Err(TransferError::Stall) => {
-                let _ = self.queue.clear_halt();
-                Err(Error::other(TransferError::Stall))
+            progress(len_sent);
+        }
+        Ok(())
+    }
+
+    /// Waits until all submitted OUT transfers have completed, which means the device
+    /// has received all data sent by the host. `write()` with a nonzero timeout waits
+    /// for its transfer, so this returns immediately unless the latest write is a polling
+    /// write (zero timeout) or the queue has other pending transfers (e.g. submitted before
+    /// it is wrapped here). A failure of the pending transfer is returned here.
+    pub fn drain(&mut self, timeout: Duration) -> std::io::Result<()> {
+        let t_start = Instant::now();
+        while queue_mut(&mut self.queue).pending() > 0 {
+            let remaining = timeout.saturating_sub(t_start.elapsed());
+            let comp = block_for_timeout(queue_mut(&mut self.queue).next_complete(), remaining)
+                .ok_or(Error::from(ErrorKind::TimedOut))?;
+            let status = comp.status;
+            self.buf = comp.data.reuse();
+            if let Err(TransferError::Stall) = status {
+                self.halt.on_stall(queue_mut(&mut self.queue))?;
             }
-            Err(e) => Err(Error::other(e)),
-        };
-        self.buf.replace(comp.data.reuse());
-        result
+            match status {
+                Ok(()) | Err(TransferError::Cancelled) => (),
+                Err(e) => return Err(crate::usb::map_transfer_error(e)),
+            }
+        }
+        Ok(())
     }
 }
 
@@ -155,7 +497,423 @@ impl From<WriteQueue> for SyncWriter {
 }
 
 impl From<SyncWriter> for WriteQueue {
-    fn from(value: SyncWriter) -> Self {
-        value.queue
+    fn from(mut value: SyncWriter) -> Self {
+        value.queue.take().unwrap().into_inner()
+    }
+}
+
+impl Drop for SyncWriter {
+    fn drop(&mut self) {
+        if self.queue.is_none() {
+            return; // taken by `From<SyncWriter>`
+        }
+        if let Some(timeout) = self.drain_on_drop {
+            let _ = self.drain(timeout);
+        }
+    }
+}
+
+/// Returns the queue of `SyncWriter`, which is `None` only after `From<SyncWriter>`.
+#[inline(always)]
+fn queue_mut(queue: &mut Option<Exclusive<WriteQueue>>) -> &mut WriteQueue {
+    queue.as_mut().expect("the queue is taken").get_mut()
+}
+
+/// Synchronous wrapper of a `nusb` interrupt IN transfer queue, e.g. for the CDC notification
+/// endpoint. Unlike `SyncReader`, each read returns the data of a single transfer (a report
+/// or a notification, ended by a short packet), and zero-length packets are returned as
+/// `Ok(0)`. Timeouts, cancellation, halt clearing and retries are the same as `SyncReader`.
+pub struct SyncInterruptReader {
+    reader: SyncReader,
+    max_len: usize, // requested length of each transfer
+}
+
+impl SyncInterruptReader {
+    /// Wraps the asynchronous queue. `max_len` is the requested length of each transfer,
+    /// which should be a multiple of the maximum packet size of the endpoint.
+    pub fn new(queue: ReadQueue, max_len: usize) -> Self {
+        Self {
+            reader: SyncReader::new(queue),
+            max_len: max_len.max(1),
+        }
+    }
+
+    /// Returns the requested length of each transfer.
+    pub fn max_len(&self) -> usize {
+        self.max_len
+    }
+
+    /// Waits for the next packet and copies it into `buf`, returns the length of the packet.
+    /// If `buf` is shorter than the packet, the rest of the packet is dropped.
+    pub fn read(&mut self, buf: &mut [u8], timeout: Duration) -> std::io::Result<usize> {
+        let len = self.reader.read_with(self.max_len, timeout, |data| {
+            let len = data.len().min(buf.len());
+            buf[..len].copy_from_slice(&data[..len])
+        })?;
+        Ok(len.min(buf.len()))
+    }
+
+    /// Waits for the next packet and returns it.
+    pub fn read_packet(&mut self, timeout: Duration) -> std::io::Result<Vec<u8>> {
+        let mut packet = Vec::new();
+        self.reader
+            .read_with(self.max_len, timeout, |data| packet.extend_from_slice(data))?;
+        Ok(packet)
+    }
+
+    /// Clears the halt (stall) condition of the endpoint. Check `SyncReader::clear_halt()`.
+    pub fn clear_halt(&mut self) -> std::io::Result<()> {
+        self.reader.clear_halt()
+    }
+
+    /// Returns the amount of stalled transfers since the reader is created.
+    pub fn stall_count(&self) -> usize {
+        self.reader.stall_count()
+    }
+
+    /// Check `SyncReader::set_auto_clear_halt()`.
+    pub fn set_auto_clear_halt(&mut self, auto: bool) {
+        self.reader.set_auto_clear_halt(auto);
+    }
+
+    /// Sets the retry policy for transient transfer failures, no retry by default.
+    pub fn set_retry_policy(&mut self, retry: RetryPolicy) {
+        self.reader.set_retry_policy(retry);
+    }
+
+    /// Returns the handle for aborting reads in progress from another thread.
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.reader.cancel_handle()
+    }
+
+    /// Replaces the cancellation handle, e.g. to share one handle with other wrappers.
+    pub fn set_cancel_handle(&mut self, cancel: CancelHandle) {
+        self.reader.set_cancel_handle(cancel);
+    }
+}
+
+impl From<SyncInterruptReader> for ReadQueue {
+    fn from(value: SyncInterruptReader) -> Self {
+        value.reader.into()
+    }
+}
+
+/// Synchronous wrapper of a `nusb` interrupt OUT transfer queue.
+/// Each write is a single transfer, which should not exceed the maximum packet size of
+/// the endpoint for devices expecting one report per transfer. Timeouts, cancellation,
+/// halt clearing and retries are the same as `SyncWriter`.
+pub struct SyncInterruptWriter {
+    writer: SyncWriter,
+}
+
+impl SyncInterruptWriter {
+    /// Wraps the asynchronous queue.
+    pub fn new(queue: WriteQueue) -> Self {
+        Self {
+            writer: SyncWriter::new(queue),
+        }
+    }
+
+    /// Sends the packet (report), returns the sent length. Check `SyncWriter::write()`.
+    pub fn write(&mut self, buf: &[u8], timeout: Duration) -> std::io::Result<usize> {
+        self.writer.write(buf, timeout)
+    }
+
+    /// Waits until all submitted transfers have completed. Check `SyncWriter::drain()`.
+    pub fn drain(&mut self, timeout: Duration) -> std::io::Result<()> {
+        self.writer.drain(timeout)
+    }
+
+    /// Clears the halt (stall) condition of the endpoint. Check `SyncReader::clear_halt()`.
+    pub fn clear_halt(&mut self) -> std::io::Result<()> {
+        self.writer.clear_halt()
+    }
+
+    /// Returns the amount of stalled transfers since the writer is created.
+    pub fn stall_count(&self) -> usize {
+        self.writer.stall_count()
+    }
+
+    /// Check `SyncReader::set_auto_clear_halt()`.
+    pub fn set_auto_clear_halt(&mut self, auto: bool) {
+        self.writer.set_auto_clear_halt(auto);
+    }
+
+    /// Sets the retry policy for transient transfer failures, no retry by default.
+    pub fn set_retry_policy(&mut self, retry: RetryPolicy) {
+        self.writer.set_retry_policy(retry);
+    }
+
+    /// Returns the handle for aborting writes in progress from another thread.
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.writer.cancel_handle()
+    }
+
+    /// Replaces the cancellation handle, e.g. to share one handle with other wrappers.
+    pub fn set_cancel_handle(&mut self, cancel: CancelHandle) {
+        self.writer.set_cancel_handle(cancel);
+    }
+}
+
+impl From<SyncInterruptWriter> for WriteQueue {
+    fn from(value: SyncInterruptWriter) -> Self {
+        value.writer.into()
+    }
+}
+
+/// Cloneable handle for aborting synchronous transfers in progress from another thread
+/// (e.g. when the user hits "disconnect" while a read with a long timeout is waiting).
+/// The aborted operation returns `Interrupted` error if no data has been transferred.
+///
+/// Note: Only operations in progress are aborted, later operations are not affected.
+/// `Read::read_exact()` and `Write::write_all()` retry on `Interrupted` errors.
+#[derive(Clone, Debug, Default)]
+pub struct CancelHandle {
+    waiters: Arc<Mutex<Vec<(Thread, Arc<AtomicBool>)>>>,
+}
+
+impl CancelHandle {
+    /// Aborts the operations in progress.
+    pub fn cancel(&self) {
+        for (thread, flag) in lock_waiters(&self.waiters).iter() {
+            flag.store(true, Ordering::Release);
+            thread.unpark();
+        }
+    }
+
+    fn register(&self) -> CancelGuard<'_> {
+        self.register_flag(Arc::new(AtomicBool::new(false)))
+    }
+
+    /// Registers an operation with the flag which may be shared with another handle.
+    fn register_flag(&self, flag: Arc<AtomicBool>) -> CancelGuard<'_> {
+        lock_waiters(&self.waiters).push((thread::current(), flag.clone()));
+        CancelGuard { handle: self, flag }
+    }
+}
+
+/// Handle shared by all synchronous wrappers, aborting their operations in progress
+/// on `usb::on_pause()`.
+fn pause_handle() -> &'static CancelHandle {
+    static PAUSE: OnceLock<CancelHandle> = OnceLock::new();
+    PAUSE.get_or_init(CancelHandle::default)
+}
+
+/// Aborts synchronous transfers in progress, called by `usb::on_pause()`.
+pub(crate) fn abort_for_pause() {
+    pause_handle().cancel();
+}
+
+/// Registration of an operation in progress, removed on drop.
+struct CancelGuard<'a> {
+    handle: &'a CancelHandle,
+    flag: Arc<AtomicBool>,
+}
+
+impl Drop for CancelGuard<'_> {
+    fn drop(&mut self) {
+        lock_waiters(&self.handle.waiters).retain(|(_, flag)| !Arc::ptr_eq(flag, &self.flag));
+    }
+}
+
+fn lock_waiters<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Waits for the next completion, the transfer is cancelled on timeout or by the handle.
+/// Returns the completion and whether it is cancelled by the handle. For zero `timeout`,
+/// the completion is checked once, and the transfer is kept pending if it isn't finished.
+fn wait_completion<R>(
+    queue: &mut Queue<R>,
+    timeout: Duration,
+    cancel: &CancelGuard<'_>,
+) -> std::io::Result<(nusb::transfer::Completion<R::Response>, bool)>
+where
+    R: nusb::transfer::TransferRequest,
+{
+    let fut = queue.next_complete();
+    if timeout.is_zero() {
+        return match block_until(fut, timeout, Some(&cancel.flag)) {
+            Blocked::Ready(comp) => Ok((comp, false)),
+            Blocked::TimedOut => Err(Error::from(ErrorKind::TimedOut)),
+            Blocked::Cancelled => Err(Error::from(ErrorKind::Interrupted)),
+        };
+    }
+    let cancelled = match block_until(fut, timeout, Some(&cancel.flag)) {
+        Blocked::Ready(comp) => return Ok((comp, false)),
+        Blocked::TimedOut => false,
+        Blocked::Cancelled => true,
+    };
+    queue.cancel_all();
+    // the cancelled transfer is waited by the next call if it is not finished
+    let kind = if cancelled {
+        ErrorKind::Interrupted
+    } else {
+        ErrorKind::TimedOut
+    };
+    let comp = block_for_timeout(queue.next_complete(), CANCEL_TIMEOUT).ok_or(Error::from(kind))?;
+    Ok((comp, cancelled))
+}
+
+/// Gives the completed transfer buffer of `SyncReader::read_buf()` back to the caller, and
+/// keeps the previous buffer of the caller for the next transfer. If the transfer is still
+/// pending (polling or cancellation timed out), it holds the allocation submitted from `buf`
+/// until it is reaped by the next read; then `buf` gets the spare buffer with the requested
+/// capacity instead.
+fn give_back_read_buf(
+    buf: &mut Vec<u8>,
+    spare: &mut Vec<u8>,
+    pending: bool,
+    len: usize,
+    result: &std::io::Result<usize>,
+) {
+    if pending {
+        buf.clear();
+        buf.reserve(len);
+        return;
+    }
+    std::mem::swap(buf, spare);
+    buf.truncate(*result.as_ref().unwrap_or(&0));
+}
+
+/// Stall statistics and the halt clearing mode of an endpoint.
+#[derive(Clone, Copy, Debug, Default)]
+struct HaltState {
+    count: usize,
+    manual: bool, // false: clear the halt automatically on stall
+}
+
+impl HaltState {
+    /// Clears the halt in the automatic mode. Returns `BrokenPipe` error if it fails,
+    /// because the endpoint keeps stalling then.
+    fn on_stall<T>(&mut self, queue: &mut Queue<T>) -> std::io::Result<()> {
+        self.count = self.count.wrapping_add(1);
+        if self.manual {
+            return Ok(());
+        }
+        queue.clear_halt().map_err(|e| {
+            Error::new(
+                ErrorKind::BrokenPipe,
+                format!("The endpoint stalled, and clearing the halt failed: {e}"),
+            )
+        })
+    }
+}
+
+/// Wrapper which is `Sync` for any `T: Send`, because `T` can only be reached through
+/// `&mut self` or by value; nothing can be done through a shared reference of it.
+/// `nusb` queues are not `Sync`, while the wrappers here should be.
+struct Exclusive<T>(T);
+
+// Safety: `&Exclusive<T>` gives no access to `T`, so sharing it between threads is sound.
+unsafe impl<T: Send> Sync for Exclusive<T> {}
+
+impl<T> Exclusive<T> {
+    fn get_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+
+    fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+// Compile-time check: `Exclusive<T>` is `Sync` even if `T` is not (`Cell` is `Send` only).
+const _: () = {
+    const fn assert_sync<T: Sync>() {}
+    assert_sync::<Exclusive<std::cell::Cell<u8>>>();
+};
+
+/// Wakes up the thread blocked in `block_for_timeout()`.
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Polls the future in current thread until it is ready or the timeout is reached.
+/// This avoids depending on an asynchronous runtime (or `futures-lite`).
+pub(crate) fn block_for_timeout<F: Future>(fut: F, timeout: Duration) -> Option<F::Output> {
+    match block_until(fut, timeout, None) {
+        Blocked::Ready(output) => Some(output),
+        _ => None,
+    }
+}
+
+/// Result of `block_until()`.
+enum Blocked<T> {
+    Ready(T),
+    TimedOut,
+    Cancelled,
+}
+
+/// Polls the future in current thread until it is ready, the timeout is reached or
+/// the cancellation flag is set (the setter should unpark this thread).
+fn block_until<F: Future>(
+    fut: F,
+    timeout: Duration,
+    cancel: Option<&AtomicBool>,
+) -> Blocked<F::Output> {
+    let mut fut = std::pin::pin!(fut);
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    let deadline = Instant::now().checked_add(timeout);
+    loop {
+        if let Poll::Ready(output) = fut.as_mut().poll(&mut cx) {
+            return Blocked::Ready(output);
+        }
+        if cancel.is_some_and(|flag| flag.load(Ordering::Acquire)) {
+            return Blocked::Cancelled;
+        }
+        if let Some(deadline) = deadline {
+            let now = Instant::now();
+            if now >= deadline {
+                return Blocked::TimedOut;
+            }
+            thread::park_timeout(deadline - now);
+        } else {
+            thread::park();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_buf_after_timed_out_read() {
+        let len = 64;
+        let mut buf = Vec::with_capacity(len);
+        let mut spare = Vec::new(); // `SyncReader::buf` before the first transfer
+
+        // the polling read times out, the pending transfer holds the allocation of `buf`
+        std::mem::swap(&mut buf, &mut spare);
+        let mut pending = std::mem::take(&mut spare);
+        let timed_out = Err(Error::from(ErrorKind::TimedOut));
+        give_back_read_buf(&mut buf, &mut spare, true, len, &timed_out);
+        assert!(buf.is_empty() && buf.capacity() >= len);
+
+        // the next read copies the data of the pending transfer (the queue is not empty)
+        pending.extend_from_slice(b"data");
+        spare = pending;
+        buf.clear();
+        buf.extend_from_slice(&spare);
+        assert_eq!(buf, b"data");
+
+        // then the buffers are swapped again
+        std::mem::swap(&mut buf, &mut spare);
+        let mut pending = std::mem::take(&mut spare);
+        pending.clear();
+        pending.extend_from_slice(b"more");
+        spare = pending;
+        give_back_read_buf(&mut buf, &mut spare, false, len, &Ok(4));
+        assert_eq!(buf, b"more");
+        assert!(spare.capacity() >= len);
     }
 }